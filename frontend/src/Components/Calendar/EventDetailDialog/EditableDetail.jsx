@@ -13,7 +13,7 @@ import { Clear as ClearIcon, Done as DoneIcon } from '@material-ui/icons'
 import { useUpdateEvent, useDeleteEvent } from 'Hooks'
 import EditEventForm from 'Components/EditEventForm'
 
-const EditableDetail = ({ visibleEventDetail, setInfoId }) => {
+const EditableDetail = ({ visibleEventDetail, version, setInfoId }) => {
   const updateEvent = useUpdateEvent()
   const [eventData, setEventData] = useState(visibleEventDetail)
   const deleteEvent = useDeleteEvent()
@@ -23,7 +23,7 @@ const EditableDetail = ({ visibleEventDetail, setInfoId }) => {
   const onSubmit = e => {
     e.preventDefault()
     if (visibleEventDetail !== undefined)
-      updateEvent(visibleEventDetail.id)(eventData)
+      updateEvent(visibleEventDetail.id, version)(eventData)
 
     onClose()
   }
@@ -42,7 +42,7 @@ const EditableDetail = ({ visibleEventDetail, setInfoId }) => {
           <Box mt={2}>
             <DialogActions>
               <Button
-                onClick={() => deleteEvent(visibleEventDetail.id)}
+                onClick={() => deleteEvent(visibleEventDetail.id, version)}
                 color="secondary"
                 variant="contained"
                 startIcon={<ClearIcon />}
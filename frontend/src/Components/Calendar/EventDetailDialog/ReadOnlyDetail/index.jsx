@@ -17,7 +17,7 @@ import Row from './Row'
 
 const useStyle = makeStyles({})
 
-const EventDetailDialog = ({ visibleEventDetail, setInfoId }) => {
+const EventDetailDialog = ({ visibleEventDetail, version, setInfoId }) => {
   const c = useStyle()
   const disapprove = useDisapprove()
   const approve = useApprove()
@@ -60,7 +60,7 @@ const EventDetailDialog = ({ visibleEventDetail, setInfoId }) => {
                 <DialogActions>
                   <Button
                     onClick={() => {
-                      disapprove(visibleEventDetail.id)
+                      disapprove(visibleEventDetail.id, version)
                       setInfoId(undefined)
                     }}
                     color="secondary"
@@ -71,7 +71,7 @@ const EventDetailDialog = ({ visibleEventDetail, setInfoId }) => {
                   </Button>
                   <Button
                     onClick={() => {
-                      approve(visibleEventDetail.id)
+                      approve(visibleEventDetail.id, version)
                       setInfoId(undefined)
                     }}
                     color="primary"
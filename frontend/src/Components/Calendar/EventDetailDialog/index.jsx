@@ -1,21 +1,36 @@
 import React from 'react'
 
-import { useUserEmail } from 'Hooks'
+import { useEventVersion, useUserEmail } from 'Hooks'
 
 import EditableDetail from './EditableDetail'
 import ReadOnlyDetail from './ReadOnlyDetail'
 
 const EventDetailDialog = ({ visibleEventDetail, ...props }) => {
   const email = useUserEmail()
+  const version = useEventVersion(
+    visibleEventDetail !== undefined ? visibleEventDetail.id : undefined
+  )
 
   if (
     visibleEventDetail !== undefined &&
     visibleEventDetail.author_email === email
   ) {
-    return <EditableDetail visibleEventDetail={visibleEventDetail} {...props} />
+    return (
+      <EditableDetail
+        visibleEventDetail={visibleEventDetail}
+        version={version}
+        {...props}
+      />
+    )
   }
 
-  return <ReadOnlyDetail visibleEventDetail={visibleEventDetail} {...props} />
+  return (
+    <ReadOnlyDetail
+      visibleEventDetail={visibleEventDetail}
+      version={version}
+      {...props}
+    />
+  )
 }
 
 export default EventDetailDialog
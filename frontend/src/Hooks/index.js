@@ -18,6 +18,22 @@ export const api = axios.create({
   timeout: 2000,
 })
 
+// changes of an event have to name the version they're based on, see ETag / If-Match in the backend
+const ifMatch = version => ({ headers: { 'If-Match': version } })
+
+// 412: the event changed since it was loaded, 428: it hasn't been loaded yet
+const reportChangeError = error => {
+  const status = error.response !== undefined ? error.response.status : undefined
+
+  if (status === 412) {
+    alert('Someone else has changed this event in the meantime. Please open it again and redo your changes.')
+  } else if (status === 428) {
+    alert('The event is still loading. Please try again.')
+  } else {
+    console.log(error)
+  }
+}
+
 const useRerender = () => {
   const [, setB] = useState(true)
   return () => setB(b => !b)
@@ -134,8 +150,25 @@ export const useCreateEvent = onFinish => {
   return createEvent
 }
 
+// the version of the event the dialog shows, loaded once it's opened
+export const useEventVersion = id => {
+  const [version, setVersion] = useState(undefined)
+
+  useEffect(() => {
+    setVersion(undefined)
+    if (id === undefined) return
+
+    api
+      .get(`api/events/${id}/`)
+      .then(response => setVersion(response.headers.etag))
+      .catch(console.log)
+  }, [id])
+
+  return version
+}
+
 export const useUpdateEvent = () => {
-  const updateEvent = id => event => {
+  const updateEvent = (id, version) => event => {
     const data = {
       ...event,
     }
@@ -159,7 +192,7 @@ export const useUpdateEvent = () => {
       data.people = shouldBackendWork ? event.people : parseInt(event.people)
     }
 
-    api.patch(`api/events/${id}/`, data)
+    api.patch(`api/events/${id}/`, data, ifMatch(version)).catch(reportChangeError)
   }
 
   return updateEvent
@@ -167,12 +200,12 @@ export const useUpdateEvent = () => {
 
 export const useDisapprove = () => {
   const deleteEvent = useDeleteEvent()
-  return id => deleteEvent(id)
+  return (id, version) => deleteEvent(id, version)
 }
 
 export const useApprove = () => {
-  return id => {
-    api.post(`api/events/${id}/approve/`)
+  return (id, version) => {
+    api.post(`api/events/${id}/approve/`, {}, ifMatch(version)).catch(reportChangeError)
   }
 }
 
@@ -250,8 +283,8 @@ export const useUserRole = () => {
 }
 
 export const useDeleteEvent = () => {
-  const deleteEvent = id => {
-    api.delete(`api/events/${id}/`)
+  const deleteEvent = (id, version) => {
+    api.delete(`api/events/${id}/`, ifMatch(version)).catch(reportChangeError)
   }
   return deleteEvent
 }
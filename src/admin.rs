@@ -1,5 +1,5 @@
 //! a module containing admin functions
-use rocket::Route;
+use rocket::{Route, State};
 use rocket_contrib::json::Json;
 
 use std::env;
@@ -7,49 +7,95 @@ use std::env;
 use crate::auth::AuthToken;
 use crate::auth::roles::{Role, Superadmin};
 
+use crate::audit;
 use crate::db::{
+	self,
 	Database,
+	Table,
 	table::Reservations,
+	table::Devices,
 	table::Users,
+	table::Audit,
 };
 
 use crate::models::{User, Reservation};
+use crate::response::Error;
+use crate::store::Store;
+
+/// names of the roles a user can have
+pub(crate) const ROLES: [&str; 4] = ["Noob", "Superadmin", "Approver", "FacilityManager"];
 
 /// geenrates a superadmin
 #[post("/generate_sa/<email>/<password>")]
-pub fn generate_superadmin(email: String, password: String, mut db: Database<Users>) -> Option<()> {
-	if password != env::var("SA_SECRET").unwrap() { return None }
+pub fn generate_superadmin(email: String, password: String, mut db: Database<Users>, mut audit: Database<Audit>) -> Result<(), Error> {
+	if password != env::var("SA_SECRET").unwrap() { return Err(Error::Forbidden) }
 
-	db.write()
-		.insert(&email, User { email: email.clone(), name: "Superadmin".to_string(), role: Superadmin::name().to_string() })
-		.ok()?
-		.map(|_| ())
+	let before = db.read().get(&email)?;
+	let user = User { email: email.clone(), name: "Superadmin".to_string(), role: Superadmin::name().to_string() };
+
+	db.write().insert(&email, &user)?;
+
+	audit::record(&mut audit, &email, "user.generate_superadmin", format!("user:{}", email), before.as_ref(), Some(&user))
+		.ok_or(Error::Database)
 }
 
 /// get all users
 #[get("/users", format = "application/json")]
-pub fn users(db: Database<Users>, _u: AuthToken<Superadmin>) -> Json<Vec<(String, User)>> {
-	Json(db.read().iter().collect::<Vec<(String, User)>>())
+pub fn users(db: Database<Users>, _u: AuthToken<Superadmin>) -> Result<Json<Vec<(String, User)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
 }
 
 /// change user role
 #[patch("/users/<email>/<new_role>", format = "application/json")]
-pub fn change_role(email: String, new_role: String, mut db: Database<Users>, _u: AuthToken<Superadmin>) -> Option<()> {
-	if !["Noob", "Superadmin", "Approver", "FacilityManager"].contains(&new_role.as_str()) {
-		return None;
+pub fn change_role(
+	email: String,
+	new_role: String,
+	mut db: Database<Users>,
+	mut audit: Database<Audit>,
+	usr: AuthToken<Superadmin>,
+) -> Result<(), Error> {
+	if !ROLES.contains(&new_role.as_str()) {
+		return Err(Error::NotFound);
 	}
 
+	let before = db.read().get(&email)?.ok_or(Error::NotFound)?;
+
 	db.write()
-		.update::<_, User, _>(email, |x| if let Some(mut x) = x {
+		.update::<_, User, _>(&email, |x| if let Some(mut x) = x {
 			x.role = new_role.clone();
 			Some(x)
-		} else {None}).ok()?;
+		} else {None})?;
 
-	Some(())
+	let after = db.read().get(&email)?;
+	audit::record(&mut audit, &usr.user.email, "user.change_role", format!("user:{}", email), Some(&before), after.as_ref())
+		.ok_or(Error::Database)
 }
 
+/// znovu sestaví indexy tabulek, vrací počet zaindexovaných záznamů každé tabulky
+///
+/// POST /indexes/rebuild
+///
+/// potřeba, pokud se indexy rozejdou s daty, např. po ruční opravě databáze
+#[post("/indexes/rebuild")]
+pub fn rebuild_indexes(
+	store: State<Store>,
+	mut audit: Database<Audit>,
+	usr: AuthToken<Superadmin>,
+) -> Result<Json<Vec<(String, usize)>>, Error> {
+	let counts = vec![
+		(Reservations::name().to_string(), db::rebuild::<Reservations>(&store, false)?),
+		(Devices::name().to_string(), db::rebuild::<Devices>(&store, false)?),
+	];
+
+	for (table, count) in &counts {
+		audit::record(&mut audit, &usr.user.email, "indexes.rebuild", format!("table:{}", table), None, Some(count))
+			.ok_or(Error::Database)?;
+	}
+
+	Ok(Json(counts))
+}
 
 /// vrací seznam endpointů pro nabindování do Rocketu
 pub fn routes() -> Vec<Route> {
-	routes![users, generate_superadmin, change_role]
+	routes![users, generate_superadmin, change_role, rebuild_indexes]
 }
@@ -0,0 +1,157 @@
+//! a module containing the audit log
+//!
+//! every state-changing route of [`crate::booking`] and [`crate::admin`] records
+//! who did what to which record and which fields changed, see [`record`].
+//! the log is append-only, there are no routes changing or removing entries.
+//! superadmins can query it and export it as CSV
+use rocket::Route;
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, offset::Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use std::collections::BTreeSet;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Superadmin;
+
+use crate::db::{Database, table::Audit};
+
+use crate::models::{AuditEntry, FieldChange};
+use crate::response::Error;
+
+/// fields of a record serialized as a JSON object
+fn fields<T: Serialize>(record: Option<&T>) -> serde_json::Map<String, Value> {
+	match record.and_then(|r| serde_json::to_value(r).ok()) {
+		Some(Value::Object(map)) => map,
+		Some(other) => vec![("value".to_string(), other)].into_iter().collect(),
+		None => serde_json::Map::new(),
+	}
+}
+
+/// fields which differ between the two versions of a record
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Vec<FieldChange> {
+	let (before, after) = (fields(before), fields(after));
+
+	before
+		.keys()
+		.chain(after.keys())
+		.collect::<BTreeSet<&String>>()
+		.into_iter()
+		.filter(|k| before.get(*k) != after.get(*k))
+		.map(|k| FieldChange {
+			field:  k.clone(),
+			before: before.get(k).cloned().unwrap_or(Value::Null),
+			after:  after.get(k).cloned().unwrap_or(Value::Null),
+		})
+		.collect()
+}
+
+/// a new audit log entry
+pub fn entry<T: Serialize>(actor: &str, action: &str, target: String, before: Option<&T>, after: Option<&T>) -> AuditEntry {
+	AuditEntry {
+		actor: actor.to_string(),
+		action: action.to_string(),
+		target,
+		diff: diff(before, after),
+		time: Utc::now(),
+	}
+}
+
+/// appends an entry to the audit log
+pub fn record<T: Serialize>(
+	db: &mut Database<Audit>,
+	actor: &str,
+	action: &str,
+	target: String,
+	before: Option<&T>,
+	after: Option<&T>,
+) -> Option<()> {
+	let entry = entry(actor, action, target, before, after);
+
+	let key = db.get_key().ok()?;
+	db.write().insert(key, entry).ok().map(|_| ())
+}
+
+/// entries matching the filters, oldest first
+fn query(
+	db: &Database<Audit>,
+	actor: Option<String>,
+	target: Option<String>,
+	from: Option<String>,
+	to: Option<String>,
+) -> Result<Vec<(u64, AuditEntry)>, Error> {
+	let parse = |t: Option<String>| -> Result<Option<DateTime<Utc>>, Error> {
+		match t {
+			Some(t) => Ok(Some(DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&t).map_err(|_| Error::NotFound)?))),
+			None => Ok(None),
+		}
+	};
+	let (from, to) = (parse(from)?, parse(to)?);
+
+	let entries = db
+		.read()
+		.try_iter()
+		.collect::<Result<Vec<(u64, AuditEntry)>, _>>()?
+		.into_iter()
+		.filter(|(_, e)| actor.as_ref().map(|a| &e.actor == a).unwrap_or(true))
+		.filter(|(_, e)| target.as_ref().map(|t| &e.target == t).unwrap_or(true))
+		.filter(|(_, e)| from.map(|f| e.time >= f).unwrap_or(true) && to.map(|t| e.time <= t).unwrap_or(true))
+		.collect::<Vec<(u64, AuditEntry)>>();
+
+	Ok(entries)
+}
+
+/// query the audit log
+///
+/// all filters are optional, `from` and `to` are RFC 3339 timestamps
+#[get("/audit?<actor>&<target>&<from>&<to>", format = "application/json")]
+pub fn list(
+	actor: Option<String>,
+	target: Option<String>,
+	from: Option<String>,
+	to: Option<String>,
+	db: Database<Audit>,
+	_u: AuthToken<Superadmin>,
+) -> Result<Json<Vec<(u64, AuditEntry)>>, Error> {
+	query(&db, actor, target, from, to).map(Json)
+}
+
+/// export the audit log as CSV, takes the same filters as [`list`]
+///
+/// the diff is exported as a JSON array
+#[get("/audit.csv?<actor>&<target>&<from>&<to>")]
+pub fn export(
+	actor: Option<String>,
+	target: Option<String>,
+	from: Option<String>,
+	to: Option<String>,
+	db: Database<Audit>,
+	_u: AuthToken<Superadmin>,
+) -> Result<Content<Vec<u8>>, Error> {
+	let mut writer = csv::Writer::from_writer(vec![]);
+	writer.write_record(["id", "time", "actor", "action", "target", "diff"]).map_err(|_| Error::Database)?;
+
+	for (seq, e) in query(&db, actor, target, from, to)? {
+		writer
+			.write_record(&[
+				seq.to_string(),
+				e.time.to_rfc3339(),
+				e.actor,
+				e.action,
+				e.target,
+				serde_json::to_string(&e.diff).map_err(|_| Error::Database)?,
+			])
+			.map_err(|_| Error::Database)?;
+	}
+
+	Ok(Content(ContentType::CSV, writer.into_inner().map_err(|_| Error::Database)?))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, export]
+}
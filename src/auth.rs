@@ -61,6 +61,16 @@ impl<T: roles::Role> AuthToken<T> {
 	pub fn from_user(user: User) -> Self {
 		AuthToken { user, _m: PhantomData }
 	}
+
+	/// zda má uživatel roli `R` nebo roli, která z ní dědí
+	pub fn is<R: roles::Role>(&self) -> bool {
+		roles::is::<R>(&self.user.role)
+	}
+
+	/// zda uživatel spravuje auditorium (facility manager nebo superadmin)
+	pub fn manages_facility(&self) -> bool {
+		self.is::<roles::FacilityManager>() || self.is::<roles::Superadmin>()
+	}
 }
 
 /// obsahuje nulové typy pro role
@@ -108,9 +118,25 @@ pub mod roles {
 					$(fn is_root() -> bool { $is_root })?
 				}
 			)*
+
+			/// vrací roli daného jména a všechny její předky (kromě kořenové role)
+			pub fn resolve(role: &str) -> Vec<&'static str> {
+				$(if role == $role::name() { return $role::resolve_daddy() })*
+				vec![]
+			}
 		}
 	 }
 
+	/// zda je role `role` rolí `R` nebo jejím potomkem
+	pub fn is<R: Role>(role: &str) -> bool {
+		R::is_root() || resolve(role).contains(&R::name())
+	}
+
+	/// jako [`is`], ale pro roli zadanou jménem
+	pub fn is_named(role: &str, name: &str) -> bool {
+		role == name || resolve(role).contains(&name)
+	}
+
 	role_gen! {
 		Noob[Noob]            -> true,
 		Approver[Noob]        -> false,
@@ -123,9 +149,9 @@ impl<'a, 'r, T: roles::Role> FromRequest<'a, 'r> for AuthToken<T> {
 	type Error = String;
 
 	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
-		let mut db = match Database::<Users>::open() {
-			Some(d) => d,
-			None => return Outcome::Failure((Status::InternalServerError, "failed to connect to db".to_string())),
+		let mut db = match request.guard::<Database<Users>>() {
+			Outcome::Success(d) => d,
+			_ => return Outcome::Failure((Status::InternalServerError, "failed to connect to db".to_string())),
 		};
 
 		let keys: Vec<_> = request.headers().get("Authorization").collect();
@@ -151,10 +177,16 @@ impl<'a, 'r, T: roles::Role> FromRequest<'a, 'r> for AuthToken<T> {
 
 				//... pošéfit databázi zde
 
-				let result = if let Some((_, u)) = db.read()
-					.iter()
-					.find(|(_, u)| u.email == token.email)
-				{ u } else {
+				let existing = match db.read().get(&token.email) {
+					Ok(existing) => existing,
+					Err(e) =>
+						return Outcome::Failure((
+							Status::InternalServerError,
+							format!("failed to read user from DB: {}", e),
+						)),
+				};
+
+				let result = if let Some(u) = existing { u } else {
 					let new_u = token.clone().into_user();
 
 					if db.write()
@@ -170,14 +202,14 @@ impl<'a, 'r, T: roles::Role> FromRequest<'a, 'r> for AuthToken<T> {
 					new_u
 				};
 
-				if T::name() == result.role || T::resolve_daddy().contains(&result.role.as_str()) {
+				if roles::is::<T>(&result.role) {
 					Outcome::Success(AuthToken::from_user(result))
 				} else {
 					Outcome::Failure((Status::Forbidden, "you don't have the required role".to_string()))
 				}
 			}
 			x => {
-				println!("{:?}", x);
+				log::warn!("auth: invalid authorization header: {:?}", x);
 				Outcome::Failure((Status::BadRequest, "invalid authorization header".to_string()))
 			}
 		}
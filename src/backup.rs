@@ -0,0 +1,350 @@
+//! a module containing backups of the whole database
+//!
+//! a backup is an archive of a consistent snapshot of all trees (see
+//! [`Store::snapshot`]), which can be taken while the server runs. the archive
+//! starts with [`MAGIC`] followed by a CBOR encoded [`Manifest`] and the trees,
+//! the manifest carries the format version, the schema version of the data
+//! and a SHA-256 checksum of the trees, see [`archive`] and [`verify`]
+//!
+//! superadmins create and download archives through the API, the `backup`
+//! binary exports, verifies and restores them offline and the background job
+//! makes them periodically, keeping only the newest ones, see [`Schedule`]
+use rocket::Route;
+use rocket::State;
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, offset::Utc};
+use serde::{Serialize, Deserialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Superadmin;
+
+use crate::db;
+use crate::migrate;
+use crate::response;
+use crate::store::Store;
+
+/// the first bytes of every archive
+pub const MAGIC: &[u8] = b"BOOKSNAP";
+
+/// version of the archive format written by this build
+pub const FORMAT: u32 = 1;
+
+/// prefix of the names of archives in a backup directory
+const PREFIX: &str = "backup-";
+
+/// suffix of the names of archives in a backup directory
+const SUFFIX: &str = ".snap";
+
+/// errors of backups
+#[derive(Debug)]
+pub enum Error {
+	/// reading or writing the archive file failed
+	Io(std::io::Error),
+	/// the store failed
+	Storage(db::Error),
+	/// the file isn't a valid archive
+	Invalid(String),
+	/// the store to restore to already contains data
+	NotEmpty,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "i/o error: {}", e),
+			Error::Storage(e) => write!(f, "{}", e),
+			Error::Invalid(why) => write!(f, "invalid archive: {}", why),
+			Error::NotEmpty => write!(f, "the database isn't empty, restore only works into an empty one"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+	fn from(e: std::io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+impl From<db::Error> for Error {
+	fn from(e: db::Error) -> Self {
+		Error::Storage(e)
+	}
+}
+
+impl From<Error> for response::Error {
+	fn from(e: Error) -> Self {
+		log::error!("backup: {}", e);
+		response::Error::Database
+	}
+}
+
+/// description of an archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+	/// version of the archive format, see [`FORMAT`]
+	pub format:   u32,
+	/// version of the schema of the data, see [`crate::migrate`]
+	pub schema:   u32,
+	/// when the snapshot was taken
+	pub created:  DateTime<Utc>,
+	/// number of records of every tree
+	pub trees:    BTreeMap<String, usize>,
+	/// the next id to generate, see [`crate::store::Backend::reserve_ids`]
+	#[serde(default)]
+	pub next_id:  u64,
+	/// hex SHA-256 of the encoded trees
+	pub checksum: String,
+}
+
+/// an archive following [`MAGIC`]
+#[derive(Serialize, Deserialize)]
+struct Archive {
+	manifest: Manifest,
+	/// the encoded [`Tree`]s
+	trees:    ByteBuf,
+}
+
+/// records of a tree in an archive
+#[derive(Serialize, Deserialize)]
+struct Tree {
+	name:    String,
+	records: Vec<(ByteBuf, ByteBuf)>,
+}
+
+/// hex SHA-256 of the data
+fn checksum(data: &[u8]) -> String {
+	Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// takes a snapshot of the store, returns its manifest and the archive
+pub fn archive(store: &Store) -> Result<(Manifest, Vec<u8>), Error> {
+	let schema = migrate::version(store)?;
+	let snapshot = store.snapshot()?;
+	// after the snapshot, so no record in it has a greater id
+	let next_id = store.generate_id()?;
+
+	let counts = snapshot.iter().map(|(name, records)| (name.clone(), records.len())).collect();
+	let trees = snapshot
+		.into_iter()
+		.map(|(name, records)| Tree { name, records: records.into_iter().map(|(k, v)| (ByteBuf::from(k), ByteBuf::from(v))).collect() })
+		.collect::<Vec<_>>();
+
+	let trees = serde_cbor::to_vec(&trees).map_err(db::Error::from)?;
+	let manifest = Manifest { format: FORMAT, schema, created: Utc::now(), trees: counts, next_id, checksum: checksum(&trees) };
+
+	let mut out = MAGIC.to_vec();
+	serde_cbor::to_writer(&mut out, &Archive { manifest: manifest.clone(), trees: ByteBuf::from(trees) }).map_err(db::Error::from)?;
+
+	Ok((manifest, out))
+}
+
+/// decodes an archive and checks its integrity
+fn open(raw: &[u8]) -> Result<(Manifest, Vec<Tree>), Error> {
+	if !raw.starts_with(MAGIC) {
+		return Err(Error::Invalid("not a backup archive".to_string()));
+	}
+
+	let archive: Archive = serde_cbor::from_slice(&raw[MAGIC.len()..]).map_err(|e| Error::Invalid(e.to_string()))?;
+	let manifest = archive.manifest;
+
+	if manifest.format > FORMAT {
+		return Err(Error::Invalid(format!("format {} is newer than the supported format {}", manifest.format, FORMAT)));
+	}
+
+	if checksum(&archive.trees) != manifest.checksum {
+		return Err(Error::Invalid("checksum mismatch, the archive is damaged".to_string()));
+	}
+
+	let trees: Vec<Tree> = serde_cbor::from_slice(&archive.trees).map_err(|e| Error::Invalid(e.to_string()))?;
+	let counts = trees.iter().map(|t| (t.name.clone(), t.records.len())).collect::<BTreeMap<_, _>>();
+
+	if counts != manifest.trees {
+		return Err(Error::Invalid("the trees don't match the manifest".to_string()));
+	}
+
+	Ok((manifest, trees))
+}
+
+/// writes a snapshot of the store to the file, returns its manifest
+///
+/// the archive is written next to the file first, so the file is never left half written
+pub fn export(store: &Store, path: &Path) -> Result<Manifest, Error> {
+	let (manifest, raw) = archive(store)?;
+	let partial = path.with_extension("partial");
+
+	fs::write(&partial, raw)?;
+	fs::rename(&partial, path)?;
+
+	Ok(manifest)
+}
+
+/// checks the archive in the file, returns its manifest
+pub fn verify(path: &Path) -> Result<Manifest, Error> {
+	Ok(open(&fs::read(path)?)?.0)
+}
+
+/// imports the archive in the file into an empty store, returns its manifest
+///
+/// the archive is checked first, nothing is written if it's damaged. data of an older
+/// schema are migrated when the server starts, a newer schema is refused
+pub fn restore(store: &Store, path: &Path) -> Result<Manifest, Error> {
+	let (manifest, trees) = open(&fs::read(path)?)?;
+
+	if manifest.schema > migrate::latest() {
+		return Err(db::Error::Schema { found: manifest.schema, supported: migrate::latest() }.into());
+	}
+
+	for name in store.tree_names() {
+		if !store.open_tree(&name)?.is_empty() {
+			return Err(Error::NotEmpty);
+		}
+	}
+
+	for tree in trees {
+		let target = store.open_tree(&tree.name)?;
+
+		for (key, value) in tree.records {
+			target.insert(&key, &value)?;
+		}
+	}
+
+	store.reserve_ids(manifest.next_id)?;
+	store.flush()?;
+	Ok(manifest)
+}
+
+/// names of the archives in the directory, oldest first
+pub fn list(dir: &Path) -> Result<Vec<String>, Error> {
+	let mut names = vec![];
+
+	for entry in fs::read_dir(dir)? {
+		let name = entry?.file_name().to_string_lossy().to_string();
+
+		if name.starts_with(PREFIX) && name.ends_with(SUFFIX) {
+			names.push(name);
+		}
+	}
+
+	names.sort();
+	Ok(names)
+}
+
+/// writes a snapshot of the store to a new archive in the directory, returns its name and manifest
+pub fn create(store: &Store, dir: &Path) -> Result<(String, Manifest), Error> {
+	fs::create_dir_all(dir)?;
+
+	let name = format!("{}{}{}", PREFIX, Utc::now().format("%Y%m%dT%H%M%S%.6fZ"), SUFFIX);
+	let manifest = export(store, &dir.join(&name))?;
+
+	Ok((name, manifest))
+}
+
+/// removes all but the newest `keep` archives in the directory, returns the removed names
+pub fn prune(dir: &Path, keep: usize) -> Result<Vec<String>, Error> {
+	let names = list(dir)?;
+	let old = names[..names.len().saturating_sub(keep)].to_vec();
+
+	for name in &old {
+		fs::remove_file(dir.join(name))?;
+	}
+
+	Ok(old)
+}
+
+/// periodic backups
+#[derive(Debug, Clone)]
+pub struct Schedule {
+	/// directory of the archives
+	pub dir:   PathBuf,
+	/// time between backups
+	pub every: Duration,
+	/// number of archives to keep
+	pub keep:  usize,
+}
+
+impl Schedule {
+	/// the schedule given by `BACKUP_DIR`, `BACKUP_INTERVAL_HOURS` (24 by default)
+	/// and `BACKUP_KEEP` (7 by default), `None` without `BACKUP_DIR`
+	pub fn from_env() -> Option<Self> {
+		Some(Schedule {
+			dir:   backup_dir()?,
+			every: Duration::from_secs(60 * 60 * env::var("BACKUP_INTERVAL_HOURS").ok().and_then(|h| h.parse().ok()).unwrap_or(24)),
+			keep:  env::var("BACKUP_KEEP").ok().and_then(|k| k.parse().ok()).unwrap_or(7),
+		})
+	}
+}
+
+/// directory of the archives, `BACKUP_DIR`
+fn backup_dir() -> Option<PathBuf> {
+	env::var("BACKUP_DIR").ok().map(PathBuf::from)
+}
+
+/// background job making a backup and dropping the old ones
+pub fn scheduled(store: &Store, schedule: &Schedule) -> Result<(), Error> {
+	let (name, manifest) = create(store, &schedule.dir)?;
+	log::info!("backup: created {} schema={} trees={}", name, manifest.schema, manifest.trees.len());
+
+	let removed = prune(&schedule.dir, schedule.keep)?;
+	if !removed.is_empty() {
+		log::info!("backup: removed {} old backups", removed.len());
+	}
+
+	Ok(())
+}
+
+/// vytvoří zálohu databáze v adresáři `BACKUP_DIR`, vrací její jméno a popis
+///
+/// POST /backups
+///
+/// záloha je konzistentní snímek všech tabulek, zápisy během jeho pořízení počkají
+#[post("/backups")]
+pub fn post(store: State<Store>, _u: AuthToken<Superadmin>) -> Result<Json<(String, Manifest)>, response::Error> {
+	let dir = backup_dir().ok_or(response::Error::NotFound)?;
+	Ok(Json(create(&store, &dir)?))
+}
+
+/// vrací jména záloh v adresáři `BACKUP_DIR`, od nejstarší
+///
+/// GET /backups "application/json"
+#[get("/backups", format = "application/json")]
+pub fn get_list(_u: AuthToken<Superadmin>) -> Result<Json<Vec<String>>, response::Error> {
+	let dir = backup_dir().ok_or(response::Error::NotFound)?;
+	Ok(Json(list(&dir)?))
+}
+
+/// vrací soubor dané zálohy
+///
+/// GET /backups/<name>
+///
+/// parametry:
+/// - `name`: jméno zálohy, viz [`get_list`]
+#[get("/backups/<name>")]
+pub fn download(name: String, _u: AuthToken<Superadmin>) -> Result<Content<Vec<u8>>, response::Error> {
+	let dir = backup_dir().ok_or(response::Error::NotFound)?;
+
+	// only names of existing archives, never paths
+	if !list(&dir)?.contains(&name) {
+		return Err(response::Error::NotFound);
+	}
+
+	Ok(Content(ContentType::Binary, fs::read(dir.join(name)).map_err(Error::from)?))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![post, get_list, download]
+}
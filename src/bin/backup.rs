@@ -0,0 +1,67 @@
+extern crate backend;
+extern crate yansi;
+
+use yansi::Paint;
+use backend::Config;
+use backend::backup::{self, Manifest};
+use backend::store::Store;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+fn usage() -> ! {
+	eprintln!("usage: backup <export|verify|restore> <file>");
+	process::exit(2)
+}
+
+fn fail(what: &str, e: impl std::fmt::Display) -> ! {
+	eprintln!("[{}] {}", Paint::magenta("rust booking"), Paint::red(format!("{}: {}", what, e)));
+	process::exit(1)
+}
+
+fn report(verb: &str, path: &Path, manifest: &Manifest) {
+	let records: usize = manifest.trees.values().sum();
+	println!(
+		"[{}] {} {}",
+		Paint::magenta("rust booking"),
+		Paint::yellow(format!("{}:", verb)),
+		path.display()
+	);
+	println!(
+		"[{}] {}",
+		Paint::magenta("rust booking"),
+		Paint::yellow(format!("created {}, schema version {}, {} trees, {} records", manifest.created.to_rfc3339(), manifest.schema, manifest.trees.len(), records))
+	);
+}
+
+fn main() {
+	let mut args = env::args().skip(1);
+	let (command, path) = match (args.next(), args.next(), args.next()) {
+		(Some(command), Some(path), None) => (command, PathBuf::from(path)),
+		_ => usage(),
+	};
+
+	// the file alone is enough to verify it
+	if command == "verify" {
+		match backup::verify(&path) {
+			Ok(manifest) => return report("valid", &path, &manifest),
+			Err(e) => fail("verification failed", e),
+		}
+	}
+
+	let config = Config::from_env().unwrap_or_else(|e| fail("failed to read DATABASE_URL", e));
+	let store = Store::open(&config.storage).unwrap_or_else(|e| fail("failed to open the database", e));
+
+	match command.as_str() {
+		"export" => match backup::export(&store, &path) {
+			Ok(manifest) => report("exported", &path, &manifest),
+			Err(e) => fail("export failed", e),
+		},
+		"restore" => match backup::restore(&store, &path) {
+			Ok(manifest) => report("restored", &path, &manifest),
+			Err(e) => fail("restore failed", e),
+		},
+		_ => usage(),
+	}
+}
@@ -0,0 +1,49 @@
+extern crate backend;
+extern crate yansi;
+
+use yansi::Paint;
+use backend::db;
+use backend::store::{Storage, Store};
+
+use std::env;
+use std::process;
+
+fn usage() -> ! {
+	eprintln!("usage: copy <from DATABASE_URL> <to DATABASE_URL>");
+	eprintln!("e.g.:  copy ./db sqlite:booking.sqlite");
+	process::exit(2)
+}
+
+fn fail(what: &str, e: impl std::fmt::Display) -> ! {
+	eprintln!("[{}] {}", Paint::magenta("rust booking"), Paint::red(format!("{}: {}", what, e)));
+	process::exit(1)
+}
+
+fn main() {
+	let mut args = env::args().skip(1);
+	let (from, to) = match (args.next(), args.next(), args.next()) {
+		(Some(from), Some(to), None) => (Storage::parse(&from), Storage::parse(&to)),
+		_ => usage(),
+	};
+
+	let source = Store::open(&from).unwrap_or_else(|e| fail("failed to open the source database", e));
+	let target = Store::open(&to).unwrap_or_else(|e| fail("failed to open the target database", e));
+
+	// copying into existing data would mix two databases
+	for name in target.tree_names() {
+		match target.open_tree(&name) {
+			Ok(tree) if tree.is_empty() => (),
+			Ok(_) => fail("the target database isn't empty", name),
+			Err(e) => fail("failed to open a tree of the target database", e),
+		}
+	}
+
+	match db::copy(&source, &target) {
+		Ok(count) => println!(
+			"[{}] {}",
+			Paint::magenta("rust booking"),
+			Paint::yellow(format!("copied {} records of {} trees from {:?} to {:?}", count, source.tree_names().len(), from, to))
+		),
+		Err(e) => fail("copy failed", e),
+	}
+}
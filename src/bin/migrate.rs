@@ -0,0 +1,67 @@
+extern crate backend;
+extern crate yansi;
+
+use yansi::Paint;
+use backend::Config;
+use backend::migrate::{self, Options};
+use backend::store::Store;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+fn usage() -> ! {
+	eprintln!("usage: migrate [--dry-run] [--backup <dir>]");
+	process::exit(2)
+}
+
+fn main() {
+	let config = match Config::from_env() {
+		Ok(config) => config,
+		Err(e) => {
+			eprintln!("[{}] {}", Paint::magenta("rust booking"), Paint::red(format!("failed to read DATABASE_URL: {}", e)));
+			process::exit(1);
+		}
+	};
+	let mut options = Options::from_env();
+	let mut args = env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--dry-run" => options.dry_run = true,
+			"--backup" => options.backup = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+			_ => usage(),
+		}
+	}
+
+	let store = match Store::open(&config.storage) {
+		Ok(store) => store,
+		Err(e) => {
+			eprintln!("[{}] {}", Paint::magenta("rust booking"), Paint::red(format!("failed to open the database: {}", e)));
+			process::exit(1);
+		}
+	};
+
+	match migrate::run(&store, &options) {
+		Ok(report) => {
+			if let Some(path) = &report.backup {
+				println!("[{}] {} {}", Paint::magenta("rust booking"), Paint::yellow("backup:"), path.display());
+			}
+
+			for (version, changed) in &report.applied {
+				let verb = if options.dry_run { "would change" } else { "changed" };
+				println!("[{}] {}", Paint::magenta("rust booking"), Paint::yellow(format!("version {}: {} {} records", version, verb, changed)));
+			}
+
+			println!(
+				"[{}] {}",
+				Paint::magenta("rust booking"),
+				Paint::yellow(format!("schema version {} -> {} (latest {})", report.from, report.to, migrate::latest()))
+			);
+		}
+		Err(e) => {
+			eprintln!("[{}] {}", Paint::magenta("rust booking"), Paint::red(format!("migration failed: {}", e)));
+			process::exit(1);
+		}
+	}
+}
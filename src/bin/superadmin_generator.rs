@@ -9,7 +9,7 @@ extern crate rocket;
 extern crate yansi;
 
 use yansi::Paint;
-use backend::init;
+use backend::{init, Config};
 
 use rocket::http::Status;
 use rocket::local::Client;
@@ -17,7 +17,8 @@ use rocket::local::Client;
 use std::env;
 
 fn main() {
-	let rocket = init();
+	let config = Config::from_env().expect("failed to read DATABASE_URL environment variable");
+	let rocket = init(config).expect("failed to start the backend");
 	let client = Client::new(rocket).expect("rocket instance is not valid");
 
 	println!();
@@ -32,7 +33,7 @@ fn main() {
 	println!("[{}] {}", Paint::magenta("rust booking"), Paint::yellow("generating superadmin"));
 	let req = client.post(format!("/admin/generate_sa/{}/{}", admin_email, password)).dispatch();
 
-	if req.status() != Status::NotFound {
+	if req.status() != Status::Ok {
 		 println!("[{}] {}", Paint::magenta("rust booking"), Paint::yellow("failed to generate superadmin account"))
 	} else {
 		 println!("[{}] {}", Paint::magenta("rust booking"), Paint::yellow("successfully generated superadmin account"))
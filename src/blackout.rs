@@ -0,0 +1,177 @@
+//! a module containing blackout functionality
+//!
+//! blackouts are periods (maintenance, holidays, company-wide events) when
+//! the auditorium is closed, they are treated as hard conflicts by booking
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, offset::Utc};
+
+use std::cell::Cell;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Noob;
+
+use crate::db::{
+	Database,
+	table::Blackouts,
+	table::Reservations,
+	table::Notifications,
+	table::Generations,
+	TxTree,
+};
+
+use crate::models::{Blackout, NewBlackout, Rule, Violation};
+use crate::notify;
+use crate::response::Error;
+use crate::store::TxError;
+
+/// vrací id všech výluk, které blokují daný interval v daných místnostech
+pub fn blocking(db: &Database<Blackouts>, rooms: u8, begin_time: DateTime<Utc>, end_time: DateTime<Utc>) -> Vec<u64> {
+	db.read()
+		.iter()
+		.filter(|(_, b)| b.blocks(rooms, begin_time, end_time))
+		.map(|(id, _)| id)
+		.collect()
+}
+
+/// writes (or with `None` removes) the blackout, returns whether there was one under the id
+///
+/// the write advances the generation of blackouts, so a [`crate::booking::save`]
+/// or a new hold checked against the blackouts as they were is retried
+fn write(db: &Database<Blackouts>, generations: &Database<Generations>, id: u64, blackout: Option<&Blackout>) -> Result<bool, Error> {
+	let existed = Cell::new(false);
+
+	loop {
+		let generation = generations.generation::<Blackouts>()?;
+
+		let committed = db.store().transaction(&[db.tree(), generations.tree()], &|t| {
+			if !TxTree::<Generations>::new(t[1]).advance::<Blackouts>(generation)? {
+				return Err(TxError::Abort);
+			}
+
+			let tree = TxTree::<Blackouts>::new(t[0]);
+			existed.set(tree.get(id)?.is_some());
+
+			match blackout {
+				Some(blackout) => tree.insert(id, blackout),
+				None => tree.remove(id),
+			}
+		})?;
+
+		if committed {
+			return Ok(existed.get());
+		}
+	}
+}
+
+/// vrací všechny výluky
+///
+/// GET /blackouts "application/json"
+#[get("/blackouts", format = "application/json")]
+pub fn list(db: Database<Blackouts>, _u: AuthToken<Noob>) -> Result<Json<Vec<(u64, Blackout)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// vrátí JSON dané výluky
+///
+/// GET /blackouts/<id> application/json
+///
+/// parametry:
+/// - `id`: identifikátor dané výluky
+#[get("/blackouts/<id>")]
+pub fn get(id: u64, db: Database<Blackouts>, _u: AuthToken<Noob>) -> Result<Json<Blackout>, Error> {
+	db.read().get(id)?.map(Json).ok_or(Error::NotFound)
+}
+
+/// vytvoří výluku, vrací její id
+///
+/// POST /blackouts application/json
+///
+/// data: [`NewBlackout`]
+///
+/// pokud je nastaveno `notify_owners`, autoři schválených rezervací,
+/// do kterých výluka zasahuje, dostanou upozornění. výluka se vytvoří,
+/// i když se upozornění nepodaří zařadit do fronty
+#[post("/blackouts", data = "<input>")]
+pub fn post(
+	input: Json<NewBlackout>,
+	db: Database<Blackouts>,
+	reservations: Database<Reservations>,
+	generations: Database<Generations>,
+	mut notifications: Database<Notifications>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<u64>, Error> {
+	if !usr.manages_facility() {
+		return Err(Error::Forbidden);
+	}
+
+	if input.begin_time >= input.end_time {
+		return Err(Error::Policy(vec![Violation {
+			rule:    Rule::Interval,
+			room:    input.rooms,
+			message: "blackout must end after it begins".to_string(),
+		}]));
+	}
+
+	let notify_owners = input.notify_owners;
+	let mut blackout: Blackout = input.into_inner().into();
+	blackout.author = usr.user.email;
+
+	let id = db.get_key()?;
+	write(&db, &generations, id, Some(&blackout))?;
+
+	if notify_owners {
+		let affected = reservations
+			.read()
+			.find("approved", &true)?
+			.filter(|(_, r)| blackout.blocks(r.rooms, r.begin_time, r.end_time))
+			.collect::<Vec<_>>();
+
+		// the blackout is in place already, a failed notification mustn't fail the request
+		for (reservation, r) in affected {
+			let queued = notify::queue_reservation(
+				&mut notifications,
+				&r,
+				"Your booking of auditorium is affected by a closure",
+				format!(
+					"Your reservation \"{}\" of {} from {} to {} collides with a closure of the auditorium: {}",
+					r.name,
+					notify::room_names(r.rooms),
+					r.begin_time,
+					r.end_time,
+					blackout.reason,
+				),
+			);
+
+			if queued.is_none() {
+				log::error!("blackout: failed to notify the owners of reservation {} about blackout {}", reservation, id);
+			}
+		}
+	}
+
+	Ok(Json(id))
+}
+
+/// vymaže danou výluku
+///
+/// DELETE /blackouts/<id>
+///
+/// parametry:
+/// - `id`: identifikátor dané výluky
+#[delete("/blackouts/<id>")]
+pub fn delete(id: u64, db: Database<Blackouts>, generations: Database<Generations>, usr: AuthToken<Noob>) -> Result<(), Error> {
+	if !usr.manages_facility() {
+		return Err(Error::Forbidden);
+	}
+
+	match write(&db, &generations, id, None)? {
+		true => Ok(()),
+		false => Err(Error::NotFound),
+	}
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, get, post, delete]
+}
@@ -1,68 +1,395 @@
 //! a module containing  booking functionality
+//!
+//! reservations are versioned by their `ETag` (see [`crate::db::tag`]), changing
+//! routes require a matching `If-Match` header so that concurrent edits don't
+//! silently overwrite each other
 use rocket::Route;
+use rocket::request::{FromRequest, Request, Outcome};
 use rocket_contrib::json::Json;
 
+use std::cell::Cell;
+
 use crate::auth::AuthToken;
 use crate::auth::roles::{Noob, Approver};
 
-use crate::db::{Database, table::Reservations};
+use crate::db::{
+	self,
+	Database,
+	table::Reservations,
+	table::Blackouts,
+	table::Policies,
+	table::Quotas,
+	table::Teams,
+	table::Users,
+	table::Delegations,
+	table::Notifications,
+	table::Waitlist,
+	table::Holds,
+	table::Audit,
+	table::Revisions,
+	table::Generations,
+	TxTree,
+};
+
+use crate::models::{NewReservation, UpdateReservation, Reservation, Availability, User};
+use crate::response::{Error, Tagged};
+use crate::store::TxError;
+use crate::{audit, blackout, delegation, history, hold, notify, policy, quota, trash, waitlist};
 
-use crate::models::{NewReservation, UpdateReservation, Reservation};
+use chrono::{DateTime, offset::Utc};
 
 /*
 ** TODO proper type for response, handle RGI responses
 */
 
+/// the `If-Match` header of a request
+pub struct IfMatch(Option<String>);
+
+impl IfMatch {
+	/// whether the request was made against the current version tag of a record
+	pub fn check(&self, tag: &str) -> Result<(), Error> {
+		let header = self.0.as_ref().ok_or(Error::PreconditionRequired)?;
+
+		let matches = header
+			.split(',')
+			.map(|t| t.trim().trim_start_matches("W/").trim_matches('"'))
+			.any(|t| t == "*" || t == tag);
+
+		if matches {
+			Ok(())
+		} else {
+			Err(Error::PreconditionFailed)
+		}
+	}
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfMatch {
+	type Error = ();
+
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		Outcome::Success(IfMatch(request.headers().get_one("If-Match").map(|h| h.to_string())))
+	}
+}
+
+/// the reservation and its tag, unless it's in the trash, see [`crate::trash`]
+pub(crate) fn live(db: &Database<Reservations>, id: u64) -> Result<(Reservation, String), Error> {
+	db.read().get_tagged(id)?.filter(|(r, _)| !r.cancelled).ok_or(Error::NotFound)
+}
+
+/// zda smí uživatel upravovat nebo rušit danou rezervaci
+///
+/// smí autor, zmocněnci autora, schvalovatelé a členové týmu, kterému rezervace patří.
+/// ten, kdo ji vytvořil v zastoupení autora, jen dokud má od autora zmocnění
+pub(crate) fn can_modify(
+	event: &Reservation,
+	usr: &AuthToken<Noob>,
+	teams: &Database<Teams>,
+	delegations: &Database<Delegations>,
+) -> db::Result<bool> {
+	let member = match event.team {
+		Some(team) => teams.read().get(team)?.map(|t| t.is_member(&usr.user.email)).unwrap_or(false),
+		None => false,
+	};
+
+	Ok(event.author == usr.user.email
+		|| usr.is::<Approver>()
+		|| member
+		|| delegation::allows(delegations, &event.author, &usr.user.email)?)
+}
+
+/// zda je daný interval blokovaný schválenou rezervací, výlukou
+/// nebo blokací termínu někoho jiného než `author`
+pub(crate) fn has_conflict(
+	rooms: u8,
+	begin_time: DateTime<Utc>,
+	end_time: DateTime<Utc>,
+	author: &str,
+	reservations: &Database<Reservations>,
+	blackouts: &Database<Blackouts>,
+	holds: &Database<Holds>,
+) -> Result<bool, Error> {
+	Ok(reservations.read().find("approved", &true)?.any(|(_, x)| x.collides(rooms, begin_time, end_time))
+		|| !blackout::blocking(blackouts, rooms, begin_time, end_time).is_empty()
+		|| !hold::blocking(holds, rooms, begin_time, end_time, author).is_empty())
+}
+
+/// where a new reservation comes from, see [`Save::from`]
+#[derive(Clone, Copy)]
+pub(crate) enum Origin {
+	/// a converted hold, see [`crate::hold`]
+	Hold(u64),
+	/// a promoted waitlist entry, see [`crate::waitlist`]
+	Waitlist(u64),
+}
+
+/// a reservation write made by [`save`]
+pub(crate) struct Save<'a> {
+	pub id:     u64,
+	/// the stored version being replaced and its tag, `None` for a new reservation
+	pub before: Option<(&'a Reservation, &'a str)>,
+	pub after:  &'a Reservation,
+	pub actor:  &'a str,
+	/// the audit log action, see [`crate::audit`]
+	pub action: &'a str,
+	/// the hold or waitlist entry removed together with the write,
+	/// if it's gone already, the write is an [`Error::NotFound`]
+	pub from:   Option<Origin>,
+}
+
+/// atomically checks and writes a reservation together with its audit entry and revision
+///
+/// every write of a reservation goes through here. `check` (conflicts, quotas...) looks
+/// at the reservations, holds and blackouts as they are, the write only goes through if no
+/// other [`save`], new hold or blackout got in between, otherwise both are retried.
+/// a reservation changed since `before` is an [`Error::PreconditionFailed`]
+pub(crate) fn save<F>(write: Save, reservations: &Database<Reservations>, check: F) -> Result<(), Error>
+where
+	F: Fn() -> Result<(), Error>,
+{
+	save_all(&[write], reservations, check)
+}
+
+/// like [`save`], but all the writes go through together or none of them does
+pub(crate) fn save_all<F>(writes: &[Save], reservations: &Database<Reservations>, check: F) -> Result<(), Error>
+where
+	F: Fn() -> Result<(), Error>,
+{
+	let store = reservations.store();
+	let generations = Database::<Generations>::open(store).ok_or(Error::Database)?;
+	let audit = Database::<Audit>::open(store).ok_or(Error::Database)?;
+	let revisions = Database::<Revisions>::open(store).ok_or(Error::Database)?;
+	let holds = Database::<Holds>::open(store).ok_or(Error::Database)?;
+	let waitlist = Database::<Waitlist>::open(store).ok_or(Error::Database)?;
+
+	let mut entries = vec![];
+	for write in writes {
+		let target = format!("reservation:{}", write.id);
+		let entry = audit::entry(write.actor, write.action, target, write.before.map(|(b, _)| b), Some(write.after));
+
+		entries.push((audit.get_key()?, entry));
+	}
+
+	loop {
+		let generation = generations.generation::<Reservations>()?;
+		let held = generations.generation::<Holds>()?;
+		let closed = generations.generation::<Blackouts>()?;
+		check()?;
+
+		let mut new_revisions = vec![];
+		for write in writes {
+			// a reservation from before versioning gets its stored state as the first revision
+			let mut numbered = vec![];
+			let mut last = history::last(&revisions, write.id);
+			if let (Some((before, _)), None) = (write.before, &last) {
+				numbered.push((1, history::revision(before, history::SYSTEM, None)));
+				last = numbered.last().cloned();
+			}
+			let number = last.as_ref().map(|(n, _)| n + 1).unwrap_or(1);
+			numbered.push((number, history::revision(write.after, write.actor, last.as_ref().map(|(_, r)| r))));
+
+			new_revisions.push(numbered);
+		}
+
+		let failed = Cell::new(None);
+
+		let index = reservations.index_tree().expect("reservations are indexed");
+		let trees = [reservations.tree(), index, generations.tree(), audit.tree(), revisions.tree(), holds.tree(), waitlist.tree()];
+
+		let committed = store.transaction(&trees, &|t| {
+			let (r, g) = (TxTree::<Reservations>::indexed(t[0], t[1]), TxTree::<Generations>::new(t[2]));
+
+			// the check is stale, try again
+			if !g.advance::<Reservations>(generation)? || !g.unchanged::<Holds>(held)? || !g.unchanged::<Blackouts>(closed)? {
+				return Err(TxError::Abort);
+			}
+
+			for ((write, numbered), (audit_key, entry)) in writes.iter().zip(&new_revisions).zip(&entries) {
+				if let Some((_, tag)) = write.before {
+					if r.get_tagged(write.id)?.map(|(_, t)| t != tag).unwrap_or(true) {
+						failed.set(Some(Error::PreconditionFailed));
+						return Err(TxError::Abort);
+					}
+				}
+
+				let found = match write.from {
+					Some(Origin::Hold(id)) => TxTree::<Holds>::new(t[5]).get(id)?.map(|_| TxTree::<Holds>::new(t[5]).remove(id)),
+					Some(Origin::Waitlist(id)) => TxTree::<Waitlist>::new(t[6]).get(id)?.map(|_| TxTree::<Waitlist>::new(t[6]).remove(id)),
+					None => Some(Ok(())),
+				};
+				match found {
+					Some(removed) => removed?,
+					None => {
+						failed.set(Some(Error::NotFound));
+						return Err(TxError::Abort);
+					}
+				}
+
+				let revision_tree = TxTree::<Revisions>::new(t[4]);
+				for (n, revision) in numbered {
+					// someone else got the number, try again
+					if revision_tree.get((write.id, *n))?.is_some() {
+						return Err(TxError::Abort);
+					}
+
+					revision_tree.insert((write.id, *n), revision)?;
+				}
+
+				r.insert(write.id, write.after)?;
+				TxTree::<Audit>::new(t[3]).insert(*audit_key, entry)?;
+			}
+
+			Ok(())
+		})?;
+
+		if committed {
+			return Ok(());
+		}
+
+		if let Some(e) = failed.take() {
+			return Err(e);
+		}
+	}
+}
+
 /// vrací všechny rezervace
 ///
-/// GET /events "application/json"
-#[get("/events", format = "application/json")]
-pub fn list(db: Database<Reservations>) -> Option<Json<Vec<(u64, Reservation)>>> {
-	Some(Json(db.read().iter().collect::<Vec<(u64, Reservation)>>()))
+/// GET /events?<team> "application/json"
+///
+/// parametry:
+/// - `team`: volitelně pouze rezervace daného týmu
+#[get("/events?<team>", format = "application/json")]
+pub fn list(team: Option<u64>, db: Database<Reservations>) -> Result<Json<Vec<(u64, Reservation)>>, Error> {
+	Ok(Json(
+		match team {
+			Some(team) => db.read().find("team", &team)?.collect::<Vec<(u64, Reservation)>>(),
+			None => db.read().try_iter().filter(|r| r.as_ref().map(|(_, r)| !r.cancelled).unwrap_or(true)).collect::<Result<_, _>>()?,
+		},
+	))
 }
 
 /// vrátí JSON dané rezervace
 ///
 /// GET /events/<id> application/json
 ///
+/// hlavička `ETag` obsahuje verzi rezervace, kterou je třeba poslat
+/// v `If-Match` při úpravě, mazání a schvalování
+///
 /// parametry:
 /// - `id`: identifikátor dané rezervace
 #[get("/events/<id>")]
-pub fn get(id: u64, db: Database<Reservations>, _u: AuthToken<Noob>) -> Option<Json<Reservation>> {
-	db.read()
-		.get(id) // can't fail
-		.map(Json)
+pub fn get(id: u64, db: Database<Reservations>, _u: AuthToken<Noob>) -> Result<Tagged<Json<Reservation>>, Error> {
+	let (reservation, tag) = live(&db, id)?;
+
+	Ok(Tagged(Json(reservation), tag))
 }
 
-/// vrátí JSON dané rezervace
+/// ověří a sestaví novou rezervaci, vrací jejího vlastníka a rezervaci
+///
+/// vyřeší zastoupení (`on_behalf_of`), členství v týmu a pravidla místností,
+/// kolize a kvóty kontroluje volající
+pub(crate) fn prepare(
+	input: NewReservation,
+	usr: &User,
+	users: &Database<Users>,
+	delegations: &Database<Delegations>,
+	teams: &Database<Teams>,
+	policies: &Database<Policies>,
+) -> Result<(User, Reservation), Error> {
+	let owner = match &input.on_behalf_of {
+		Some(principal) if principal != &usr.email => {
+			if !delegation::allows(delegations, principal, &usr.email)? {
+				return Err(Error::Forbidden);
+			}
+
+			users.read().get(principal.clone())?.ok_or(Error::NotFound)?
+		}
+		_ => usr.clone(),
+	};
+
+	if let Some(team) = input.team {
+		let team = teams.read().get(team)?.ok_or(Error::NotFound)?;
+
+		if !team.is_member(&owner.email) {
+			return Err(Error::Forbidden);
+		}
+	}
+
+	policy::check(policies, &owner, input.rooms, input.begin_time, input.end_time)?;
+
+	let mut new_res: Reservation = input.into();
+
+	new_res.author = owner.email.clone();
+
+	if owner.email != usr.email {
+		new_res.requested_by = Some(usr.email.clone());
+	}
+
+	Ok((owner, new_res))
+}
+
+/// vytvoří rezervaci, vrací její id
 ///
 /// POST /events application/json
 ///
 /// data: [`NewReservation`]
+///
+/// s `on_behalf_of` vytvoří rezervaci v zastoupení jiného uživatele,
+/// ten musí uživateli udělit zmocnění, viz [`crate::delegation`].
+/// pokud je termín obsazený, je možné se zapsat na čekací listinu, viz [`crate::waitlist`]
 #[post("/events", data = "<input>")]
-pub fn post(input: Json<NewReservation>, mut db: Database<Reservations>, usr: AuthToken<Noob>) -> Option<()> {
-	let has_conflict = db.read().iter().any(|(_, x)| {
-		x.approved
-			&& x.begin_time <= input.end_time
-			&& x.end_time >= input.begin_time
-			&& (x.rooms == 3 || x.rooms == input.rooms)
-	});
-
-	if has_conflict {
-		return None; // todo proper errors
-	}
+pub fn post(
+	input: Json<NewReservation>,
+	db: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	teams: Database<Teams>,
+	users: Database<Users>,
+	delegations: Database<Delegations>,
+	mut notifications: Database<Notifications>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<u64>, Error> {
+	let (owner, new_res) = prepare(input.into_inner(), &usr.user, &users, &delegations, &teams, &policies)?;
+	let id = db.get_key()?;
+
+	let write = Save { id, before: None, after: &new_res, actor: &usr.user.email, action: "reservation.create", from: None };
+	save(write, &db, || {
+		let (rooms, begin_time, end_time) = (new_res.rooms, new_res.begin_time, new_res.end_time);
+		if has_conflict(rooms, begin_time, end_time, &new_res.author, &db, &blackouts, &holds)? {
+			return Err(Error::Conflict);
+		}
 
-	let mut new_res: Reservation = input.into_inner().into();
+		quota::check(&quotas, &db, &owner, &new_res, None)
+	})?;
 
-	new_res.author = usr.user.email;
+	if new_res.requested_by.is_some() {
+		notify::queue_reservation(
+			&mut notifications,
+			&new_res,
+			"Booking of auditorium on your behalf",
+			format!(
+				"{} requested a reservation \"{}\" of {} from {} to {} on behalf of {}",
+				usr.user.email,
+				new_res.name,
+				notify::room_names(new_res.rooms),
+				new_res.begin_time,
+				new_res.end_time,
+				new_res.author,
+			),
+		)
+		.ok_or(Error::Database)?;
+	}
 
-	db.write().insert(Database::<Reservations>::get_key().unwrap(), new_res).ok()?.map(|_| ())
+	Ok(Json(id))
 }
 
 /// upraví danou rezervaci
 ///
 /// PATCH /events/<id> application/json
 ///
+/// vyžaduje hlavičku `If-Match` s aktuálním `ETag` rezervace
+///
 /// parametry:
 /// - `id`: identifikátor dané rezervace
 ///
@@ -71,66 +398,95 @@ pub fn post(input: Json<NewReservation>, mut db: Database<Reservations>, usr: Au
 pub fn patch(
 	id: u64,
 	input: Json<UpdateReservation>,
-	mut db: Database<Reservations>,
+	db: Database<Reservations>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	teams: Database<Teams>,
+	users: Database<Users>,
+	delegations: Database<Delegations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	mut waitlist: Database<Waitlist>,
+	mut notifications: Database<Notifications>,
+	if_match: IfMatch,
 	usr: AuthToken<Noob>,
-) -> Option<()> {
-	let event = db.read().get(id)?;
+) -> Result<(), Error> {
+	let (event, tag) = live(&db, id)?;
 
-	// TODO  roles are uggly
-	if event.author != usr.user.email || usr.user.role != "approver" {
-		return None;
+	if !can_modify(&event, &usr, &teams, &delegations)? {
+		return Err(Error::Forbidden);
 	}
 
-	let update_result = db
-		.write()
-		.update::<_, Reservation, _>(id, |val| {
-			if let Some(mut val) = val {
-				match input.clone() {
-					UpdateReservation { name, description, rooms, begin_time, end_time, layout, people } => {
-						name.map(|x| { val.name = x });
-						description.map(|x| { val.description = x });
-						rooms.map(|x| { val.rooms = x });
-						begin_time.map(|x| { val.begin_time = x });
-						end_time.map(|x| { val.end_time = x });
-						layout.map(|x| { val.layout = x });
-						people.map(|x| { val.people = x });
-					}
-				}
+	if_match.check(&tag)?;
 
-				val.approved = false;
+	let UpdateReservation { name, description, rooms, begin_time, end_time, layout, people } = input.into_inner();
 
-				Some(val)
-			} else {
-				None
-			}
-		});
+	let mut updated = event.clone();
+	name.map(|x| { updated.name = x });
+	description.map(|x| { updated.description = x });
+	rooms.map(|x| { updated.rooms = x });
+	begin_time.map(|x| { updated.begin_time = x });
+	end_time.map(|x| { updated.end_time = x });
+	layout.map(|x| { updated.layout = x });
+	people.map(|x| { updated.people = x });
+	updated.approved = false;
 
-	if update_result.is_err() {
-		return None;
-	}
+	let owner = users.read().get(&event.author)?.unwrap_or_else(|| usr.user.clone());
+
+	policy::check(&policies, &owner, updated.rooms, updated.begin_time, updated.end_time)?;
+
+	let write = Save {
+		id,
+		before: Some((&event, &tag)),
+		after:  &updated,
+		actor:  &usr.user.email,
+		action: "reservation.update",
+		from:   None,
+	};
+	save(write, &db, || {
+		quota::check(&quotas, &db, &owner, &updated, Some(id))
+	})?;
 
-	Some(())
+	// the reservation is no longer approved (and maybe shorter)
+	waitlist::release(&mut waitlist, &db, &blackouts, &holds, &mut notifications)
 }
 
 /// vymaže danou rezervaci
 ///
 /// DELETE /events/<id>/
+///
+/// vyžaduje hlavičku `If-Match` s aktuálním `ETag` rezervace.
+/// rezervace se zruší a zůstane v koši, odkud ji schvalovatelé mohou obnovit, viz [`crate::trash`]
+///
 /// parametry:
 /// - `id`: identifikátor dané rezervace
 #[delete("/events/<id>")]
-pub fn delete(id: u64, mut db: Database<Reservations>, usr: AuthToken<Noob>) -> Option<()> {
-	use crate::auth::roles::Role;
-
-	let event = db.read().get(id)?;
+pub fn delete(
+	id: u64,
+	db: Database<Reservations>,
+	teams: Database<Teams>,
+	delegations: Database<Delegations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	mut waitlist: Database<Waitlist>,
+	mut notifications: Database<Notifications>,
+	if_match: IfMatch,
+	usr: AuthToken<Noob>,
+) -> Result<(), Error> {
+	let (event, tag) = live(&db, id)?;
 
-	// TODO  roles are uggly
-	if event.author != usr.user.email || usr.user.role != Approver::name() {
-		None?
+	if !can_modify(&event, &usr, &teams, &delegations)? {
+		return Err(Error::Forbidden);
 	}
 
-	db.write().delete(id).ok()?;
+	if_match.check(&tag)?;
+
+	// an approver deleting someone else's request rejects it
+	let rejected = usr.is::<Approver>() && !event.approved && event.author != usr.user.email;
+
+	trash::discard(&db, id, (&event, &tag), &usr.user.email, rejected)?;
 
-	Some(())
+	waitlist::release(&mut waitlist, &db, &blackouts, &holds, &mut notifications)
 }
 
 /// filtruje podle data
@@ -155,43 +511,113 @@ pub fn date_filter(
 
 	Some(Json(
 		db.read()
-			.iter()
-			.filter(|(_, v)| v.begin_time >= begin_time && v.begin_time <= end_time)
+			.find_range("begin_time", begin_time..=end_time)
+			.ok()?
 			.filter(|(_, v)| v.rooms == rooms)
 			.collect::<Vec<(u64, Reservation)>>(),
 	))
 }
 
+/// zjistí, zda je daný termín volný
+///
+/// GET /events/availability/<rooms>/<begin_time>/<end_time>
+///
+/// parametry:
+/// - `rooms`:  bitflagy pro místnosti, viz [`Reservation`]
+/// - `begin_time`: počáteční čas
+/// - `end_time`: čas konce
+///
+/// vrací: [`Availability`]
+#[get("/events/availability/<rooms>/<begin_time>/<end_time>")]
+pub fn availability(
+	rooms: u8,
+	begin_time: String,
+	end_time: String,
+	db: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	policies: Database<Policies>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<Availability>, Error> {
+	let parse = |t: &str| DateTime::parse_from_rfc3339(t).map(DateTime::<Utc>::from).map_err(|_| Error::NotFound);
+	let (begin_time, end_time) = (parse(&begin_time)?, parse(&end_time)?);
+
+	let reservations = db
+		.read()
+		.find("approved", &true)?
+		.filter(|(_, x)| x.collides(rooms, begin_time, end_time))
+		.map(|(id, _)| id)
+		.collect::<Vec<u64>>();
+	let blackouts = blackout::blocking(&blackouts, rooms, begin_time, end_time);
+	let holds = hold::blocking(&holds, rooms, begin_time, end_time, &usr.user.email);
+	let violations = policy::violations(&policies, &usr.user, rooms, begin_time, end_time)?;
+
+	Ok(Json(Availability {
+		available: reservations.is_empty() && blackouts.is_empty() && holds.is_empty() && violations.is_empty(),
+		reservations,
+		blackouts,
+		holds,
+		violations,
+	}))
+}
+
 /// schválí endpoint
 ///
 /// POST /events/<id>/approve
 ///
+/// vyžaduje hlavičku `If-Match` s aktuálním `ETag` rezervace,
+/// schvaluje se tedy právě ta verze, kterou schvalovatel viděl
+///
 /// parametry:
 /// - `id`: id rezervace
 #[post("/events/<id>/approve")]
-pub fn approve(id: u64, mut db: Database<Reservations>, _u: AuthToken<Approver>) -> Option<()> {
-	let event = db.read().get(id)?;
+pub fn approve(
+	id: u64,
+	db: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	mut notifications: Database<Notifications>,
+	if_match: IfMatch,
+	usr: AuthToken<Approver>,
+) -> Result<(), Error> {
+	let (event, tag) = live(&db, id)?;
+
+	if_match.check(&tag)?;
+
+	let mut after = event.clone();
+	after.approved = true;
 
 	// TODO maybe also delete conflicting events
-	let has_conflict = db.read().iter().any(|(_, x)| {
-		x.approved
-			&& x.begin_time <= event.end_time
-			&& x.end_time >= event.begin_time
-			&& (x.rooms == 3 || x.rooms == event.rooms)
-	});
-
-	if !has_conflict {
-		db.write()
-			.update::<_, Reservation, _>(id, |x| if let Some(mut x) = x {
-				x.approved = true;
-				Some(x)
-			} else {None}).ok()?;
-	}
+	let write = Save {
+		id,
+		before: Some((&event, &tag)),
+		after:  &after,
+		actor:  &usr.user.email,
+		action: "reservation.approve",
+		from:   None,
+	};
+	save(write, &db, || {
+		match has_conflict(event.rooms, event.begin_time, event.end_time, &event.author, &db, &blackouts, &holds)? {
+			true => Err(Error::Conflict),
+			false => Ok(()),
+		}
+	})?;
 
-	Some(())
+	notify::queue_reservation(
+		&mut notifications,
+		&event,
+		"Approval of your booking of auditorium",
+		format!(
+			"Your reservation of {} from {} to {} has been approved!",
+			notify::room_names(event.rooms),
+			event.begin_time,
+			event.end_time,
+		),
+	)
+	.ok_or(Error::Database)
 }
 
 /// vrací seznam endpointů pro nabindování do Rocketu
 pub fn routes() -> Vec<Route> {
-	routes![date_filter, list, approve, get, post, patch, delete,]
+	routes![date_filter, availability, list, approve, get, post, patch, delete,]
 }
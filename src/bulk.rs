@@ -0,0 +1,418 @@
+//! a module containing bulk export and import of reservations and users
+//!
+//! superadmins export reservations (with filters) and users as CSV or JSON Lines,
+//! e.g. for spreadsheets of auditorium usage, and import them back, e.g. to seed data.
+//! an import first validates every row and reports the errors by line, it writes
+//! nothing unless all rows are valid, and with `dry_run` it never writes anything
+use rocket::{Data, Route};
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, offset::Utc};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::io::Read;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Superadmin;
+
+use crate::db::{
+	Database,
+	table::Reservations,
+	table::Users,
+	table::Blackouts,
+	table::Holds,
+	table::Audit,
+};
+
+use crate::admin::ROLES;
+use crate::audit;
+use crate::booking::{self, Save};
+use crate::models::{Reservation, User, ROOMS};
+use crate::response::Error;
+
+/// the largest accepted import
+const LIMIT: u64 = 16 * 1024 * 1024;
+
+/// columns of exported reservations
+const RESERVATION_COLUMNS: [&str; 14] = [
+	"id", "name", "description", "author", "requested_by", "rooms", "begin_time", "end_time",
+	"layout", "approved", "people", "team", "checked_in", "no_show",
+];
+
+/// format of exported and imported rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	/// CSV with a header
+	Csv,
+	/// JSON Lines, one object per line
+	JsonLines,
+}
+
+impl Format {
+	/// the format given by its name in the path
+	fn parse(name: &str) -> Option<Format> {
+		match name {
+			"csv" => Some(Format::Csv),
+			"jsonl" => Some(Format::JsonLines),
+			_ => None,
+		}
+	}
+
+	fn content_type(self) -> ContentType {
+		match self {
+			Format::Csv => ContentType::CSV,
+			Format::JsonLines => ContentType::new("application", "x-ndjson"),
+		}
+	}
+}
+
+/// chyba na jednom řádku importu
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LineError {
+	/// číslo řádku od 1, u CSV včetně hlavičky
+	pub line:  u64,
+	/// popis chyby
+	pub error: String,
+}
+
+/// výsledek importu
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportReport {
+	/// zda šlo jen o zkoušku, nic se nezapsalo
+	pub dry_run:  bool,
+	/// počet bezchybných řádků
+	pub valid:    usize,
+	/// klíče zapsaných záznamů
+	pub imported: Vec<String>,
+	/// chyby podle řádků, s chybami se nezapíše nic
+	pub errors:   Vec<LineError>,
+}
+
+/// parses the rows of an import, returns the rows with their line numbers and the rows which failed
+pub fn parse<T: DeserializeOwned>(format: Format, input: &str) -> (Vec<(u64, T)>, Vec<LineError>) {
+	let (mut rows, mut errors) = (vec![], vec![]);
+
+	match format {
+		Format::Csv => {
+			let mut reader = csv::Reader::from_reader(input.as_bytes());
+			let headers = match reader.headers() {
+				Ok(headers) => headers.clone(),
+				Err(e) => return (rows, vec![LineError { line: 1, error: e.to_string() }]),
+			};
+
+			for record in reader.records() {
+				let parsed = record.and_then(|r| Ok((r.position().map(|p| p.line()).unwrap_or(0), r.deserialize::<T>(Some(&headers))?)));
+
+				match parsed {
+					Ok(row) => rows.push(row),
+					Err(e) => errors.push(LineError { line: e.position().map(|p| p.line()).unwrap_or(0), error: e.to_string() }),
+				}
+			}
+		}
+		Format::JsonLines => {
+			for (line, text) in (1..).zip(input.lines()).filter(|(_, t)| !t.trim().is_empty()) {
+				match serde_json::from_str(text) {
+					Ok(row) => rows.push((line, row)),
+					Err(e) => errors.push(LineError { line, error: e.to_string() }),
+				}
+			}
+		}
+	}
+
+	(rows, errors)
+}
+
+/// writes the rows as CSV with the given columns or as JSON Lines of the objects
+fn write<T: Serialize>(format: Format, columns: &[&str], rows: impl Iterator<Item = (Value, T)>) -> Option<Vec<u8>> {
+	let objects = rows.map(|(id, row)| match serde_json::to_value(row) {
+		Ok(Value::Object(mut map)) => {
+			if !id.is_null() {
+				map.insert("id".to_string(), id);
+			}
+			Some(map)
+		}
+		_ => None,
+	});
+
+	match format {
+		Format::Csv => {
+			let mut writer = csv::Writer::from_writer(vec![]);
+			writer.write_record(columns).ok()?;
+
+			for object in objects {
+				let object = object?;
+				let cells = columns.iter().map(|c| match object.get(*c) {
+					None | Some(Value::Null) => String::new(),
+					Some(Value::String(s)) => s.clone(),
+					Some(other) => other.to_string(),
+				});
+
+				writer.write_record(cells).ok()?;
+			}
+
+			writer.into_inner().ok()
+		}
+		Format::JsonLines => {
+			let mut out = vec![];
+
+			for object in objects {
+				serde_json::to_writer(&mut out, &object?).ok()?;
+				out.push(b'\n');
+			}
+
+			Some(out)
+		}
+	}
+}
+
+/// reads the body of an import
+fn read(data: Data) -> Result<String, Error> {
+	let mut input = String::new();
+	data.open().take(LIMIT).read_to_string(&mut input).map_err(|_| Error::Database)?;
+
+	Ok(input)
+}
+
+/// parses an RFC 3339 timestamp of a filter
+fn timestamp(t: Option<String>) -> Option<Option<DateTime<Utc>>> {
+	match t {
+		Some(t) => Some(Some(DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&t).ok()?))),
+		None => Some(None),
+	}
+}
+
+/// exportuje rezervace jako CSV nebo JSON Lines
+///
+/// GET /export/reservations/<kind>?<author>&<team>&<approved>&<from>&<to>
+///
+/// parametry:
+/// - `kind`: formát, `csv` nebo `jsonl`
+/// - `author`: volitelně pouze rezervace daného uživatele
+/// - `team`: volitelně pouze rezervace daného týmu
+/// - `approved`: volitelně pouze schválené, nebo neschválené rezervace
+/// - `from`, `to`: volitelně pouze rezervace zasahující do intervalu (RFC 3339)
+#[get("/export/reservations/<kind>?<author>&<team>&<approved>&<from>&<to>")]
+pub fn export_reservations(
+	kind: String,
+	author: Option<String>,
+	team: Option<u64>,
+	approved: Option<bool>,
+	from: Option<String>,
+	to: Option<String>,
+	db: Database<Reservations>,
+	_u: AuthToken<Superadmin>,
+) -> Result<Content<Vec<u8>>, Error> {
+	let format = Format::parse(&kind).ok_or(Error::NotFound)?;
+	let (from, to) = (timestamp(from).ok_or(Error::NotFound)?, timestamp(to).ok_or(Error::NotFound)?);
+
+	let rows = db
+		.read()
+		.try_iter()
+		.collect::<Result<Vec<(u64, Reservation)>, _>>()?
+		.into_iter()
+		.filter(|(_, r)| !r.cancelled)
+		.filter(|(_, r)| author.as_ref().map(|a| &r.author == a).unwrap_or(true))
+		.filter(|(_, r)| team.map(|t| r.team == Some(t)).unwrap_or(true))
+		.filter(|(_, r)| approved.map(|a| r.approved == a).unwrap_or(true))
+		.filter(|(_, r)| from.map(|f| r.end_time >= f).unwrap_or(true) && to.map(|t| r.begin_time <= t).unwrap_or(true))
+		.map(|(id, r)| (Value::from(id), r));
+
+	Ok(Content(format.content_type(), write(format, &RESERVATION_COLUMNS, rows).ok_or(Error::Database)?))
+}
+
+/// exportuje uživatele jako CSV nebo JSON Lines
+///
+/// GET /export/users/<kind>
+///
+/// parametry:
+/// - `kind`: formát, `csv` nebo `jsonl`
+#[get("/export/users/<kind>")]
+pub fn export_users(kind: String, db: Database<Users>, _u: AuthToken<Superadmin>) -> Result<Content<Vec<u8>>, Error> {
+	let format = Format::parse(&kind).ok_or(Error::NotFound)?;
+	let users = db.read().try_iter().collect::<Result<Vec<(String, User)>, _>>()?;
+	let rows = users.into_iter().map(|(_, u)| (Value::Null, u));
+
+	Ok(Content(format.content_type(), write(format, &["name", "email", "role"], rows).ok_or(Error::Database)?))
+}
+
+/// validates an imported reservation, `accepted` are the valid rows before it
+///
+/// returns what is wrong with the row, if anything
+fn validate(
+	row: &Reservation,
+	accepted: &[(u64, Reservation)],
+	users: &Database<Users>,
+	reservations: &Database<Reservations>,
+	blackouts: &Database<Blackouts>,
+	holds: &Database<Holds>,
+) -> Result<Option<String>, Error> {
+	if row.cancelled || row.deleted_at.is_some() {
+		return Ok(Some("deleted reservations can't be imported".to_string()));
+	}
+
+	if row.end_time <= row.begin_time {
+		return Ok(Some("end_time must be after begin_time".to_string()));
+	}
+
+	if row.rooms == 0 || row.rooms & !ROOMS.iter().fold(0, |a, r| a | r) != 0 {
+		return Ok(Some(format!("unknown rooms {}", row.rooms)));
+	}
+
+	if users.read().get(&row.author)?.is_none() {
+		return Ok(Some(format!("unknown author {}", row.author)));
+	}
+
+	let (rooms, begin_time, end_time) = (row.rooms, row.begin_time, row.end_time);
+	if booking::has_conflict(rooms, begin_time, end_time, &row.author, reservations, blackouts, holds)?
+		|| accepted.iter().any(|(_, r)| r.approved && r.collides(rooms, begin_time, end_time))
+	{
+		return Ok(Some("the term collides with an approved reservation, a blackout or a hold".to_string()));
+	}
+
+	Ok(None)
+}
+
+/// importuje rezervace z CSV nebo JSON Lines, vrací [`ImportReport`]
+///
+/// POST /import/reservations/<kind>?<dry_run>
+///
+/// sloupce jsou stejné jako u exportu, sloupec `id` se ignoruje a rezervace dostanou nová id.
+/// každý řádek se ověří (interval, místnosti, existence autora) a zkontrolují se kolize
+/// se schválenými rezervacemi, výlukami, blokacemi i s předchozími řádky.
+/// pravidla místností a kvóty se nekontrolují. pokud má některý řádek chybu, nezapíše se nic,
+/// jinak se všechny řádky zapíší najednou
+///
+/// parametry:
+/// - `kind`: formát, `csv` nebo `jsonl`
+/// - `dry_run`: pouze ověří data, nic nezapíše
+#[post("/import/reservations/<kind>?<dry_run>", data = "<data>")]
+pub fn import_reservations(
+	kind: String,
+	dry_run: Option<bool>,
+	data: Data,
+	db: Database<Reservations>,
+	users: Database<Users>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	usr: AuthToken<Superadmin>,
+) -> Result<Json<ImportReport>, Error> {
+	let format = Format::parse(&kind).ok_or(Error::NotFound)?;
+	let dry_run = dry_run.unwrap_or(false);
+	let (rows, mut errors) = parse::<Reservation>(format, &read(data)?);
+
+	let mut accepted = vec![];
+	for (line, row) in rows {
+		match validate(&row, &accepted, &users, &db, &blackouts, &holds)? {
+			None => accepted.push((line, row)),
+			Some(error) => errors.push(LineError { line, error }),
+		}
+	}
+	errors.sort_by_key(|e| e.line);
+
+	let mut report = ImportReport { dry_run, valid: accepted.len(), imported: vec![], errors };
+	if dry_run || !report.errors.is_empty() {
+		return Ok(Json(report));
+	}
+
+	let mut ids = vec![];
+	for _ in &accepted {
+		ids.push(db.get_key()?);
+	}
+
+	let writes = ids
+		.iter()
+		.zip(&accepted)
+		.map(|(id, (_, row))| Save { id: *id, before: None, after: row, actor: &usr.user.email, action: "reservation.import", from: None })
+		.collect::<Vec<Save>>();
+
+	// all rows are written together, a term taken since the validation fails the whole import
+	let taken = Cell::new(None);
+	let saved = booking::save_all(&writes, &db, || {
+		for (line, r) in &accepted {
+			if booking::has_conflict(r.rooms, r.begin_time, r.end_time, &r.author, &db, &blackouts, &holds)? {
+				taken.set(Some(*line));
+				return Err(Error::Conflict);
+			}
+		}
+
+		Ok(())
+	});
+
+	match (saved, taken.get()) {
+		(Ok(()), _) => report.imported = ids.iter().map(u64::to_string).collect(),
+		(Err(Error::Conflict), Some(line)) => report.errors.push(LineError { line, error: "the term has been taken meanwhile".to_string() }),
+		(Err(e), _) => return Err(e),
+	}
+
+	Ok(Json(report))
+}
+
+/// importuje uživatele z CSV nebo JSON Lines, vrací [`ImportReport`]
+///
+/// POST /import/users/<kind>?<dry_run>
+///
+/// sloupce `name`, `email` a `role`. existující uživatelé se nepřepisují,
+/// role se mění přes `PATCH /users/<email>/<new_role>`. pokud má některý řádek chybu, nezapíše se nic
+///
+/// parametry:
+/// - `kind`: formát, `csv` nebo `jsonl`
+/// - `dry_run`: pouze ověří data, nic nezapíše
+#[post("/import/users/<kind>?<dry_run>", data = "<data>")]
+pub fn import_users(
+	kind: String,
+	dry_run: Option<bool>,
+	data: Data,
+	mut db: Database<Users>,
+	mut audit: Database<Audit>,
+	usr: AuthToken<Superadmin>,
+) -> Result<Json<ImportReport>, Error> {
+	let format = Format::parse(&kind).ok_or(Error::NotFound)?;
+	let dry_run = dry_run.unwrap_or(false);
+	let (rows, mut errors) = parse::<User>(format, &read(data)?);
+
+	let mut seen = HashSet::new();
+	let mut accepted = vec![];
+	for (line, row) in rows {
+		let error = if !row.email.contains('@') {
+			Some(format!("invalid email {}", row.email))
+		} else if !ROLES.contains(&row.role.as_str()) {
+			Some(format!("unknown role {}", row.role))
+		} else if db.read().get(&row.email)?.is_some() || !seen.insert(row.email.clone()) {
+			Some(format!("user {} already exists", row.email))
+		} else {
+			None
+		};
+
+		match error {
+			Some(error) => errors.push(LineError { line, error }),
+			None => accepted.push(row),
+		}
+	}
+	errors.sort_by_key(|e| e.line);
+
+	let mut report = ImportReport { dry_run, valid: accepted.len(), imported: vec![], errors };
+	if dry_run || !report.errors.is_empty() {
+		return Ok(Json(report));
+	}
+
+	for user in accepted {
+		db.write().insert(&user.email, &user)?;
+		audit::record(&mut audit, &usr.user.email, "user.import", format!("user:{}", user.email), None, Some(&user))
+			.ok_or(Error::Database)?;
+
+		report.imported.push(user.email);
+	}
+
+	Ok(Json(report))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![export_reservations, export_users, import_reservations, import_users]
+}
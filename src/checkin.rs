@@ -0,0 +1,275 @@
+//! a module containing check-in of reservations
+//!
+//! an approved reservation is checked in by its owner or by anyone knowing
+//! the check-in code of one of its rooms (see [`crate::room`]). if nobody checks in
+//! within `NO_SHOW_MINUTES` (15 by default) of the beginning, the [`release`] job
+//! marks the reservation as a no-show and frees the rest of its slot.
+//! the check-in QR code of a room points at [`room_checkin`]
+use rocket::Route;
+use rocket::response::content::Html;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, Duration, offset::Utc};
+
+use std::env;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{Noob, Approver};
+
+use crate::db::{
+	self,
+	Database,
+	table::Reservations,
+	table::Rooms,
+	table::Teams,
+	table::Delegations,
+	table::Blackouts,
+	table::Holds,
+	table::Waitlist,
+	table::Notifications,
+};
+
+use crate::booking::Save;
+use crate::models::{Attendance, Reservation, ROOMS};
+use crate::response::Error;
+use crate::store::Store;
+use crate::{booking, history, notify, room, waitlist};
+use crate::static_server::{escape, page};
+
+/// actor of check-ins made with the check-in code of a room, see [`crate::history`]
+const CODE_ACTOR: &str = "checkin-code";
+
+/// how early before the beginning a reservation can be checked in
+const EARLY_MINUTES: i64 = 15;
+
+/// after how long without a check-in the reservation is a no-show
+fn no_show_after() -> Duration {
+	Duration::minutes(env::var("NO_SHOW_MINUTES").ok().and_then(|m| m.parse().ok()).unwrap_or(15))
+}
+
+/// whether `code` is the check-in code of one of the reserved rooms
+fn code_matches(rooms: &Database<Rooms>, reservation: &Reservation, code: &str) -> db::Result<bool> {
+	for &room in ROOMS.iter().filter(|&&room| reservation.rooms & room != 0) {
+		if rooms.read().get(room)?.and_then(|r| r.checkin_code).as_deref() == Some(code) {
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
+/// whether the reservation can be checked in at `now`
+fn window_open(reservation: &Reservation, now: DateTime<Utc>) -> bool {
+	reservation.approved
+		&& !reservation.no_show
+		&& now >= reservation.begin_time - Duration::minutes(EARLY_MINUTES)
+		&& now <= reservation.end_time
+}
+
+/// checks the reservation in at `now` unless it already is, returns it as checked in
+///
+/// a reservation changed in the meantime is read and checked again
+fn check_in(db: &Database<Reservations>, id: u64, actor: &str, now: DateTime<Utc>) -> Result<Reservation, Error> {
+	loop {
+		let (event, tag) = booking::live(db, id)?;
+
+		if !window_open(&event, now) {
+			return Err(Error::Conflict);
+		}
+
+		if event.checked_in.is_some() {
+			return Ok(event);
+		}
+
+		let mut after = event.clone();
+		after.checked_in = Some(now);
+
+		let write = Save { id, before: Some((&event, &tag)), after: &after, actor, action: "reservation.checkin", from: None };
+		match booking::save(write, db, || Ok(())) {
+			Err(Error::PreconditionFailed) => continue,
+			saved => return saved.map(|()| after),
+		}
+	}
+}
+
+/// attendance of the user's approved reservations which already began
+pub fn attendance(reservations: &Database<Reservations>, email: &str) -> db::Result<Attendance> {
+	let now = Utc::now();
+
+	Ok(reservations
+		.read()
+		.find("author", email)?
+		.filter(|(_, r)| r.approved && r.begin_time <= now)
+		.fold(Attendance::default(), |mut a, (_, r)| {
+			a.reservations += 1;
+			a.checked_in += r.checked_in.is_some() as u32;
+			a.no_shows += r.no_show as u32;
+			a
+		}))
+}
+
+/// background job marking reservations nobody checked in to as no-shows
+///
+/// the rest of their slot is released and offered to the waitlist
+pub fn release(store: &Store) -> Result<(), Error> {
+	let now = Utc::now();
+	let deadline = now - no_show_after();
+
+	let reservations = Database::<Reservations>::open(store).ok_or(Error::Database)?;
+	let mut notifications = Database::<Notifications>::open(store).ok_or(Error::Database)?;
+
+	let missed = |r: &Reservation| r.approved && !r.no_show && r.checked_in.is_none() && r.begin_time <= deadline && r.end_time > now;
+	let no_shows = reservations
+		.read()
+		.find_range("begin_time", ..=deadline)?
+		.filter(|(_, r)| missed(r))
+		.map(|(id, _)| id)
+		.collect::<Vec<u64>>();
+
+	if no_shows.is_empty() {
+		return Ok(());
+	}
+
+	for id in no_shows {
+		let (r, tag) = match reservations.read().get_tagged(id)? {
+			Some((r, tag)) if missed(&r) => (r, tag),
+			_ => continue,
+		};
+
+		let mut after = r.clone();
+		after.no_show = true;
+		after.end_time = now;
+
+		let write = Save {
+			id,
+			before: Some((&r, &tag)),
+			after:  &after,
+			actor:  history::SYSTEM,
+			action: "reservation.no_show",
+			from:   None,
+		};
+		match booking::save(write, &reservations, || Ok(())) {
+			// checked in or changed in the meantime, the next run decides
+			Err(Error::PreconditionFailed) => continue,
+			saved => saved?,
+		}
+
+		notify::queue_reservation(
+			&mut notifications,
+			&after,
+			"Your auditorium reservation was released",
+			format!(
+				"Nobody checked in to \"{}\" in {} within {} minutes of its beginning, the rest of the slot was released",
+				after.name,
+				notify::room_names(after.rooms),
+				no_show_after().num_minutes(),
+			),
+		)
+		.ok_or(Error::Database)?;
+	}
+
+	waitlist::release(
+		&mut Database::<Waitlist>::open(store).ok_or(Error::Database)?,
+		&reservations,
+		&Database::<Blackouts>::open(store).ok_or(Error::Database)?,
+		&Database::<Holds>::open(store).ok_or(Error::Database)?,
+		&mut notifications,
+	)
+}
+
+/// odbaví (check-in) danou rezervaci
+///
+/// POST /events/<id>/checkin?<code>
+///
+/// parametry:
+/// - `id`: identifikátor dané rezervace
+/// - `code`: kód místnosti pro odbavení bez přihlášení, viz [`crate::models::Room`]
+#[post("/events/<id>/checkin?<code>")]
+pub fn checkin(
+	id: u64,
+	code: Option<String>,
+	db: Database<Reservations>,
+	rooms: Database<Rooms>,
+	teams: Database<Teams>,
+	delegations: Database<Delegations>,
+	usr: Option<AuthToken<Noob>>,
+) -> Result<(), Error> {
+	let (event, _) = booking::live(&db, id)?;
+
+	let actor = match (usr, code) {
+		(Some(u), _) if booking::can_modify(&event, &u, &teams, &delegations)? => u.user.email.clone(),
+		(_, Some(c)) if code_matches(&rooms, &event, &c)? => CODE_ACTOR.to_string(),
+		_ => return Err(Error::Forbidden),
+	};
+
+	check_in(&db, id, &actor, Utc::now()).map(|_| ())
+}
+
+/// odbaví probíhající (nebo nejbližší) rezervaci místnosti, sem vede QR kód u místnosti
+///
+/// GET /rooms/<room>/checkin?<code>
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+/// - `code`: kód místnosti pro odbavení, viz [`crate::models::Room`]
+#[get("/rooms/<room>/checkin?<code>")]
+pub fn room_checkin(
+	room: u8,
+	code: String,
+	db: Database<Reservations>,
+	rooms: Database<Rooms>,
+) -> Result<Html<String>, Error> {
+	let registered = rooms.read().get(room)?.ok_or(Error::NotFound)?;
+	if registered.checkin_code.as_deref() != Some(code.as_str()) {
+		return Err(Error::NotFound);
+	}
+
+	let now = Utc::now();
+	let mut current = db
+		.read()
+		.find("approved", &true)?
+		.filter(|(_, r)| r.rooms & room != 0 && window_open(r, now))
+		.collect::<Vec<(u64, Reservation)>>();
+	current.sort_by_key(|(_, r)| r.begin_time);
+
+	let body = match current.into_iter().next() {
+		Some((id, _)) => {
+			let event = check_in(&db, id, CODE_ACTOR, now)?;
+
+			format!("<p>Checked in to <b>{}</b>.</p>", escape(&event.name))
+		}
+		None => "<p>There is no reservation to check in to right now.</p>".to_string(),
+	};
+
+	Ok(page(&room::name(&rooms, room)?, body))
+}
+
+/// statistika docházky přihlášeného uživatele
+///
+/// GET /attendance "application/json"
+#[get("/attendance", format = "application/json")]
+pub fn mine(db: Database<Reservations>, usr: AuthToken<Noob>) -> Result<Json<Attendance>, Error> {
+	Ok(Json(attendance(&db, &usr.user.email)?))
+}
+
+/// statistika docházky daného uživatele
+///
+/// GET /attendance/<email> "application/json"
+#[get("/attendance/<email>", format = "application/json")]
+pub fn of_user(email: String, db: Database<Reservations>, usr: AuthToken<Noob>) -> Option<Json<Attendance>> {
+	if usr.user.email != email && !usr.is::<Approver>() {
+		return None;
+	}
+
+	Some(Json(attendance(&db, &email).ok()?))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![checkin, mine, of_user]
+}
+
+/// vrací stránky renderované na serveru, mountují se mimo `/api/`
+pub fn pages() -> Vec<Route> {
+	routes![room_checkin]
+}
@@ -1,96 +1,886 @@
-//! obsahuje modely
-//! pro přidávání nového modelu viz [dokumentace Diesel ORM](https://diesel.rs)
+//! obsahuje přístup k databázi
+//!
+//! data jsou uložena v úložišti (viz [`crate::store`]), na disku v [sled](https://sled.rs)
+//! nebo v paměti, každá tabulka je strom (viz [`Table`] a modul [`table`]),
+//! klíče i hodnoty jsou serializované do CBORu. při změně modelu, kterou staré záznamy nepřežijí, je potřeba
+//! přidat migraci, viz [`crate::migrate`]
 
 use rocket::request::{FromRequest, Request, Outcome};
 use rocket::http::Status;
+use rocket::State;
 
 use serde::{Serialize, Deserialize};
-use sled::{Db, Tree};
+use sha2::{Digest, Sha256};
 use serde_cbor;
 
-use std::env;
+use chrono::{DateTime, offset::Utc};
+
+use std::cell::RefCell;
+use std::fmt;
 use std::ops::Drop;
-use std::sync::RwLock;
+use std::sync::Arc;
 use std::borrow::Borrow;
 use std::iter::Iterator;
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use crate::store::{Event, RawTree, Record, Store, TxResult, TxView};
+
+/// version tag of a stored value, a hash of its encoded form
+///
+/// used as the `ETag` of records, see [`TreeMan::get_tagged`]
+pub fn tag(raw: &[u8]) -> String {
+	Sha256::digest(raw).iter().take(16).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// errors of the storage layer
+#[derive(Debug)]
+pub enum Error {
+	/// the storage backend failed
+	Io(Box<dyn std::error::Error + Send + Sync>),
+	/// a key or a value couldn't be encoded
+	Serialization(serde_cbor::Error),
+	/// a stored record couldn't be decoded
+	Corrupt {
+		/// name of the tree
+		tree:  String,
+		/// the raw key of the record
+		key:   Vec<u8>,
+		/// why it couldn't be decoded
+		error: serde_cbor::Error,
+	},
+	/// the record doesn't exist
+	NotFound,
+	/// the tree has no index of the name, see [`Table::indexes`]
+	UnknownIndex {
+		/// name of the tree
+		tree:  String,
+		/// name of the index
+		index: String,
+	},
+	/// the record has been changed in the meantime
+	Conflict,
+	/// the database has been migrated by a newer version of the backend
+	Schema {
+		/// version of the database
+		found:     u32,
+		/// the latest version known to this build
+		supported: u32,
+	},
+	/// the database hasn't been migrated to the version required by this build yet
+	Outdated {
+		/// version of the database
+		found:    u32,
+		/// the latest version known to this build
+		required: u32,
+	},
+}
+
+/// result of a storage operation
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "database error: {}", e),
+			Error::Serialization(e) => write!(f, "failed to encode a record: {}", e),
+			Error::Corrupt { tree, key, error } => write!(f, "corrupt record in tree {} under key {:?}: {}", tree, key, error),
+			Error::NotFound => write!(f, "record not found"),
+			Error::UnknownIndex { tree, index } => write!(f, "tree {} has no index named {}", tree, index),
+			Error::Conflict => write!(f, "record changed concurrently"),
+			Error::Schema { found, supported } => write!(f, "database schema version {} is newer than the supported version {}", found, supported),
+			Error::Outdated { found, required } => write!(f, "database schema is at version {}, version {} is required", found, required),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<sled::Error> for Error {
+	fn from(e: sled::Error) -> Self {
+		Error::Io(Box::new(e))
+	}
+}
+
+impl From<serde_cbor::Error> for Error {
+	fn from(e: serde_cbor::Error) -> Self {
+		Error::Serialization(e)
+	}
+}
+
+/// encodes a key or a value
+fn encode<T: Serialize>(t: &T) -> Result<Vec<u8>> {
+	Ok(serde_cbor::to_vec(t)?)
+}
+
+/// decodes a record of the named tree
+pub(crate) fn decode<T: for<'a> Deserialize<'a>>(tree: &str, key: &[u8], raw: &[u8]) -> Result<T> {
+	serde_cbor::from_slice(raw).map_err(|error| Error::Corrupt { tree: tree.to_string(), key: key.to_vec(), error })
+}
+
+/// logs a corrupt record skipped while iterating
+fn skip<T>(result: Result<T>) -> Option<T> {
+	result.map_err(|e| log::error!("db: skipping record: {}", e)).ok()
+}
+
+/// copies all trees of `from` to `to`, returns the number of copied records
+///
+/// ids generated by `to` afterwards don't clash with the copied keys, see [`Database::get_key`]
+pub fn copy(from: &Store, to: &Store) -> Result<usize> {
+	let mut count = 0;
+
+	for name in from.tree_names() {
+		let (source, target) = (from.open_tree(&name)?, to.open_tree(&name)?);
+
+		for record in source.iter() {
+			let (k, v) = record?;
+			target.insert(&k, &v)?;
+			count += 1;
+		}
+	}
+
+	to.reserve_ids(from.generate_id()?)?;
+	to.flush()?;
+	Ok(count)
+}
+
+/// order-preserving encoding of indexed values, see [`Index`]
+///
+/// encoded values compare the same way as the values themselves and none
+/// is a prefix of another, so more data can follow them
+pub trait Ordered {
+	/// appends the encoded value
+	fn encode_ordered(&self, out: &mut Vec<u8>);
+
+	/// appends the common prefix of the encodings of all values starting with this one
+	///
+	/// only differs from the encoding for strings, see [`TreeMan::find_prefix`]
+	fn encode_prefix(&self, out: &mut Vec<u8>) {
+		self.encode_ordered(out)
+	}
+}
+
+/// the order-preserving encoding of a value, see [`Ordered`]
+pub fn ordered<O: Ordered + ?Sized>(value: &O) -> Vec<u8> {
+	let mut out = vec![];
+	value.encode_ordered(&mut out);
+	out
+}
+
+macro_rules! ordered_uint {
+	($($t:ty),*) => {$(
+		impl Ordered for $t {
+			fn encode_ordered(&self, out: &mut Vec<u8>) {
+				out.extend_from_slice(&self.to_be_bytes());
+			}
+		}
+	)*};
+}
+
+ordered_uint!(u8, u16, u32, u64);
+
+impl Ordered for i64 {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		(*self as u64 ^ 1 << 63).encode_ordered(out)
+	}
+}
+
+impl Ordered for bool {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		out.push(*self as u8)
+	}
+}
+
+/// zero bytes are escaped as `0x00 0xff`, the string ends with `0x00 0x00`
+impl Ordered for str {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		self.encode_prefix(out);
+		out.extend_from_slice(&[0, 0]);
+	}
+
+	fn encode_prefix(&self, out: &mut Vec<u8>) {
+		for &b in self.as_bytes() {
+			match b {
+				0 => out.extend_from_slice(&[0, 0xff]),
+				b => out.push(b),
+			}
+		}
+	}
+}
+
+impl Ordered for String {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		self.as_str().encode_ordered(out)
+	}
+
+	fn encode_prefix(&self, out: &mut Vec<u8>) {
+		self.as_str().encode_prefix(out)
+	}
+}
+
+impl Ordered for DateTime<Utc> {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		self.timestamp().encode_ordered(out);
+		self.timestamp_subsec_nanos().encode_ordered(out);
+	}
+}
+
+impl<T: Ordered> Ordered for Option<T> {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		match self {
+			None => out.push(0),
+			Some(t) => {
+				out.push(1);
+				t.encode_ordered(out);
+			}
+		}
+	}
+}
+
+impl<A: Ordered, B: Ordered> Ordered for (A, B) {
+	fn encode_ordered(&self, out: &mut Vec<u8>) {
+		self.0.encode_ordered(out);
+		self.1.encode_ordered(out);
+	}
+
+	fn encode_prefix(&self, out: &mut Vec<u8>) {
+		self.0.encode_ordered(out);
+		self.1.encode_prefix(out);
+	}
+}
+
+/// the smallest key greater than all keys starting with `prefix`
+///
+/// empty if there's none, i.e. the prefix is only `0xff` bytes. index keys start
+/// with the index name, which contains zero bytes, so they always have one
+fn successor(mut prefix: Vec<u8>) -> Vec<u8> {
+	while prefix.last() == Some(&0xff) {
+		prefix.pop();
+	}
+
+	if let Some(last) = prefix.last_mut() {
+		*last += 1;
+	}
+
+	prefix
+}
+
+/// decoding of the order-preserving encoding, see [`Ordered`]
+pub trait FromOrdered: Ordered + Sized {
+	/// decodes a value from the beginning of `input` and advances it,
+	/// `None` if it isn't a valid encoding
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self>;
+}
+
+/// splits off the first `n` bytes of the input
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+	if input.len() < n {
+		return None;
+	}
+
+	let (head, tail) = input.split_at(n);
+	*input = tail;
+	Some(head)
+}
+
+macro_rules! from_ordered_uint {
+	($($t:ty),*) => {$(
+		impl FromOrdered for $t {
+			fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+				let mut bytes = [0; std::mem::size_of::<$t>()];
+				bytes.copy_from_slice(take(input, std::mem::size_of::<$t>())?);
+				Some(<$t>::from_be_bytes(bytes))
+			}
+		}
+	)*};
+}
+
+from_ordered_uint!(u8, u16, u32, u64);
+
+impl FromOrdered for i64 {
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+		Some((u64::decode_ordered(input)? ^ 1 << 63) as i64)
+	}
+}
+
+impl FromOrdered for bool {
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+		match take(input, 1)? {
+			[0] => Some(false),
+			[1] => Some(true),
+			_ => None,
+		}
+	}
+}
+
+impl FromOrdered for String {
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+		let mut bytes = vec![];
+
+		loop {
+			match take(input, 1)? {
+				[0] => match take(input, 1)? {
+					[0] => return String::from_utf8(bytes).ok(),
+					[0xff] => bytes.push(0),
+					_ => return None,
+				},
+				[b] => bytes.push(*b),
+				_ => return None,
+			}
+		}
+	}
+}
+
+impl FromOrdered for DateTime<Utc> {
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+		let (secs, nanos) = (i64::decode_ordered(input)?, u32::decode_ordered(input)?);
+		Some(DateTime::from_utc(chrono::NaiveDateTime::from_timestamp_opt(secs, nanos)?, Utc))
+	}
+}
+
+impl<T: FromOrdered> FromOrdered for Option<T> {
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+		match take(input, 1)? {
+			[0] => Some(None),
+			[1] => Some(Some(T::decode_ordered(input)?)),
+			_ => None,
+		}
+	}
+}
 
-lazy_static! {
-	/// a global handle to the Sled database
-	pub static ref DB: RwLock<Db> = RwLock::new({
-		Db::open(&env::var("DATABASE_URL").expect("failed to read DATABASE_URL environment variable"))
-			.expect("failed to open database")
-	});
+impl<A: FromOrdered, B: FromOrdered> FromOrdered for (A, B) {
+	fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+		Some((A::decode_ordered(input)?, B::decode_ordered(input)?))
+	}
 }
 
+/// encoding of the keys of a table, see [`Table::Codec`]
+pub trait KeyCodec<K> {
+	/// encodes a key
+	fn encode_key(key: &K) -> Result<Vec<u8>>;
+	/// decodes a key
+	fn decode_key(raw: &[u8]) -> std::result::Result<K, serde_cbor::Error>;
+}
+
+/// keys encoded as CBOR, the byte order of the keys doesn't match their order
+pub struct Cbor;
+
+impl<K: Serialize + for<'a> Deserialize<'a>> KeyCodec<K> for Cbor {
+	fn encode_key(key: &K) -> Result<Vec<u8>> {
+		encode(key)
+	}
+
+	fn decode_key(raw: &[u8]) -> std::result::Result<K, serde_cbor::Error> {
+		serde_cbor::from_slice(raw)
+	}
+}
+
+/// keys encoded by [`Ordered`], stored in the order of the keys,
+/// which allows [`TreeMan::range`] and the like
+pub struct OrderedKeys;
+
+impl<K: FromOrdered> KeyCodec<K> for OrderedKeys {
+	fn encode_key(key: &K) -> Result<Vec<u8>> {
+		Ok(ordered(key))
+	}
+
+	fn decode_key(mut raw: &[u8]) -> std::result::Result<K, serde_cbor::Error> {
+		K::decode_ordered(&mut raw)
+			.filter(|_| raw.is_empty())
+			.ok_or_else(|| serde::de::Error::custom("invalid ordered key"))
+	}
+}
+
+/// decodes a key of a record of the tree
+fn decode_key<K, C: KeyCodec<K>>(tree: &str, key: &[u8]) -> Result<K> {
+	C::decode_key(key).map_err(|error| Error::Corrupt { tree: tree.to_string(), key: key.to_vec(), error })
+}
+
+/// a secondary index of a table, see [`Table::indexes`]
+///
+/// entries of all indexes of a table are kept in a single tree next to the table,
+/// keyed by the index name, the indexed value and the key of the record
+pub struct Index<V> {
+	/// name of the index
+	pub name:    &'static str,
+	/// the indexed values of a record, encoded by [`ordered`]
+	///
+	/// a record may be listed under several values or none
+	pub extract: fn(&V) -> Vec<Vec<u8>>,
+}
+
+impl<V> Clone for Index<V> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<V> Copy for Index<V> {}
+
+/// keys of the index entries of a record
+fn entries<V>(indexes: &[Index<V>], key: &[u8], value: &V) -> Vec<Vec<u8>> {
+	indexes
+		.iter()
+		.flat_map(|index| (index.extract)(value).into_iter().map(move |v| [ordered(index.name), v, key.to_vec()].concat()))
+		.collect()
+}
+
+/// replaces the index entries of the old version of a record with those of the new one
+fn reindex<V>(index: &dyn TxView, indexes: &[Index<V>], key: &[u8], old: Option<&V>, new: Option<&V>) -> TxResult<()> {
+	let old = old.map(|v| entries(indexes, key, v)).unwrap_or_default();
+	let new = new.map(|v| entries(indexes, key, v)).unwrap_or_default();
+
+	for entry in old.iter().filter(|e| !new.contains(e)) {
+		index.remove(entry)?;
+	}
+
+	for entry in new.iter().filter(|e| !old.contains(e)) {
+		index.insert(entry, key)?;
+	}
+
+	Ok(())
+}
+
+/// the tree of the index entries of a table and its indexes
+type Indexes<V> = (Arc<dyn RawTree>, Vec<Index<V>>);
+
+/// raw values of a record before and after a write
+type Written = (Option<Vec<u8>>, Option<Vec<u8>>);
+
 /// manages a tree and ensures it's type safety
 /// also allows automatic type conversions
-pub struct TreeMan<K, V>
+///
+/// keys are encoded by the codec `C`, see [`KeyCodec`]
+pub struct TreeMan<K, V, C = Cbor>
 where
 	for<'a> K: Serialize + Deserialize<'a>,
 	for<'b> V: Serialize + Deserialize<'b>,
+	C: KeyCodec<K>,
 {
-	tree: Tree,
-	_k:   PhantomData<K>,
-	_v:   PhantomData<V>,
+	store: Store,
+	tree:  Arc<dyn RawTree>,
+	index: Option<Indexes<V>>,
+	_k:    PhantomData<K>,
+	_v:    PhantomData<V>,
+	_c:    PhantomData<C>,
+}
+
+/// name of the tree of the index entries of the table `name`, see [`Table::indexes`]
+fn index_name(name: &str) -> String {
+	format!("{}.index", name)
 }
 
-impl<K, V> TreeMan<K, V>
+impl<K, V, C> TreeMan<K, V, C>
 where
 	for<'a> K: Serialize + Deserialize<'a>,
 	for<'b> V: Serialize + Deserialize<'b>,
+	C: KeyCodec<K>,
 {
-	/// create a new tree manager from a tree
-	pub fn from_tree(tree: Tree) -> Self {
-		Self { tree, _k: PhantomData, _v: PhantomData }
+	/// create a new tree manager of the named tree of the store
+	pub fn open(store: &Store, name: &str) -> Result<Self> {
+		Ok(Self { store: store.clone(), tree: store.open_tree(name)?, index: None, _k: PhantomData, _v: PhantomData, _c: PhantomData })
+	}
+
+	/// create a new tree manager maintaining the given indexes in the tree `{name}.index`
+	pub fn open_indexed(store: &Store, name: &str, indexes: Vec<Index<V>>) -> Result<Self> {
+		let index = Some((store.open_tree(&index_name(name))?, indexes));
+		Ok(Self { store: store.clone(), tree: store.open_tree(name)?, index, _k: PhantomData, _v: PhantomData, _c: PhantomData })
+	}
+
+	/// creates an iterator over (K, V), failing on records which can't be read
+	pub fn try_iter(&self) -> impl Iterator<Item = Result<(K, V)>> {
+		let tree = self.tree.clone();
+
+		self.tree.iter().map(move |res| {
+			let (k, v) = res?;
+			Ok((decode_key::<K, C>(tree.name(), &k)?, decode(tree.name(), &k, &v)?))
+		})
 	}
 
 	/// creates an iterator over (K, V)
+	///
+	/// records which can't be read are logged and skipped, see [`TreeMan::try_iter`]
 	pub fn iter(&self) -> impl Iterator<Item = (K, V)> {
-		self.tree.iter().filter_map(|res| {
-			if let Ok((k, v)) = res {
-				Some((serde_cbor::from_slice::<K>(&k).ok()?, serde_cbor::from_slice::<V>(&v).ok()?))
-			} else {
-				None
+		self.try_iter().filter_map(skip)
+	}
+
+	/// records listed in the index under `value`, see [`Table::indexes`]
+	///
+	/// an [`Error::UnknownIndex`] if there's no such index
+	pub fn find<O: Ordered + ?Sized>(&self, index: &str, value: &O) -> Result<impl Iterator<Item = (K, V)>> {
+		let prefix = [ordered(index), ordered(value)].concat();
+		self.scan(index, (Bound::Included(prefix.clone()), Bound::Excluded(successor(prefix))))
+	}
+
+	/// records listed in the index under values starting with `prefix`, e.g. emails of a domain
+	pub fn find_prefix<O: Ordered + ?Sized>(&self, index: &str, prefix: &O) -> Result<impl Iterator<Item = (K, V)>> {
+		let mut start = ordered(index);
+		prefix.encode_prefix(&mut start);
+
+		self.scan(index, (Bound::Included(start.clone()), Bound::Excluded(successor(start))))
+	}
+
+	/// records listed in the index under values in `range`, ordered by the value
+	pub fn find_range<O: Ordered, R: RangeBounds<O>>(&self, index: &str, range: R) -> Result<impl Iterator<Item = (K, V)>> {
+		let name = ordered(index);
+		let with = |v: &O| [name.clone(), ordered(v)].concat();
+
+		let start = match range.start_bound() {
+			Bound::Included(v) => Bound::Included(with(v)),
+			Bound::Excluded(v) => Bound::Included(successor(with(v))),
+			Bound::Unbounded => Bound::Included(name.clone()),
+		};
+		let end = match range.end_bound() {
+			Bound::Included(v) => Bound::Excluded(successor(with(v))),
+			Bound::Excluded(v) => Bound::Excluded(with(v)),
+			Bound::Unbounded => Bound::Excluded(successor(name.clone())),
+		};
+
+		self.scan(index, (start, end))
+	}
+
+	/// records of the index entries in the range
+	///
+	/// entries left behind (e.g. by an unreadable old version of a record) are skipped
+	fn scan(&self, index: &str, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Result<impl Iterator<Item = (K, V)>> {
+		let unknown = || Error::UnknownIndex { tree: self.tree.name().to_string(), index: index.to_string() };
+		let (tree, indexes) = self.index.as_ref().ok_or_else(unknown)?;
+		let definition = *indexes.iter().find(|i| i.name == index).ok_or_else(unknown)?;
+		let records = self.tree.clone();
+
+		Ok(tree.range(range).filter_map(move |entry| {
+			let (entry, key) = skip(entry)?;
+			let raw = skip(records.get(&key))??;
+			let value = skip(decode(records.name(), &key, &raw))?;
+
+			if !entries(&[definition], &key, &value).iter().any(|e| e[..] == entry[..]) {
+				return None;
 			}
-		})
+
+			Some((skip(decode_key::<K, C>(records.name(), &key))?, value))
+		}))
+	}
+
+	/// recomputes the index from the records, returns the number of indexed records
+	///
+	/// needed when an index is added or changed, see [`rebuild`]
+	pub fn rebuild_indexes(&mut self) -> Result<usize> {
+		let (index, indexes) = match &self.index {
+			Some(index) => index,
+			None => return Ok(0),
+		};
+		let mut count = 0;
+
+		index.clear()?;
+
+		for record in self.tree.iter() {
+			let (key, raw) = record?;
+
+			if let Some(value) = skip(decode::<V>(self.tree.name(), &key, &raw)) {
+				for entry in entries(indexes, &key, &value) {
+					index.insert(&entry, &key)?;
+				}
+
+				count += 1;
+			}
+		}
+
+		Ok(count)
 	}
 
 	/// try to get a value from the database
-	pub fn get<Key: Borrow<K>>(&self, k: Key) -> Option<V> {
-		self.tree
-			.get(serde_cbor::to_vec(k.borrow()).unwrap()) // can't fail
-			.ok()
-			.flatten()
-			.map(|v| serde_cbor::from_slice::<V>(&*v).ok())
-			.flatten()
+	pub fn get<Key: Borrow<K>>(&self, k: Key) -> Result<Option<V>> {
+		Ok(self.get_tagged(k)?.map(|(v, _)| v))
+	}
+
+	/// try to get a value together with its version tag, see [`tag`]
+	pub fn get_tagged<Key: Borrow<K>>(&self, k: Key) -> Result<Option<(V, String)>> {
+		let key = C::encode_key(k.borrow())?;
+
+		match self.tree.get(&key)? {
+			Some(raw) => Ok(Some((decode(self.tree.name(), &key, &raw)?, tag(&raw)))),
+			None => Ok(None),
+		}
+	}
+
+	/// writes the raw value chosen by `fun` from the current one (`None` removes it)
+	/// together with its index entries, returns the previous and the new raw value
+	fn write_indexed<F>(&self, index: &dyn RawTree, indexes: &[Index<V>], key: &[u8], fun: F) -> Result<Written>
+	where
+		F: Fn(Option<&[u8]>) -> Result<Option<Vec<u8>>>,
+	{
+		let name = self.tree.name();
+		let written = RefCell::new((None, None));
+
+		let committed = self.store.transaction(&[name, index.name()], &|trees| {
+			let current = trees[0].get(key)?;
+			let new = fun(current.as_deref())?;
+
+			match &new {
+				Some(new) => trees[0].insert(key, new)?,
+				None => trees[0].remove(key)?,
+			};
+
+			// an unreadable old version leaves its entries behind, they are skipped by queries
+			let old = current.as_ref().and_then(|raw| skip(decode::<V>(name, key, raw)));
+			let value = new.as_ref().and_then(|raw| skip(decode::<V>(name, key, raw)));
+			reindex(trees[1], indexes, key, old.as_ref(), value.as_ref())?;
+
+			*written.borrow_mut() = (current, new);
+			Ok(())
+		})?;
+
+		if !committed {
+			return Err(Error::Conflict);
+		}
+
+		Ok(written.into_inner())
 	}
 
-	/// try to insert into database
-	pub fn insert<Key: Borrow<K>, Value: Borrow<V>>(&mut self, k: Key, v: Value) -> sled::Result<Option<sled::IVec>> {
-		let tmp = self.tree.insert(serde_cbor::to_vec(k.borrow()).unwrap(), serde_cbor::to_vec(v.borrow()).unwrap());
-		println!("{:?}", tmp);
-		tmp
-		// can't fail
+	/// replaces (or with `None` removes) a value only if its version tag is still `expected`
+	///
+	/// the check and the write are a single compare-and-swap, so a concurrent
+	/// change in between is detected as well, both are an [`Error::Conflict`]
+	pub fn swap_tagged<Key: Borrow<K>>(&mut self, k: Key, expected: &str, new: Option<&V>) -> Result<()> {
+		let key = C::encode_key(k.borrow())?;
+		let new = new.map(encode).transpose()?;
+
+		if let Some((index, indexes)) = &self.index {
+			self.write_indexed(&**index, indexes, &key, |current| match current {
+				Some(raw) if tag(raw) == expected => Ok(new.clone()),
+				Some(_) => Err(Error::Conflict),
+				None => Err(Error::NotFound),
+			})?;
+
+			return Ok(());
+		}
+
+		let current = match self.tree.get(&key)? {
+			Some(raw) if tag(&raw) == expected => raw,
+			Some(_) => return Err(Error::Conflict),
+			None => return Err(Error::NotFound),
+		};
+
+		if !self.tree.compare_and_swap(&key, Some(&current), new.as_deref())? {
+			return Err(Error::Conflict);
+		}
+
+		Ok(())
 	}
 
-	/// update a key
-	pub fn update<Key, Value, F>(&mut self, k: Key, fun: F) -> sled::Result<Option<sled::IVec>>
+	/// try to insert into database, returns the previous raw value
+	pub fn insert<Key: Borrow<K>, Value: Borrow<V>>(&mut self, k: Key, v: Value) -> Result<Option<Vec<u8>>> {
+		let (key, value) = (C::encode_key(k.borrow())?, encode(v.borrow())?);
+
+		match &self.index {
+			Some((index, indexes)) => Ok(self.write_indexed(&**index, indexes, &key, |_| Ok(Some(value.clone())))?.0),
+			None => self.tree.insert(&key, &value),
+		}
+	}
+
+	/// update a key, returns the new raw value
+	///
+	/// `fun` may be called again if the value changes concurrently.
+	/// a stored value which can't be read is left as it is and reported
+	pub fn update<Key, Value, F>(&mut self, k: Key, fun: F) -> Result<Option<Vec<u8>>>
 	where
 		Key: Borrow<K>,
 		Value: Borrow<V>,
 		F: Fn(Option<V>) -> Option<V>,
 	{
-		self.tree.update_and_fetch(serde_cbor::to_vec(k.borrow()).unwrap(), |value| {
-			let value = value.and_then(|val| serde_cbor::from_slice(val.borrow()).ok());
-			let res = fun(value);
-			res.and_then(|v| serde_cbor::to_vec(v.borrow()).ok())
+		let key = C::encode_key(k.borrow())?;
+		let name = self.tree.name();
+		let new = |current: Option<&[u8]>| {
+			let value = current.map(|raw| decode::<V>(name, &key, raw)).transpose()?;
+			fun(value).map(|v| encode(v.borrow())).transpose()
+		};
+
+		if let Some((index, indexes)) = &self.index {
+			return Ok(self.write_indexed(&**index, indexes, &key, new)?.1);
+		}
+
+		loop {
+			let current = self.tree.get(&key)?;
+			let value = new(current.as_deref())?;
+
+			if self.tree.compare_and_swap(&key, current.as_deref(), value.as_deref())? {
+				return Ok(value);
+			}
+		}
+	}
+
+	/// subscribes to changes of the tree, yields keys and new values (`None` when removed)
+	///
+	/// the iterator blocks until the next change, see [`RawTree::watch`].
+	/// changes which can't be read are logged and skipped
+	pub fn watch(&self) -> impl Iterator<Item = (K, Option<V>)> {
+		let tree = self.tree.clone();
+
+		self.tree.watch().filter_map(move |event| match event {
+			Event::Insert(k, v) => skip(decode_key::<K, C>(tree.name(), &k).and_then(|key| Ok((key, Some(decode(tree.name(), &k, &v)?))))),
+			Event::Remove(k) => skip(decode_key::<K, C>(tree.name(), &k).map(|key| (key, None))),
 		})
 	}
 
+	/// remove a value, returns the previous raw value
+	pub fn delete<Key: Borrow<K>>(&mut self, k: Key) -> Result<Option<Vec<u8>>> {
+		let key = C::encode_key(k.borrow())?;
+
+		match &self.index {
+			Some((index, indexes)) => Ok(self.write_indexed(&**index, indexes, &key, |_| Ok(None))?.0),
+			None => self.tree.remove(&key),
+		}
+	}
+}
+
+impl<K, V> TreeMan<K, V, OrderedKeys>
+where
+	for<'a> K: Serialize + Deserialize<'a> + FromOrdered,
+	for<'b> V: Serialize + Deserialize<'b>,
+{
+	/// records with keys in `range`, in the order of the keys
+	pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl DoubleEndedIterator<Item = (K, V)> {
+		let bound = |b: Bound<&K>| match b {
+			Bound::Included(k) => Bound::Included(ordered(k)),
+			Bound::Excluded(k) => Bound::Excluded(ordered(k)),
+			Bound::Unbounded => Bound::Unbounded,
+		};
+
+		self.records(self.tree.range((bound(range.start_bound()), bound(range.end_bound()))))
+	}
+
+	/// records with keys starting with `prefix`, in the order of the keys
+	///
+	/// e.g. all revisions of a reservation, whose keys are (reservation, revision)
+	pub fn scan_prefix<P: Ordered + ?Sized>(&self, prefix: &P) -> impl DoubleEndedIterator<Item = (K, V)> {
+		let mut raw = vec![];
+		prefix.encode_prefix(&mut raw);
+
+		let end = match successor(raw.clone()) {
+			end if end.is_empty() => Bound::Unbounded,
+			end => Bound::Excluded(end),
+		};
+
+		self.records(self.tree.range((Bound::Included(raw), end)))
+	}
+
+	/// the record with the smallest key
+	pub fn first(&self) -> Result<Option<(K, V)>> {
+		self.tree.iter().next().map(|res| self.record(res?)).transpose()
+	}
+
+	/// the record with the greatest key
+	pub fn last(&self) -> Result<Option<(K, V)>> {
+		self.tree.iter().next_back().map(|res| self.record(res?)).transpose()
+	}
+
+	/// decodes a raw record
+	fn record(&self, (k, v): Record) -> Result<(K, V)> {
+		Ok((decode_key::<K, OrderedKeys>(self.tree.name(), &k)?, decode(self.tree.name(), &k, &v)?))
+	}
+
+	/// decodes raw records, the ones which can't be read are logged and skipped
+	fn records(&self, iter: Box<dyn DoubleEndedIterator<Item = Result<Record>>>) -> impl DoubleEndedIterator<Item = (K, V)> {
+		let tree = self.tree.clone();
+
+		iter.filter_map(move |res| {
+			skip(res.and_then(|(k, v)| Ok((decode_key::<K, OrderedKeys>(tree.name(), &k)?, decode(tree.name(), &k, &v)?))))
+		})
+	}
+}
+
+/// typed access to a tree inside a transaction
+///
+/// trees of several tables are written atomically by passing their [`Database::tree`]s
+/// to [`crate::store::Backend::transaction`] and wrapping the views, e.g.
+/// `store.transaction(&[a.tree(), b.tree()], &|t| ...)`. the closure holds the trees
+/// locked, so it mustn't touch them outside of the transaction.
+/// always list the trees in the same order to avoid deadlocks
+///
+/// writes of an indexed table have to include its [`Database::index_tree`]
+/// and use [`TxTree::indexed`]
+pub struct TxTree<'a, T: Table>(&'a dyn TxView, Option<&'a dyn TxView>, PhantomData<T>);
+
+impl<'a, T: Table> TxTree<'a, T> {
+	/// wraps a view of the table's tree
+	pub fn new(tree: &'a dyn TxView) -> Self {
+		TxTree(tree, None, PhantomData)
+	}
+
+	/// wraps views of the table's tree and its index tree
+	pub fn indexed(tree: &'a dyn TxView, index: &'a dyn TxView) -> Self {
+		TxTree(tree, Some(index), PhantomData)
+	}
+
+	/// try to get a value
+	pub fn get<Key: Borrow<T::Key>>(&self, k: Key) -> TxResult<Option<T::Value>> {
+		Ok(self.get_tagged(k)?.map(|(v, _)| v))
+	}
+
+	/// try to get a value together with its version tag, see [`tag`]
+	pub fn get_tagged<Key: Borrow<T::Key>>(&self, k: Key) -> TxResult<Option<(T::Value, String)>> {
+		let key = T::Codec::encode_key(k.borrow())?;
+
+		match self.0.get(&key)? {
+			Some(raw) => Ok(Some((decode(T::name(), &key, &raw)?, tag(&raw)))),
+			None => Ok(None),
+		}
+	}
+
+	/// the current value of the key, for updating its index entries
+	///
+	/// an unreadable old version is overwritten and leaves its entries behind, they are skipped by queries
+	fn old(&self, key: &[u8]) -> TxResult<Option<T::Value>> {
+		Ok(self.0.get(key)?.and_then(|raw| skip(decode(T::name(), key, &raw))))
+	}
+
+	/// updates the index entries of the record, see [`Table::indexes`]
+	fn reindex(&self, key: &[u8], old: Option<&T::Value>, new: Option<&T::Value>) -> TxResult<()> {
+		let indexes = T::indexes();
+
+		match self.1 {
+			Some(index) => reindex(index, &indexes, key, old, new),
+			None if indexes.is_empty() => Ok(()),
+			// written without its index tree, the entries would go stale
+			None => Err(Error::UnknownIndex { tree: T::name().to_string(), index: indexes[0].name.to_string() }.into()),
+		}
+	}
+
+	/// insert or replace a value
+	pub fn insert<Key: Borrow<T::Key>, Value: Borrow<T::Value>>(&self, k: Key, v: Value) -> TxResult<()> {
+		let key = T::Codec::encode_key(k.borrow())?;
+		let old = self.old(&key)?;
+
+		self.0.insert(&key, &encode(v.borrow())?)?;
+		self.reindex(&key, old.as_ref(), Some(v.borrow()))
+	}
+
 	/// remove a value
-	pub fn delete<Key: Borrow<K>>(&mut self, k: Key) -> sled::Result<Option<sled::IVec>> {
-		self.tree.remove(serde_cbor::to_vec(k.borrow()).unwrap())
+	pub fn remove<Key: Borrow<T::Key>>(&self, k: Key) -> TxResult<()> {
+		let key = T::Codec::encode_key(k.borrow())?;
+		let old = self.old(&key)?;
+
+		self.0.remove(&key)?;
+		self.reindex(&key, old.as_ref(), None)
+	}
+}
+
+impl<'a> TxTree<'a, table::Generations> {
+	/// moves the generation of table `T` past `seen`, see [`Database::generation`]
+	///
+	/// returns `false` if somebody else has moved it in the meantime
+	pub fn advance<T: Table>(&self, seen: u64) -> TxResult<bool> {
+		if self.get(T::name().to_string())?.unwrap_or(0) != seen {
+			return Ok(false);
+		}
+
+		self.insert(T::name().to_string(), seen + 1)?;
+		Ok(true)
+	}
+
+	/// whether the generation of table `T` is still `seen`, see [`Database::generation`]
+	pub fn unchanged<T: Table>(&self, seen: u64) -> TxResult<bool> {
+		Ok(self.get(T::name().to_string())?.unwrap_or(0) == seen)
 	}
 }
 
@@ -99,78 +889,102 @@ where
 /// the reasons are two:
 /// 1. to allow  FromRequest implementation
 /// 2. declarative table access
-pub struct Database<T: Table>(TreeMan<T::Key, T::Value>, PhantomData<T>);
+pub struct Database<T: Table>(TreeMan<T::Key, T::Value, T::Codec>, PhantomData<T>);
+
+/// recomputes the indexes of the table in `store`, returns the number of indexed records
+///
+/// with `dry_run` only counts the records, see [`crate::migrate`]
+pub fn rebuild<T: Table>(store: &Store, dry_run: bool) -> Result<usize> {
+	if T::indexes().is_empty() || dry_run {
+		return Ok(store.open_tree(T::name())?.len());
+	}
+
+	TreeMan::<T::Key, T::Value, T::Codec>::open_indexed(store, T::name(), T::indexes())?.rebuild_indexes()
+}
 
 impl<T: Table> Database<T> {
 	/// read-only access to tree
-	pub fn read(&self) -> &TreeMan<T::Key, T::Value> {
+	pub fn read(&self) -> &TreeMan<T::Key, T::Value, T::Codec> {
 		&self.0
 	}
 
 	/// read and write access to tree
-	pub fn write(&mut self) -> &mut TreeMan<T::Key, T::Value> {
+	pub fn write(&mut self) -> &mut TreeMan<T::Key, T::Value, T::Codec> {
 		&mut self.0
 	}
 
-	/// procures a new random u64 key
-	pub fn get_key() -> sled::Result<u64> {
-		let lock = DB.read().expect("the rwlock has been poisoned");
-		lock.generate_id()
+	/// name of the underlying tree, for use in transactions, see [`TxTree`]
+	pub fn tree(&self) -> &str {
+		self.0.tree.name()
 	}
 
-	/// opens the databasse
-	pub fn open() -> Option<Self> {
-		if T::has_get_tree() {
-			match T::get_tree() {
-				Ok(t) => Some(Database(TreeMan::from_tree(t), PhantomData)),
-				Err(_) => None,
-			}
+	/// name of the tree of the index entries if the table has indexes, see [`TxTree::indexed`]
+	pub fn index_tree(&self) -> Option<&str> {
+		self.0.index.as_ref().map(|(tree, _)| tree.name())
+	}
+
+	/// the store the table is in
+	pub fn store(&self) -> &Store {
+		&self.0.store
+	}
+
+	/// procures a new unique u64 key
+	pub fn get_key(&self) -> Result<u64> {
+		self.0.store.generate_id()
+	}
+
+	/// opens the table in the store, failures are logged
+	pub fn open(store: &Store) -> Option<Self> {
+		let indexes = T::indexes();
+
+		let tree = if indexes.is_empty() {
+			TreeMan::open(store, T::name())
 		} else {
-			match T::get_tree_naive() {
-				Ok(t) => Some(Database(TreeMan::from_tree(t), PhantomData)),
-				Err(_) => None,
-			}
-		}
+			TreeMan::open_indexed(store, T::name(), indexes)
+		};
+
+		tree.map(|tree| Database(tree, PhantomData)).map_err(|e| log::error!("db: failed to open tree={}: {}", T::name(), e)).ok()
+	}
+}
+
+impl Database<table::Generations> {
+	/// the generation of table `T`
+	///
+	/// transactions advance it with [`TxTree::advance`], whatever was read
+	/// from the table at some generation is still valid while it stays the same
+	pub fn generation<T: Table>(&self) -> Result<u64> {
+		Ok(self.read().get(T::name().to_string())?.unwrap_or(0))
 	}
 }
 
 /// trait for the Table marker types
 pub trait Table {
-	/// opening a table might not always work,
-	/// this type should explain what's the issue
-	type TableError = sled::Error;
 	/// type of the key/ID
 	type Key: Serialize + for<'a> Deserialize<'a>;
 	/// type of the value
 	type Value: Serialize + for<'b> Deserialize<'b>;
+	/// encoding of the keys, tables iterated or queried by ranges
+	/// of keys should use [`OrderedKeys`]
+	///
+	/// changing it needs a migration of the stored keys, see [`crate::migrate`]
+	type Codec: KeyCodec<Self::Key> = Cbor;
 
 	/// name (actually prefix) of the table
 	fn name() -> &'static str;
-	/// gets the actual tree, should do it using the global DB handle
-	fn get_tree_naive() -> Result<Tree, sled::Error> {
-		let lock = DB.read().expect("the database rwlock has been poisoned");
-		// ^ this usually implies a deeper underlying problem,
-		// so it's probably okay to hard crash
-
-		lock.open_tree(&Self::name())
-	}
-
-	/// should return true if a custom get tree function is available
-	fn has_get_tree() -> bool {
-		false
-	}
 
-	/// optional custom function for fetching a tree,
-	/// can call [`Table::get_tree_naive`]
-	fn get_tree() -> Result<Tree, Self::TableError> {
-		unimplemented!()
+	/// secondary indexes of the table, maintained on every write
+	///
+	/// existing records have to be indexed by [`rebuild`] when an index is added
+	/// or changed, usually in a migration, see [`crate::migrate`]
+	fn indexes() -> Vec<Index<Self::Value>> {
+		vec![]
 	}
 }
 
 /// module containing table markers
 pub mod table {
-	use super::Table;
-	use crate::models::{Reservation, User};
+	use super::{Index, OrderedKeys, Table, ordered};
+	use crate::models::{Reservation, User, Blackout, Notification, Policy, Quota, QuotaOwner, Team, Delegation, WaitlistEntry, Hold, Room, Device, Change, Webhook, Delivery, AuditEntry, Revision};
 
 	/// Reservation database table marker
 	pub struct Reservations;
@@ -178,10 +992,27 @@ pub mod table {
 	impl Table for Reservations {
 		type Key = u64;
 		type Value = Reservation;
+		type Codec = OrderedKeys;
 
 		fn name() -> &'static str {
 			"reservation"
 		}
+
+		/// reservations in the trash are only found by the time of their deletion
+		fn indexes() -> Vec<Index<Reservation>> {
+			vec![
+				Index { name: "author", extract: |r| live(r).into_iter().map(|r| ordered(&r.author)).collect() },
+				Index { name: "team", extract: |r| live(r).and_then(|r| r.team).iter().map(ordered).collect() },
+				Index { name: "begin_time", extract: |r| live(r).into_iter().map(|r| ordered(&r.begin_time)).collect() },
+				Index { name: "approved", extract: |r| live(r).into_iter().map(|r| ordered(&r.approved)).collect() },
+				Index { name: "deleted_at", extract: |r| r.deleted_at.iter().map(ordered).collect() },
+			]
+		}
+	}
+
+	/// the reservation unless it's cancelled, see [`crate::trash`]
+	fn live(r: &Reservation) -> Option<&Reservation> {
+		Some(r).filter(|r| !r.cancelled)
 	}
 
 	/// Users database table marker
@@ -195,26 +1026,269 @@ pub mod table {
 			"user"
 		}
 	}
+
+	/// Blackouts database table marker
+	pub struct Blackouts;
+
+	impl Table for Blackouts {
+		type Key = u64;
+		type Value = Blackout;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"blackout"
+		}
+	}
+
+	/// Policies database table marker
+	///
+	/// keyed by the room bitflag, see [`crate::models::ROOMS`]
+	pub struct Policies;
+
+	impl Table for Policies {
+		type Key = u8;
+		type Value = Policy;
+
+		fn name() -> &'static str {
+			"policy"
+		}
+	}
+
+	/// Rooms database table marker
+	///
+	/// the room registry, keyed by the room bitflag, see [`crate::models::ROOMS`]
+	pub struct Rooms;
+
+	impl Table for Rooms {
+		type Key = u8;
+		type Value = Room;
+
+		fn name() -> &'static str {
+			"room"
+		}
+	}
+
+	/// Devices database table marker
+	///
+	/// kiosk devices, see [`crate::kiosk`]
+	pub struct Devices;
+
+	impl Table for Devices {
+		type Key = u64;
+		type Value = Device;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"device"
+		}
+
+		fn indexes() -> Vec<Index<Device>> {
+			vec![Index { name: "token", extract: |d| vec![ordered(&d.token)] }]
+		}
+	}
+
+	/// Teams database table marker
+	pub struct Teams;
+
+	impl Table for Teams {
+		type Key = u64;
+		type Value = Team;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"team"
+		}
+	}
+
+	/// Delegations database table marker
+	///
+	/// keyed by (principal, delegate)
+	pub struct Delegations;
+
+	impl Table for Delegations {
+		type Key = (String, String);
+		type Value = Delegation;
+
+		fn name() -> &'static str {
+			"delegation"
+		}
+	}
+
+	/// Waitlist database table marker
+	pub struct Waitlist;
+
+	impl Table for Waitlist {
+		type Key = u64;
+		type Value = WaitlistEntry;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"waitlist"
+		}
+	}
+
+	/// Holds database table marker
+	pub struct Holds;
+
+	impl Table for Holds {
+		type Key = u64;
+		type Value = Hold;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"hold"
+		}
+	}
+
+	/// Quotas database table marker
+	pub struct Quotas;
+
+	impl Table for Quotas {
+		type Key = QuotaOwner;
+		type Value = Quota;
+
+		fn name() -> &'static str {
+			"quota"
+		}
+	}
+
+	/// Changes database table marker
+	///
+	/// the live change feed of reservations, keyed by a growing sequence number, see [`crate::feed`]
+	pub struct Changes;
+
+	impl Table for Changes {
+		type Key = u64;
+		type Value = Change;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"change"
+		}
+	}
+
+	/// Webhooks database table marker
+	pub struct Webhooks;
+
+	impl Table for Webhooks {
+		type Key = u64;
+		type Value = Webhook;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"webhook"
+		}
+	}
+
+	/// Deliveries database table marker
+	///
+	/// the webhook delivery queue and log, see [`crate::webhook`]
+	pub struct Deliveries;
+
+	impl Table for Deliveries {
+		type Key = u64;
+		type Value = Delivery;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"delivery"
+		}
+	}
+
+	/// Audit database table marker
+	///
+	/// the append-only audit log, keyed by a growing sequence number, see [`crate::audit`]
+	pub struct Audit;
+
+	impl Table for Audit {
+		type Key = u64;
+		type Value = AuditEntry;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"audit"
+		}
+	}
+
+	/// Revisions database table marker
+	///
+	/// every version of every reservation, keyed by (reservation id, revision), see [`crate::history`]
+	pub struct Revisions;
+
+	impl Table for Revisions {
+		type Key = (u64, u32);
+		type Value = Revision;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"revision"
+		}
+	}
+
+	/// Generations database table marker
+	///
+	/// write counters of tables, keyed by the table name, see [`super::Database::generation`]
+	pub struct Generations;
+
+	impl Table for Generations {
+		type Key = String;
+		type Value = u64;
+
+		fn name() -> &'static str {
+			"generation"
+		}
+	}
+
+	/// Schema database table marker
+	///
+	/// metadata of the stored data, i.e. the schema version, see [`crate::migrate`]
+	pub struct Schema;
+
+	impl Table for Schema {
+		type Key = String;
+		type Value = u32;
+
+		fn name() -> &'static str {
+			"schema"
+		}
+	}
+
+	/// Notifications database table marker
+	///
+	/// serves as an outbox for the mailer
+	pub struct Notifications;
+
+	impl Table for Notifications {
+		type Key = u64;
+		type Value = Notification;
+		type Codec = OrderedKeys;
+
+		fn name() -> &'static str {
+			"notification"
+		}
+	}
 }
 
 impl<'a, 'r, T: Table> FromRequest<'a, 'r> for Database<T> {
 	type Error = &'static str;
 
-	fn from_request(_: &'a Request<'r>) -> Outcome<Self, Self::Error> {
-		if let Some(db) = Database::<T>::open() {
-			Outcome::Success(db)
-		} else {
-			Outcome::Failure((Status::InternalServerError, match T::has_get_tree() {
-				true => "failed to run custom db-loading function",
-				false => "failed to load database",
-			}))
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		let store = match request.guard::<State<Store>>() {
+			Outcome::Success(store) => store,
+			_ => return Outcome::Failure((Status::InternalServerError, "no store is managed by the server")),
+		};
+
+		match Database::<T>::open(&store) {
+			Some(db) => Outcome::Success(db),
+			None => Outcome::Failure((Status::InternalServerError, "failed to load database")),
 		}
 	}
 }
 
 impl<T: Table> Drop for Database<T> {
 	fn drop(&mut self) {
-		self.0.tree.flush();
-		println!("tree {}: {}", String::from_utf8_lossy(&self.0.tree.name()), self.0.tree.len())
+		if let Err(e) = self.0.tree.flush() {
+			log::error!("db: failed to flush tree={}: {}", T::name(), e);
+		}
 	}
 }
@@ -0,0 +1,65 @@
+//! a module containing booking delegation
+//!
+//! a user (principal) can grant another user (delegate) the right
+//! to book on their behalf, the delegate can then also edit and cancel
+//! the principal's reservations
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::offset::Utc;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Noob;
+
+use crate::db::{self, Database, table::Delegations};
+
+use crate::models::Delegation;
+use crate::response::Error;
+
+/// whether `delegate` may act on behalf of `principal`
+pub fn allows(db: &Database<Delegations>, principal: &str, delegate: &str) -> db::Result<bool> {
+	Ok(db.read().get((principal.to_string(), delegate.to_string()))?.is_some())
+}
+
+/// vrací zmocnění udělená přihlášeným uživatelem nebo jemu
+///
+/// GET /delegations "application/json"
+#[get("/delegations", format = "application/json")]
+pub fn list(db: Database<Delegations>, usr: AuthToken<Noob>) -> Result<Json<Vec<Delegation>>, Error> {
+	let all = db.read().try_iter().map(|r| r.map(|(_, d)| d)).collect::<Result<Vec<Delegation>, _>>()?;
+
+	Ok(Json(all.into_iter().filter(|d| d.principal == usr.user.email || d.delegate == usr.user.email).collect()))
+}
+
+/// zmocní uživatele k rezervacím jménem přihlášeného uživatele
+///
+/// POST /delegations/<delegate>
+///
+/// parametry:
+/// - `delegate`: email zmocněného uživatele
+#[post("/delegations/<delegate>")]
+pub fn grant(delegate: String, mut db: Database<Delegations>, usr: AuthToken<Noob>) -> Option<()> {
+	if delegate == usr.user.email {
+		return None;
+	}
+
+	let delegation = Delegation { principal: usr.user.email.clone(), delegate: delegate.clone(), granted: Utc::now() };
+
+	db.write().insert((usr.user.email, delegate), delegation).ok().map(|_| ())
+}
+
+/// odvolá zmocnění udělené přihlášeným uživatelem
+///
+/// DELETE /delegations/<delegate>
+///
+/// parametry:
+/// - `delegate`: email zmocněného uživatele
+#[delete("/delegations/<delegate>")]
+pub fn revoke(delegate: String, mut db: Database<Delegations>, usr: AuthToken<Noob>) -> Option<()> {
+	db.write().delete((usr.user.email, delegate)).ok()?.map(|_| ())
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, grant, revoke]
+}
@@ -0,0 +1,303 @@
+//! a module containing the live feed of reservation changes
+//!
+//! a background thread (see [`start`]) watches the reservation tree, classifies
+//! every change by comparing it with the previous state, appends it to the
+//! `change` tree, queues it for webhooks (see [`crate::webhook`]) and broadcasts
+//! it to the connected clients (see [`Feed`]). clients listen to the feed as
+//! Server-Sent Events (see [`stream`]) and can resume it from the last event id
+//! they've seen. changes are kept for a day, see [`purge`]
+//!
+//! every connected client occupies a Rocket worker, so `ROCKET_WORKERS`
+//! should be raised according to the number of expected listeners
+use rocket::{Route, State};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Request, Outcome};
+use rocket::response::{self, Responder, Response};
+
+use chrono::{Duration, offset::Utc};
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Noob;
+
+use crate::db::{
+	Database,
+	table::Changes,
+	table::Reservations,
+};
+
+use crate::kiosk::KioskDevice;
+use crate::webhook;
+use crate::models::{Change, ChangeKind, Reservation};
+use crate::response::Error;
+use crate::store::Store;
+
+/// for how long changes are kept for resuming
+const RETENTION_HOURS: i64 = 24;
+
+/// how often an idle stream sends a keep-alive comment
+const KEEPALIVE: time::Duration = time::Duration::from_secs(15);
+
+/// a connected client, receives the sequence numbers and the changes, see [`stream`]
+type Listener = Sender<(u64, Change)>;
+
+/// state of the feed shared by the watcher thread and the endpoints, managed by Rocket
+#[derive(Clone, Default)]
+pub struct Feed {
+	/// the streams of connected clients
+	listeners: Arc<Mutex<Vec<Listener>>>,
+}
+
+impl Feed {
+	/// number of connected clients, a disconnected one is noticed by the next change
+	pub fn listeners(&self) -> usize {
+		self.listeners.lock().expect("the mutex has been poisoned").len()
+	}
+
+	/// the changes from now on
+	fn listen(&self) -> Receiver<(u64, Change)> {
+		let (tx, rx) = mpsc::channel();
+		self.listeners.lock().expect("the mutex has been poisoned").push(tx);
+
+		rx
+	}
+
+	/// sends the change to every connected client and forgets the disconnected ones
+	fn broadcast(&self, seq: u64, change: &Change) {
+		self.listeners.lock().expect("the mutex has been poisoned").retain(|tx| tx.send((seq, change.clone())).is_ok());
+	}
+}
+
+/// classifies a change of a reservation from its previous and new state
+///
+/// for the feed, cancelled reservations don't exist (see [`crate::trash`]),
+/// so restoring one creates it and purging it is no change
+fn classify(old: Option<&Reservation>, new: Option<&Reservation>) -> Option<ChangeKind> {
+	match (old.filter(|r| !r.cancelled), new) {
+		(None, Some(new)) if new.cancelled => None,
+		(None, Some(_)) => Some(ChangeKind::Created),
+		(Some(_), Some(new)) if new.cancelled && new.rejected => Some(ChangeKind::Rejected),
+		(Some(_), Some(new)) if new.cancelled => Some(ChangeKind::Deleted),
+		(Some(old), Some(new)) if !old.approved && new.approved => Some(ChangeKind::Approved),
+		(Some(_), Some(_)) => Some(ChangeKind::Updated),
+		(Some(_), None) => Some(ChangeKind::Deleted),
+		(None, None) => None,
+	}
+}
+
+/// starts the thread turning changes of the reservation tree into the change feed
+///
+/// every change made after it returns gets to the feed
+pub fn start(store: Store, feed: Feed) {
+	// subscribe before taking the snapshot so that nothing slips through
+	let watched = Database::<Reservations>::open(&store).map(|reservations| {
+		let events = reservations.read().watch();
+		(events, reservations.read().iter().collect::<HashMap<u64, Reservation>>())
+	});
+
+	thread::Builder::new()
+		.name("feed".to_string())
+		.spawn(move || {
+			let (events, mut known, mut changes) = match (watched, Database::<Changes>::open(&store)) {
+				(Some((events, known)), Some(changes)) => (events, known, changes),
+				_ => {
+					log::error!("feed: failed to open the reservation and change tables, the change feed is stopped");
+					return;
+				}
+			};
+
+			for (id, new) in events {
+				let old = match &new {
+					Some(new) => known.insert(id, new.clone()),
+					None => known.remove(&id),
+				};
+
+				let kind = match classify(old.as_ref(), new.as_ref()) {
+					Some(k) => k,
+					None => continue,
+				};
+
+				let change = Change {
+					kind,
+					reservation_id: id,
+					rooms:          old.as_ref().map(|r| r.rooms).unwrap_or(0) | new.as_ref().map(|r| r.rooms).unwrap_or(0),
+					reservation:    new.or(old),
+					time:           Utc::now(),
+				};
+
+				// a change that can't be recorded is lost, but the feed goes on with the next ones
+				let seq = match changes.get_key().and_then(|seq| changes.write().insert(seq, &change).map(|_| seq)) {
+					Ok(seq) => seq,
+					Err(e) => {
+						log::error!("feed: failed to record a change of reservation {}: {}", id, e);
+						continue;
+					}
+				};
+
+				webhook::enqueue(&store, seq, &change);
+				feed.broadcast(seq, &change);
+			}
+
+			log::error!("feed: the reservation table is no longer watched, the change feed is stopped");
+		})
+		.expect("failed to spawn the change feed");
+}
+
+/// background job dropping changes older than a day
+pub fn purge(store: &Store) -> Result<(), Error> {
+	let cutoff = Utc::now() - Duration::hours(RETENTION_HOURS);
+	let mut db = Database::<Changes>::open(store).ok_or(Error::Database)?;
+
+	// changes are stored in order, the old ones come first
+	let old = db.read().iter().take_while(|(_, c)| c.time < cutoff).map(|(seq, _)| seq).collect::<Vec<u64>>();
+	for seq in old {
+		db.write().delete(seq)?;
+	}
+
+	Ok(())
+}
+
+/// the `Last-Event-ID` header sent by reconnecting clients
+pub struct LastEventId(pub Option<u64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId {
+	type Error = ();
+
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		Outcome::Success(LastEventId(request.headers().get_one("Last-Event-ID").and_then(|id| id.trim().parse().ok())))
+	}
+}
+
+/// a stream of Server-Sent Events
+///
+/// forces a flush after every event, see the `sse` feature of Rocket.
+/// the stream is dropped together with the connection, see [`Feed::broadcast`]
+pub struct EventStream {
+	rx:               Receiver<(u64, Change)>,
+	/// changes made before the client connected, see [`LastEventId`]
+	missed:           VecDeque<(u64, Change)>,
+	rooms:            u8,
+	with_reservation: bool,
+	last:             Option<u64>,
+	buf:              Vec<u8>,
+	pos:              usize,
+	flushed:          bool,
+}
+
+impl EventStream {
+	/// the next event for the client, a keep-alive comment if there's none for a while
+	fn next_event(&mut self) -> Option<Vec<u8>> {
+		loop {
+			let (seq, change) = match self.missed.pop_front() {
+				Some(missed) => missed,
+				None => match self.rx.recv_timeout(KEEPALIVE) {
+					Ok(change) => change,
+					Err(RecvTimeoutError::Timeout) => return Some(b": keep-alive\n\n".to_vec()),
+					Err(RecvTimeoutError::Disconnected) => return None,
+				},
+			};
+
+			// events replayed from the tree may arrive through the subscription again
+			if self.last.map(|last| seq <= last).unwrap_or(false) || change.rooms & self.rooms == 0 {
+				continue;
+			}
+			self.last = Some(seq);
+
+			if let Some(event) = format(seq, &change, self.with_reservation) {
+				return Some(event.into_bytes());
+			}
+		}
+	}
+}
+
+impl Read for EventStream {
+	fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+		if self.pos == self.buf.len() {
+			if !self.flushed {
+				self.flushed = true;
+				return Err(io::ErrorKind::WouldBlock.into());
+			}
+
+			self.buf = match self.next_event() {
+				Some(event) => event,
+				None => return Ok(0),
+			};
+			self.pos = 0;
+			self.flushed = false;
+		}
+
+		let n = out.len().min(self.buf.len() - self.pos);
+		out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+		self.pos += n;
+
+		Ok(n)
+	}
+}
+
+impl<'r> Responder<'r> for EventStream {
+	fn respond_to(self, _: &Request) -> response::Result<'r> {
+		Response::build()
+			.status(Status::Ok)
+			.header(ContentType::new("text", "event-stream"))
+			.raw_header("Cache-Control", "no-cache")
+			.chunked_body(self, 4096)
+			.ok()
+	}
+}
+
+/// formats a change as a Server-Sent Event
+fn format(seq: u64, change: &Change, with_reservation: bool) -> Option<String> {
+	let mut change = change.clone();
+	if !with_reservation {
+		change.reservation = None;
+	}
+
+	Some(format!("id: {}\nevent: {}\ndata: {}\n\n", seq, change.kind.name(), serde_json::to_string(&change).ok()?))
+}
+
+/// živý kanál změn rezervací (Server-Sent Events)
+///
+/// GET /events/feed?<room>&<last_event_id>
+///
+/// parametry:
+/// - `room`: bitflag místností, jejichž změny se posílají (výchozí všechny), zařízení kiosku
+///   dostávají jen změny své místnosti a bez údajů o rezervaci
+/// - `last_event_id`: pokračovat za touto událostí, jinak se bere hlavička `Last-Event-ID`
+#[get("/events/feed?<room>&<last_event_id>")]
+pub fn stream(
+	room: Option<u8>,
+	last_event_id: Option<u64>,
+	header: LastEventId,
+	changes: Database<Changes>,
+	feed: State<Feed>,
+	usr: Option<AuthToken<Noob>>,
+	kiosk: Option<KioskDevice>,
+) -> Option<EventStream> {
+	let (rooms, with_reservation) = match (usr, kiosk) {
+		(Some(_), _) => (room.unwrap_or(0b11), true),
+		(None, Some(k)) => (k.device.room, false),
+		(None, None) => return None,
+	};
+	let last = last_event_id.or(header.0);
+
+	// subscribe before reading the missed changes so that nothing slips through
+	let rx = feed.listen();
+	let missed = match last {
+		Some(last) => changes.read().range((Bound::Excluded(last), Bound::Unbounded)).collect::<VecDeque<(u64, Change)>>(),
+		None => VecDeque::new(),
+	};
+
+	Some(EventStream { rx, missed, rooms, with_reservation, last, buf: vec![], pos: 0, flushed: true })
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![stream]
+}
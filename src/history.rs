@@ -0,0 +1,104 @@
+//! a module containing reservation versioning
+//!
+//! every write of a reservation is stored as a new [`Revision`] with a number
+//! growing from 1, see [`crate::booking::save`]. revisions are kept even after the reservation
+//! is deleted. approvers can see what changed since the last approved revision,
+//! see [`since_approved`]
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::offset::Utc;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{Noob, Approver};
+
+use crate::db::{Database, table::Revisions};
+
+use crate::audit;
+use crate::models::{Reservation, Revision, RevisionDiff};
+use crate::response::Error;
+
+/// actor of revisions made by background jobs
+pub const SYSTEM: &str = "system";
+
+/// all revisions of the reservation, oldest first
+pub fn revisions(db: &Database<Revisions>, id: u64) -> Vec<(u32, Revision)> {
+	db.read().scan_prefix(&id).map(|((_, n), rev)| (n, rev)).collect()
+}
+
+/// the latest revision of the reservation and its number
+pub fn last(db: &Database<Revisions>, id: u64) -> Option<(u32, Revision)> {
+	db.read().scan_prefix(&id).next_back().map(|((_, n), rev)| (n, rev))
+}
+
+/// a new revision of the reservation following `previous`
+///
+/// an approved reservation keeps its approver until it's changed and approved again
+pub fn revision(reservation: &Reservation, actor: &str, previous: Option<&Revision>) -> Revision {
+	let approved_by = match previous {
+		_ if !reservation.approved => None,
+		Some(Revision { approved_by: Some(approver), .. }) => Some(approver.clone()),
+		_ => Some(actor.to_string()),
+	};
+
+	Revision { reservation: reservation.clone(), actor: actor.to_string(), approved_by, time: Utc::now() }
+}
+
+/// vrací všechny verze rezervace
+///
+/// GET /events/<id>/history "application/json"
+///
+/// parametry:
+/// - `id`: identifikátor rezervace
+#[get("/events/<id>/history", format = "application/json")]
+pub fn history(id: u64, db: Database<Revisions>, _u: AuthToken<Noob>) -> Json<Vec<(u32, Revision)>> {
+	Json(revisions(&db, id))
+}
+
+/// porovná dvě verze rezervace
+///
+/// GET /events/<id>/diff?<from>&<to> "application/json"
+///
+/// parametry:
+/// - `id`: identifikátor rezervace
+/// - `from`: starší verze
+/// - `to`: novější verze
+#[get("/events/<id>/diff?<from>&<to>", format = "application/json")]
+pub fn diff(id: u64, from: u32, to: u32, db: Database<Revisions>, _u: AuthToken<Noob>) -> Result<Json<RevisionDiff>, Error> {
+	let old = db.read().get((id, from))?.ok_or(Error::NotFound)?;
+	let new = db.read().get((id, to))?.ok_or(Error::NotFound)?;
+
+	Ok(Json(RevisionDiff {
+		from:        Some(from),
+		to,
+		approved_by: old.approved_by,
+		diff:        audit::diff(Some(&old.reservation), Some(&new.reservation)),
+	}))
+}
+
+/// co se v rezervaci změnilo od poslední schválené verze
+///
+/// GET /events/<id>/since_approved "application/json"
+///
+/// pokud rezervace nebyla nikdy schválena, porovnává se s ničím
+///
+/// parametry:
+/// - `id`: identifikátor rezervace
+#[get("/events/<id>/since_approved", format = "application/json")]
+pub fn since_approved(id: u64, db: Database<Revisions>, _u: AuthToken<Approver>) -> Option<Json<RevisionDiff>> {
+	let revisions = revisions(&db, id);
+	let (to, current) = revisions.last()?;
+	let approved = revisions.iter().rev().find(|(_, r)| r.reservation.approved);
+
+	Some(Json(RevisionDiff {
+		from:        approved.map(|(n, _)| *n),
+		to:          *to,
+		approved_by: approved.and_then(|(_, r)| r.approved_by.clone()),
+		diff:        audit::diff(approved.map(|(_, r)| &r.reservation), Some(&current.reservation)),
+	}))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![history, diff, since_approved]
+}
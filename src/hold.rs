@@ -0,0 +1,233 @@
+//! a module containing tentative holds
+//!
+//! a hold blocks a room/interval for other bookings for `HOLD_MINUTES`
+//! (a day by default), it can be converted into a reservation request
+//! by its author. expired holds don't block anything and are purged
+//! by a background job, see [`expire`]
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, Duration, offset::Utc};
+
+use std::env;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Noob;
+
+use crate::db::{
+	Database,
+	table::Holds,
+	table::Reservations,
+	table::Blackouts,
+	table::Policies,
+	table::Quotas,
+	table::Teams,
+	table::Users,
+	table::Delegations,
+	table::Generations,
+	TxTree,
+};
+
+use crate::booking::{Origin, Save};
+use crate::models::{Hold, NewHold, ConvertHold, NewReservation, Limit, QuotaViolation};
+use crate::response::Error;
+use crate::store::{Store, TxError};
+use crate::{booking, policy, quota};
+
+/// for how long a hold blocks its slot
+fn hold_duration() -> Duration {
+	Duration::minutes(env::var("HOLD_MINUTES").ok().and_then(|m| m.parse().ok()).unwrap_or(24 * 60))
+}
+
+/// vrací id aktivních blokací jiných uživatelů než `except`, které kolidují s daným intervalem
+pub fn blocking(db: &Database<Holds>, rooms: u8, begin_time: DateTime<Utc>, end_time: DateTime<Utc>, except: &str) -> Vec<u64> {
+	let now = Utc::now();
+
+	db.read()
+		.iter()
+		.filter(|(_, h)| h.expires > now && h.author != except && h.collides(rooms, begin_time, end_time))
+		.map(|(id, _)| id)
+		.collect()
+}
+
+/// number of active holds of the user
+pub fn count(db: &Database<Holds>, email: &str) -> u32 {
+	let now = Utc::now();
+
+	db.read().iter().filter(|(_, h)| h.expires > now && h.author == email).count() as u32
+}
+
+/// background job purging expired holds
+pub fn expire(store: &Store) -> Result<(), Error> {
+	let now = Utc::now();
+	let mut db = Database::<Holds>::open(store).ok_or(Error::Database)?;
+
+	let expired = db.read().iter().filter(|(_, h)| h.expires <= now).map(|(id, _)| id).collect::<Vec<u64>>();
+
+	for id in expired {
+		db.write().delete(id)?;
+	}
+
+	Ok(())
+}
+
+/// vrací všechny aktivní blokace
+///
+/// GET /holds "application/json"
+#[get("/holds", format = "application/json")]
+pub fn list(db: Database<Holds>, _u: AuthToken<Noob>) -> Result<Json<Vec<(u64, Hold)>>, Error> {
+	let now = Utc::now();
+	let all = db.read().try_iter().collect::<Result<Vec<(u64, Hold)>, _>>()?;
+
+	Ok(Json(all.into_iter().filter(|(_, h)| h.expires > now).collect()))
+}
+
+/// zablokuje termín, vrací id blokace
+///
+/// POST /holds application/json
+///
+/// data: [`NewHold`]
+#[post("/holds", data = "<input>")]
+pub fn post(
+	input: Json<NewHold>,
+	db: Database<Holds>,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	generations: Database<Generations>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<u64>, Error> {
+	policy::check(&policies, &usr.user, input.rooms, input.begin_time, input.end_time)?;
+
+	let hold = Hold {
+		rooms:      input.rooms,
+		begin_time: input.begin_time,
+		end_time:   input.end_time,
+		author:     usr.user.email.clone(),
+		expires:    Utc::now() + hold_duration(),
+	};
+	let max = quota::effective(&quotas, &usr.user)?.max_holds;
+	let id = db.get_key()?;
+
+	// like [`booking::save`], a reservation or blackout saved in the meantime makes the checks stale
+	loop {
+		let generation = generations.generation::<Holds>()?;
+		let seen = generations.generation::<Reservations>()?;
+		let closed = generations.generation::<Blackouts>()?;
+
+		if let Some(max) = max {
+			let usage = count(&db, &usr.user.email) + 1;
+
+			if usage > max {
+				return Err(Error::Quota(vec![QuotaViolation { limit: Limit::Holds, max, usage: f64::from(usage) }]));
+			}
+		}
+
+		if booking::has_conflict(hold.rooms, hold.begin_time, hold.end_time, &hold.author, &reservations, &blackouts, &db)? {
+			return Err(Error::Conflict);
+		}
+
+		let committed = db.store().transaction(&[db.tree(), generations.tree()], &|t| {
+			let g = TxTree::<Generations>::new(t[1]);
+
+			if !g.advance::<Holds>(generation)? || !g.unchanged::<Reservations>(seen)? || !g.unchanged::<Blackouts>(closed)? {
+				return Err(TxError::Abort);
+			}
+
+			TxTree::<Holds>::new(t[0]).insert(id, &hold)
+		})?;
+
+		if committed {
+			return Ok(Json(id));
+		}
+	}
+}
+
+/// převede blokaci na žádost o rezervaci, vrací id rezervace
+///
+/// POST /holds/<id>/convert application/json
+///
+/// parametry:
+/// - `id`: identifikátor blokace
+///
+/// data: [`ConvertHold`]
+#[post("/holds/<id>/convert", data = "<input>")]
+pub fn convert(
+	id: u64,
+	input: Json<ConvertHold>,
+	db: Database<Holds>,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	teams: Database<Teams>,
+	users: Database<Users>,
+	delegations: Database<Delegations>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<u64>, Error> {
+	let hold = db.read().get(id)?.filter(|h| h.expires > Utc::now()).ok_or(Error::NotFound)?;
+
+	if hold.author != usr.user.email {
+		return Err(Error::Forbidden);
+	}
+
+	let input = input.into_inner();
+	let new_res = NewReservation {
+		name:         input.name,
+		description:  input.description,
+		rooms:        hold.rooms,
+		begin_time:   hold.begin_time,
+		end_time:     hold.end_time,
+		layout:       input.layout,
+		people:       input.people,
+		team:         input.team,
+		on_behalf_of: None,
+	};
+
+	let (owner, new_res) = booking::prepare(new_res, &usr.user, &users, &delegations, &teams, &policies)?;
+	let res_id = reservations.get_key()?;
+
+	// the hold goes away with the write, so it can't be converted twice
+	let write = Save {
+		id:     res_id,
+		before: None,
+		after:  &new_res,
+		actor:  &usr.user.email,
+		action: "hold.convert",
+		from:   Some(Origin::Hold(id)),
+	};
+	booking::save(write, &reservations, || {
+		// a blackout may have been declared over the slot since it was held
+		if booking::has_conflict(hold.rooms, hold.begin_time, hold.end_time, &hold.author, &reservations, &blackouts, &db)? {
+			return Err(Error::Conflict);
+		}
+
+		quota::check(&quotas, &reservations, &owner, &new_res, None)
+	})?;
+
+	Ok(Json(res_id))
+}
+
+/// zruší blokaci
+///
+/// DELETE /holds/<id>
+///
+/// parametry:
+/// - `id`: identifikátor blokace
+#[delete("/holds/<id>")]
+pub fn delete(id: u64, mut db: Database<Holds>, usr: AuthToken<Noob>) -> Result<(), Error> {
+	let hold = db.read().get(id)?.ok_or(Error::NotFound)?;
+
+	if hold.author != usr.user.email && !usr.manages_facility() {
+		return Err(Error::Forbidden);
+	}
+
+	db.write().delete(id)?;
+	Ok(())
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, post, convert, delete]
+}
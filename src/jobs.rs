@@ -0,0 +1,56 @@
+//! a module for periodic background jobs
+//!
+//! jobs are plain threads started when Rocket launches (see [`start`]),
+//! they open the database tables they need from the store by themselves
+use std::fmt::Debug;
+use std::thread;
+use std::time::Duration;
+
+use crate::feed::Feed;
+use crate::store::Store;
+use crate::{backup, checkin, feed, hold, trash, waitlist, webhook};
+
+/// spawns a thread running `job` every `period`
+///
+/// a failed run is logged, the job runs again after the next `period`
+pub fn spawn<F, E>(name: &str, period: Duration, job: F)
+where
+	F: Fn() -> Result<(), E> + Send + 'static,
+	E: Debug,
+{
+	let label = name.to_string();
+
+	thread::Builder::new()
+		.name(name.to_string())
+		.spawn(move || loop {
+			thread::sleep(period);
+
+			if let Err(e) = job() {
+				log::error!("jobs: {} failed: {:?}", label, e);
+			}
+		})
+		.expect("failed to spawn a background job");
+}
+
+/// starts all background jobs working with the store
+pub fn start(store: Store, feed: Feed) {
+	let s = store.clone();
+	spawn("waitlist", Duration::from_secs(60), move || waitlist::expire(&s));
+	let s = store.clone();
+	spawn("holds", Duration::from_secs(60), move || hold::expire(&s));
+	let s = store.clone();
+	spawn("no-shows", Duration::from_secs(60), move || checkin::release(&s));
+	let s = store.clone();
+	spawn("webhooks", Duration::from_secs(10), move || webhook::dispatch(&s));
+	let s = store.clone();
+	spawn("feed-purge", Duration::from_secs(60 * 60), move || feed::purge(&s));
+	let s = store.clone();
+	let retention = trash::retention();
+	spawn("trash-purge", Duration::from_secs(60 * 60), move || trash::purge(&s, retention));
+	if let Some(schedule) = backup::Schedule::from_env() {
+		let s = store.clone();
+		spawn("backups", schedule.every, move || backup::scheduled(&s, &schedule));
+	}
+
+	feed::start(store, feed);
+}
@@ -0,0 +1,327 @@
+//! a module containing the kiosk mode for door tablets
+//!
+//! a kiosk device is registered by a facility manager for a single room and
+//! authenticates by its token, either in the `Authorization: Device <token>` header
+//! or in the `token` query parameter (for the server-rendered page, see [`page`]).
+//! a device can see what's going on in its room and instantly book the next 30 minutes
+use rocket::Route;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Request, Outcome};
+use rocket::response::Redirect;
+use rocket::response::content::Html;
+use rocket_contrib::json::Json;
+
+use chrono::{Duration, Timelike, offset::Utc};
+
+use std::fs::File;
+use std::io::Read;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{Noob, Role};
+
+use crate::db::{
+	self,
+	Database,
+	table::Devices,
+	table::Reservations,
+	table::Blackouts,
+	table::Holds,
+	table::Policies,
+	table::Quotas,
+	table::Rooms,
+};
+
+use crate::models::{Device, KioskEvent, KioskStatus, NewDevice, Reservation, User, ROOMS};
+use crate::response::Error;
+use crate::booking::{self, Save};
+use crate::{policy, quota, room};
+use crate::static_server::{escape, page as render};
+
+/// how long an instant booking lasts
+const INSTANT_MINUTES: i64 = 30;
+
+/// an authenticated kiosk device
+pub struct KioskDevice {
+	/// id of the device
+	pub id: u64,
+	/// the device itself
+	pub device: Device,
+}
+
+impl KioskDevice {
+	/// the pseudo-user bookings made on the device belong to
+	fn user(&self) -> User {
+		User {
+			name:  self.device.name.clone(),
+			email: format!("kiosk-{}", self.id),
+			role:  Noob::name().to_string(),
+		}
+	}
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for KioskDevice {
+	type Error = String;
+
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		let db = match request.guard::<Database<Devices>>() {
+			Outcome::Success(d) => d,
+			_ => return Outcome::Failure((Status::InternalServerError, "failed to connect to db".to_string())),
+		};
+
+		let header = request.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Device ")).map(String::from);
+		let token = match header.or_else(|| request.get_query_value::<String>("token").and_then(|t| t.ok())) {
+			Some(t) => t,
+			None => return Outcome::Failure((Status::Unauthorized, "missing device token".to_string())),
+		};
+
+		let found = match db.read().find("token", &token) {
+			Ok(mut devices) => devices.next(),
+			Err(e) => {
+				log::error!("kiosk: failed to look up a device token: {:?}", e);
+				return Outcome::Failure((Status::InternalServerError, "failed to look up the device".to_string()));
+			}
+		};
+
+		match found {
+			Some((id, device)) => Outcome::Success(KioskDevice { id, device }),
+			None => Outcome::Failure((Status::Forbidden, "unknown device token".to_string())),
+		}
+	}
+}
+
+/// generates a new random device token
+fn generate_token() -> Option<String> {
+	let mut bytes = [0u8; 24];
+	File::open("/dev/urandom").ok()?.read_exact(&mut bytes).ok()?;
+
+	Some(base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))
+}
+
+/// current and next approved reservation of the room
+pub fn status(reservations: &Database<Reservations>, rooms: &Database<Rooms>, room: u8) -> db::Result<KioskStatus> {
+	let now = Utc::now();
+
+	let mut events = reservations
+		.read()
+		.find("approved", &true)?
+		.map(|(_, r)| r)
+		.filter(|r| r.rooms & room != 0 && r.end_time > now)
+		.collect::<Vec<Reservation>>();
+	events.sort_by_key(|r| r.begin_time);
+
+	let (current, upcoming): (Vec<_>, Vec<_>) = events.into_iter().partition(|r| r.begin_time <= now);
+
+	Ok(KioskStatus {
+		room,
+		name:    room::name(rooms, room)?,
+		current: current.into_iter().next().map(KioskEvent::from),
+		next:    upcoming.into_iter().next().map(KioskEvent::from),
+	})
+}
+
+/// books the next 30 minutes of the device's room, returns id of the booking
+///
+/// the booking is approved and checked in right away, as somebody is standing at the door.
+/// room rules and quotas of the device (personal quotas of `kiosk-<id>` or of the `Noob` role) apply
+fn instant_book(
+	kiosk: &KioskDevice,
+	name: Option<String>,
+	reservations: &Database<Reservations>,
+	blackouts: &Database<Blackouts>,
+	holds: &Database<Holds>,
+	policies: &Database<Policies>,
+	quotas: &Database<Quotas>,
+) -> Result<u64, Error> {
+	let now = Utc::now();
+	let begin_time = now.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(now);
+	let end_time = begin_time + Duration::minutes(INSTANT_MINUTES);
+	let (user, rooms) = (kiosk.user(), kiosk.device.room);
+
+	policy::check(policies, &user, rooms, begin_time, end_time)?;
+
+	let reservation = Reservation {
+		name:         name.filter(|n| !n.is_empty()).unwrap_or_else(|| "Ad-hoc booking".to_string()),
+		description:  format!("booked at {}", kiosk.device.name),
+		author:       user.email.clone(),
+		requested_by: None,
+		rooms,
+		begin_time,
+		end_time,
+		layout:       0,
+		approved:     true,
+		people:       0,
+		team:         None,
+		checked_in:   Some(now),
+		no_show:      false,
+		cancelled:    false,
+		deleted_at:   None,
+		deleted_by:   None,
+		rejected:     false,
+	};
+
+	let id = reservations.get_key()?;
+
+	let write = Save { id, before: None, after: &reservation, actor: &user.email, action: "reservation.create", from: None };
+	booking::save(write, reservations, || {
+		if booking::has_conflict(rooms, begin_time, end_time, &user.email, reservations, blackouts, holds)? {
+			return Err(Error::Conflict);
+		}
+
+		quota::check(quotas, reservations, &user, &reservation, None)
+	})?;
+
+	Ok(id)
+}
+
+/// vrací zaregistrovaná zařízení
+///
+/// GET /devices "application/json"
+#[get("/devices", format = "application/json")]
+pub fn list(db: Database<Devices>, usr: AuthToken<Noob>) -> Result<Json<Vec<(u64, Device)>>, Error> {
+	if !usr.manages_facility() {
+		return Err(Error::NotFound);
+	}
+
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// zaregistruje nové zařízení, vrací jeho id a token
+///
+/// POST /devices application/json
+///
+/// data: [`NewDevice`]
+#[post("/devices", data = "<input>")]
+pub fn register(input: Json<NewDevice>, mut db: Database<Devices>, usr: AuthToken<Noob>) -> Result<Json<(u64, Device)>, Error> {
+	if !usr.manages_facility() {
+		return Err(Error::Forbidden);
+	}
+
+	if !ROOMS.contains(&input.room) {
+		return Err(Error::NotFound);
+	}
+
+	let device = Device {
+		name:    input.name.clone(),
+		room:    input.room,
+		token:   generate_token().ok_or(Error::Database)?,
+		created: Utc::now(),
+	};
+
+	let id = db.get_key()?;
+	db.write().insert(id, &device)?;
+
+	Ok(Json((id, device)))
+}
+
+/// odregistruje zařízení
+///
+/// DELETE /devices/<id>
+#[delete("/devices/<id>")]
+pub fn unregister(id: u64, mut db: Database<Devices>, usr: AuthToken<Noob>) -> Option<()> {
+	if !usr.manages_facility() {
+		return None;
+	}
+
+	db.write().delete(id).ok()?.map(|_| ())
+}
+
+/// vrací probíhající a následující rezervaci místnosti zařízení
+///
+/// GET /kiosk "application/json"
+#[get("/kiosk", format = "application/json")]
+pub fn now_next(reservations: Database<Reservations>, rooms: Database<Rooms>, kiosk: KioskDevice) -> Result<Json<KioskStatus>, Error> {
+	Ok(Json(status(&reservations, &rooms, kiosk.device.room)?))
+}
+
+/// zarezervuje místnost zařízení na následujících 30 minut, vrací id rezervace
+///
+/// POST /kiosk/book?<name>
+///
+/// parametry:
+/// - `name`: název události (nepovinný)
+#[post("/kiosk/book?<name>")]
+pub fn book(
+	name: Option<String>,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	kiosk: KioskDevice,
+) -> Result<Json<u64>, Error> {
+	instant_book(&kiosk, name, &reservations, &blackouts, &holds, &policies, &quotas).map(Json)
+}
+
+/// kompaktní stránka pro tablet u dveří místnosti
+///
+/// GET /kiosk?<token>
+#[get("/kiosk?<token>")]
+pub fn page(
+	token: String,
+	reservations: Database<Reservations>,
+	rooms: Database<Rooms>,
+	policies: Database<Policies>,
+	kiosk: KioskDevice,
+) -> Result<Html<String>, Error> {
+	let status = status(&reservations, &rooms, kiosk.device.room)?;
+	let offset = room::offset(&policies, kiosk.device.room)?;
+
+	let event = |label: &str, e: &Option<KioskEvent>| match e {
+		Some(e) => format!(
+			"<h2>{}: {}</h2><p>{} – {}</p>",
+			label,
+			escape(&e.name),
+			e.begin_time.with_timezone(&offset).format("%H:%M"),
+			e.end_time.with_timezone(&offset).format("%H:%M"),
+		),
+		None => format!("<h2>{}: free</h2>", label),
+	};
+
+	let book = match status.current {
+		Some(_) => String::new(),
+		None => format!(
+			"<form method=\"post\" action=\"/kiosk/book?token={}\"><button style=\"font-size: x-large;\">Book the next {} minutes</button></form>",
+			escape(&token),
+			INSTANT_MINUTES,
+		),
+	};
+
+	Ok(render(
+		&status.name,
+		format!(
+			"{}{}{}<script>setTimeout(function () {{ location.reload() }}, 60000)</script>",
+			event("Now", &status.current),
+			event("Next", &status.next),
+			book,
+		),
+	))
+}
+
+/// instantní rezervace z formuláře na stránce kiosku, přesměruje zpět na stránku
+///
+/// POST /kiosk/book?<token>
+#[post("/kiosk/book?<token>")]
+pub fn page_book(
+	token: String,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	kiosk: KioskDevice,
+) -> Redirect {
+	// a failed booking simply shows the current state again
+	let _ = instant_book(&kiosk, None, &reservations, &blackouts, &holds, &policies, &quotas);
+
+	Redirect::to(format!("/kiosk?token={}", token))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, register, unregister, now_next, book]
+}
+
+/// vrací stránky renderované na serveru, mountují se mimo `/api/`
+pub fn pages() -> Vec<Route> {
+	routes![page, page_book]
+}
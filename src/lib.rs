@@ -1,42 +1,40 @@
 //! ## backend rezervačního systému pro auditorium
 //! Dokumentace backendu.
-//! Jako webový framework projekt používá [Rocket](https://rocket.rs), na
-//! komunikaci s databází [Diesel](https://diesel.rs).
+//! Jako webový framework projekt používá [Rocket](https://rocket.rs), data
+//! ukládá do embedded databáze [sled](https://sled.rs), do SQLite, nebo pro testy do paměti,
+//! viz moduly [`db`] a [`store`]. Úložiště se vybírá konfigurací, viz [`Config`].
+//! Změny schématu uložených dat řeší migrace v modulu [`migrate`], které se
+//! spouští při startu serveru, zálohy a jejich obnovu modul [`backup`].
 //!
 //! Modus operandi tohoto serveru spočívá v přijímání požadavků, validaci dat
-//! a volání správného RGI, viz [`rgi!`]
+//! a zápisu do databáze, každá oblast (rezervace, blokace, kvóty, ...) má
+//! vlastní modul s endpointy.
 //!
 //! Struktura:
 //! ```bash,no_run
 //! .
 //! ├── Cargo.lock  - lockfile, nemazat! (deterministické buildy)
 //! ├── Cargo.toml  - manifest balíčku
-//! ├── diesel.toml - konfigurace Diesel.rs
 //! ├── Dockerfile  - dockerfile
 //! ├── frontend    - submodul s frontendem
+//! ├── mail.py     - odesílání notifikací
 //! ├── Makefile    - make
-//! ├── migrations  - migrace
-//! │   ├── 00000000000000_diesel_initial_setup
-//! │   │   ├── down.sql
-//! │   │   └── up.sql
-//! │   └── 2019-11-25-143159_reservations - migrace relace reservations
-//! │       ├── down.sql
-//! │       └── up.sql
-//! ├── README.md   - README
-//! ├── rgi         - obsahuje RGI, viz modul rgi
-//! │   ├── booking - booking rgi
-//! │   │   ├── booking.py
-//! │   │   ├── curltest
-//! │   │   └── mod.rs
-//! │   └── mod.rs
-//! ├── Rocket.toml  - konfigurační soubor Rocketu
-//! ├── rustfmt.toml - pravidla pro automatické formátování kódu
-//! └── src          - zdrojové soubory
-//!     ├── db.rs            - databázové modely, utility
-//!     ├── main.rs          - entrypoint programu
-//!     ├── rgi -> ../rgi/   - symlink
-//!     ├── schema.rs        - schéma databáze (vygenerováno Dieselem, neupravovat!)
-//!     └── static_server.rs - statický server
+//! ├── readme.md   - README
+//! ├── Rocket.toml - konfigurační soubor Rocketu
+//! ├── src         - zdrojové soubory
+//! │   ├── backup.rs        - zálohy a obnova databáze
+//! │   ├── bin              - pomocné programy (generátor superadminů, migrace, zálohy, kopie databáze)
+//! │   ├── bulk.rs          - hromadný export a import (CSV, JSON Lines)
+//! │   ├── db.rs            - přístup k databázi, tabulky
+//! │   ├── lib.rs           - sestavení Rocketu, viz [`init`]
+//! │   ├── main.rs          - entrypoint programu
+//! │   ├── migrate.rs       - verze schématu a migrace dat
+//! │   ├── models.rs        - datové modely
+//! │   ├── static_server.rs - statický server
+//! │   ├── store.rs         - úložiště (sled, paměť)
+//! │   ├── store/sqlite.rs  - úložiště SQLite
+//! │   └── ...              - moduly s endpointy
+//! └── tests       - integrační testy
 //! ```
 #![feature(proc_macro_hygiene, decl_macro)]
 #![feature(associated_type_defaults)]
@@ -48,9 +46,6 @@
 extern crate rocket;
 extern crate rocket_contrib;
 
-#[macro_use]
-extern crate lazy_static;
-
 extern crate serde_cbor;
 extern crate dotenv;
 extern crate chrono;
@@ -59,38 +54,132 @@ extern crate sled;
 
 use dotenv::dotenv;
 use rocket::http::Method;
+use rocket::fairing::AdHoc;
 use rocket_cors::{AllowedHeaders, AllowedOrigins};
 
+use std::env;
+
+use feed::Feed;
+use store::{Storage, Store};
+
 pub mod static_server;
+pub mod store;
 pub mod booking;
+pub mod blackout;
+pub mod checkin;
+pub mod delegation;
+pub mod feed;
+pub mod history;
+pub mod jobs;
+pub mod kiosk;
+pub mod migrate;
+pub mod hold;
 pub mod admin;
+pub mod audit;
+pub mod backup;
+pub mod bulk;
 pub mod auth;
+pub mod notify;
+pub mod policy;
+pub mod qr;
+pub mod quota;
+pub mod response;
+pub mod room;
+pub mod team;
+pub mod trash;
+pub mod waitlist;
+pub mod webhook;
 
 pub mod db;
 pub mod models;
+
+/// Konfigurace serveru, viz [`init`]
+#[derive(Debug, Clone)]
+pub struct Config {
+	/// kam se ukládají data
+	pub storage: Storage,
+}
+
+impl Config {
+	/// vrací konfiguraci z proměnných prostředí (i ze souboru `.env`)
+	///
+	/// `DATABASE_URL` je cesta k databázi sled, `sqlite:<soubor>` pro databázi SQLite,
+	/// nebo `memory` pro úložiště v paměti. chybí-li, vrací chybu
+	pub fn from_env() -> Result<Self, env::VarError> {
+		dotenv().ok();
+
+		Ok(Config {
+			storage: Storage::parse(&env::var("DATABASE_URL")?),
+		})
+	}
+
+	/// vrací konfiguraci s novým prázdným úložištěm v paměti, např. pro testy
+	pub fn memory() -> Self {
+		Config { storage: Storage::Memory }
+	}
+}
+
 /// Vrací instanci Rocketu
-pub fn init() -> rocket::Rocket {
-	dotenv().ok();
+///
+/// úložiště je ve spravovaném stavu Rocketu (`State<Store>`), background joby dostanou jeho kopii.
+/// vrací chybu, pokud nejde otevřít databázi nebo ji převést na aktuální schéma, viz [`migrate::startup`]
+pub fn init(config: Config) -> Result<rocket::Rocket, db::Error> {
 	let allowed_origins = AllowedOrigins::all();
 
 	// You can also deserialize this
 	let cors = rocket_cors::CorsOptions {
 		allowed_origins,
-		allowed_methods: vec![Method::Get, Method::Post, Method::Options, Method::Patch, Method::Delete, Method::Head]
+		allowed_methods: vec![Method::Get, Method::Post, Method::Options, Method::Put, Method::Patch, Method::Delete, Method::Head]
 			.into_iter()
 			.map(From::from)
 			.collect(),
 		allowed_headers: AllowedHeaders::all(),
+		expose_headers: vec!["ETag".to_string()].into_iter().collect(),
 		allow_credentials: true,
 		..Default::default()
 	}
 	.to_cors()
 	.unwrap();
 
-	rocket::ignite()
+	let rocket = rocket::ignite();
+	let store = Store::open(&config.storage)?;
+	migrate::startup(&store)?;
+
+	Ok(rocket
+		.manage(store)
+		.manage(Feed::default())
 		.register(catchers![static_server::not_found])
 		.mount("/", routes![static_server::index, static_server::frontend, static_server::favicon, auth::me])
+		.mount("/", room::pages())
+		.mount("/", checkin::pages())
+		.mount("/", qr::pages())
+		.mount("/", kiosk::pages())
 		.mount("/api/", booking::routes())
+		.mount("/api/", feed::routes())
+		.mount("/api/", history::routes())
+		.mount("/api/", blackout::routes())
+		.mount("/api/", policy::routes())
+		.mount("/api/", quota::routes())
+		.mount("/api/", team::routes())
+		.mount("/api/", delegation::routes())
+		.mount("/api/", waitlist::routes())
+		.mount("/api/", hold::routes())
+		.mount("/api/", trash::routes())
+		.mount("/api/", room::routes())
+		.mount("/api/", checkin::routes())
+		.mount("/api/", qr::routes())
+		.mount("/api/", kiosk::routes())
 		.mount("/admin/", admin::routes())
+		.mount("/admin/", notify::routes())
+		.mount("/admin/", webhook::routes())
+		.mount("/admin/", audit::routes())
+		.mount("/admin/", backup::routes())
+		.mount("/admin/", bulk::routes())
 		.attach(cors)
+		.attach(AdHoc::on_launch("background jobs", |rocket| {
+			jobs::start(
+				rocket.state::<Store>().expect("the store is managed").clone(),
+				rocket.state::<Feed>().expect("the feed is managed").clone(),
+			)
+		})))
 }
@@ -1,5 +1,30 @@
 extern crate backend;
 
+use backend::db;
+use backend::migrate::{self, Options};
+
+use std::process;
+
 fn main() {
-	backend::init().launch();
+	let config = backend::Config::from_env().unwrap_or_else(|e| {
+		eprintln!("failed to read DATABASE_URL environment variable: {}", e);
+		process::exit(1)
+	});
+
+	match backend::init(config) {
+		Ok(rocket) => {
+			rocket.launch();
+		}
+		// a dry run only reports the pending migrations, see migrate::startup
+		Err(db::Error::Outdated { found, .. }) if Options::from_env().dry_run => {
+			println!("dry run: database schema is at version {}, pending migrations:", found);
+			for m in migrate::MIGRATIONS.iter().filter(|m| m.version > found) {
+				println!("  version {}: {}", m.version, m.description);
+			}
+		}
+		Err(e) => {
+			eprintln!("failed to start the server: {}", e);
+			process::exit(1)
+		}
+	}
 }
@@ -0,0 +1,288 @@
+//! a module containing schema versioning and data migrations
+//!
+//! records are stored as CBOR of the structs in [`crate::models`]. a change
+//! old records can't be decoded with (a renamed field, a changed type) needs
+//! a [`Migration`] rewriting them. migrations are listed in [`MIGRATIONS`]
+//! ordered by version, the database remembers the version of the last applied
+//! one (see [`table::Schema`]) and pending migrations run at startup, see [`startup`]
+//!
+//! a migration mustn't decode records with the current models, which keep
+//! changing, but with its own copies of the old and the new shape, see [`transform`]
+use serde::{Serialize, Deserialize};
+
+use chrono::offset::Utc;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::db::{self, Table, TreeMan, table::Schema, table::Reservations, table::Devices};
+use crate::store::{Storage, Store};
+
+/// key of the schema version in [`table::Schema`]
+const VERSION: &str = "version";
+
+/// a single step of the schema
+pub struct Migration {
+	/// version of the schema after the migration
+	pub version:     u32,
+	/// what the migration does
+	pub description: &'static str,
+	/// rewrites the records, returns the number of changed records
+	///
+	/// nothing may be written when the second argument (dry run) is `true`
+	pub run:         fn(&Store, bool) -> db::Result<usize>,
+}
+
+/// all migrations, ordered by version
+pub const MIGRATIONS: &[Migration] = &[
+	Migration { version: 1, description: "initial schema, records as stored before versioning", run: initial },
+	Migration { version: 2, description: "index reservations by author, team, begin time and approval", run: index_reservations },
+	Migration { version: 3, description: "order-preserving keys of tables keyed by ids", run: ordered_keys },
+	Migration { version: 4, description: "index kiosk devices by token", run: index_devices },
+];
+
+/// the version 1 is the schema of databases created before versioning, nothing to change
+fn initial(_: &Store, _: bool) -> db::Result<usize> {
+	Ok(0)
+}
+
+/// builds the indexes of existing reservations
+///
+/// indexes are computed by the current models, so unlike other migrations it's fine
+/// to rebuild them again, e.g. after a later migration changes reservations
+fn index_reservations(store: &Store, dry_run: bool) -> db::Result<usize> {
+	db::rebuild::<Reservations>(store, dry_run)
+}
+
+/// switches tables keyed by ids and revisions from CBOR keys to [`db::OrderedKeys`]
+fn ordered_keys(store: &Store, dry_run: bool) -> db::Result<usize> {
+	let mut changed = 0;
+
+	for name in &[
+		"reservation", "blackout", "device", "team", "waitlist", "hold",
+		"change", "webhook", "delivery", "audit", "notification",
+	] {
+		changed += rekey::<u64>(store, name, dry_run)?;
+	}
+
+	changed += rekey::<(u64, u32)>(store, "revision", dry_run)?;
+
+	// index entries point at the old keys
+	db::rebuild::<Reservations>(store, dry_run)?;
+
+	Ok(changed)
+}
+
+/// builds the index of kiosk devices by their token
+fn index_devices(store: &Store, dry_run: bool) -> db::Result<usize> {
+	db::rebuild::<Devices>(store, dry_run)
+}
+
+/// version of the schema this build works with
+pub fn latest() -> u32 {
+	MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// version of the schema of the data in the store, 0 before versioning
+pub fn version(store: &Store) -> db::Result<u32> {
+	Ok(TreeMan::<String, u32>::open(store, Schema::name())?.get(VERSION.to_string())?.unwrap_or(0))
+}
+
+/// rewrites every record of the tree from `Old` to `New`, returns the number of changed records
+///
+/// `fun` returning `None` removes the record. a record which can't be decoded as `Old`
+/// stops the migration with [`db::Error::Corrupt`]
+pub fn transform<Old, New, F>(store: &Store, name: &str, dry_run: bool, fun: F) -> db::Result<usize>
+where
+	Old: for<'a> Deserialize<'a>,
+	New: Serialize,
+	F: Fn(Old) -> Option<New>,
+{
+	let tree = store.open_tree(name)?;
+	let mut changed = 0;
+
+	for record in tree.iter() {
+		let (key, raw) = record?;
+		let new = fun(db::decode(name, &key, &raw)?).map(|n| serde_cbor::to_vec(&n)).transpose()?;
+
+		if new.as_deref() == Some(&raw[..]) {
+			continue;
+		}
+
+		changed += 1;
+
+		if dry_run {
+			continue;
+		}
+
+		match new {
+			Some(new) => tree.insert(&key, &new)?,
+			None => tree.remove(&key)?,
+		};
+	}
+
+	Ok(changed)
+}
+
+/// re-encodes the keys of the tree from CBOR to [`db::Ordered`], returns the number of changed records
+///
+/// all keys are replaced by a single transaction, so the tree is never left half converted
+pub fn rekey<K>(store: &Store, name: &str, dry_run: bool) -> db::Result<usize>
+where
+	K: for<'a> Deserialize<'a> + db::Ordered,
+{
+	let mut changed = vec![];
+
+	for record in store.open_tree(name)?.iter() {
+		let (key, raw) = record?;
+		let new = db::ordered(&db::decode::<K>(name, &key, &key)?);
+
+		changed.push((key, new, raw));
+	}
+
+	if dry_run {
+		return Ok(changed.len());
+	}
+
+	let committed = store.transaction(&[name], &|t| {
+		// inserts come after all removals, so that a new key equal to some old one survives
+		for (old, _, _) in &changed {
+			t[0].remove(old)?;
+		}
+
+		for (_, new, raw) in &changed {
+			t[0].insert(new, raw)?;
+		}
+
+		Ok(())
+	})?;
+
+	if !committed {
+		return Err(db::Error::Conflict);
+	}
+
+	Ok(changed.len())
+}
+
+/// options of a migration run
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+	/// only find out what would be changed
+	pub dry_run: bool,
+	/// directory to copy the database to before migrating
+	pub backup:  Option<PathBuf>,
+}
+
+impl Options {
+	/// options given by `MIGRATE_DRY_RUN` (any value but `0`) and `MIGRATE_BACKUP_DIR`
+	pub fn from_env() -> Self {
+		Options {
+			dry_run: env::var("MIGRATE_DRY_RUN").map(|v| v != "0").unwrap_or(false),
+			backup:  env::var("MIGRATE_BACKUP_DIR").ok().map(PathBuf::from),
+		}
+	}
+}
+
+/// outcome of a migration run
+#[derive(Debug)]
+pub struct Report {
+	/// version of the database before the run
+	pub from:    u32,
+	/// version of the database after the run, same as `from` in a dry run
+	pub to:      u32,
+	/// versions of the pending migrations and the number of records they (would) change
+	pub applied: Vec<(u32, usize)>,
+	/// where the database has been copied to
+	pub backup:  Option<PathBuf>,
+}
+
+/// whether the database contains no data yet
+fn is_empty(store: &Store) -> db::Result<bool> {
+	for name in store.tree_names() {
+		if name != Schema::name() && !store.open_tree(&name)?.is_empty() {
+			return Ok(false);
+		}
+	}
+
+	Ok(true)
+}
+
+/// copies the database at `version` to a new sled database in `dir`
+fn backup(store: &Store, dir: &Path, version: u32) -> db::Result<PathBuf> {
+	let path = dir.join(format!("backup-v{}-{}", version, Utc::now().format("%Y%m%dT%H%M%SZ")));
+	let count = db::copy(store, &Store::open(&Storage::Sled(path.clone()))?)?;
+
+	log::info!("migrate: copied {} records to {}", count, path.display());
+	Ok(path)
+}
+
+/// runs the pending migrations of the store
+///
+/// a database without a version is at version 0, or at the latest version
+/// if it's empty. the version is stored after every migration, so a failed
+/// run continues with the failed migration next time
+pub fn run(store: &Store, options: &Options) -> db::Result<Report> {
+	let mut schema = TreeMan::<String, u32>::open(store, Schema::name())?;
+
+	let from = match schema.get(VERSION.to_string())? {
+		Some(version) => version,
+		None if is_empty(store)? && !options.dry_run => {
+			schema.insert(VERSION.to_string(), latest())?;
+			latest()
+		}
+		None => 0,
+	};
+
+	if from > latest() {
+		return Err(db::Error::Schema { found: from, supported: latest() });
+	}
+
+	let mut report = Report { from, to: from, applied: vec![], backup: None };
+	let pending = MIGRATIONS.iter().filter(|m| m.version > from).collect::<Vec<_>>();
+
+	if pending.is_empty() {
+		return Ok(report);
+	}
+
+	if let (Some(dir), false) = (&options.backup, options.dry_run) {
+		report.backup = Some(backup(store, dir, from)?);
+	}
+
+	for migration in pending {
+		let changed = (migration.run)(store, options.dry_run)?;
+		report.applied.push((migration.version, changed));
+
+		if options.dry_run {
+			log::info!("migrate: version={} ({}) would change {} records", migration.version, migration.description, changed);
+			continue;
+		}
+
+		schema.insert(VERSION.to_string(), migration.version)?;
+		store.flush()?;
+		report.to = migration.version;
+
+		log::info!("migrate: version={} ({}) changed {} records", migration.version, migration.description, changed);
+	}
+
+	Ok(report)
+}
+
+/// runs the pending migrations when the server starts, see [`Options::from_env`]
+///
+/// the server can't work with an outdated schema, so it fails with [`db::Error::Outdated`]
+/// if the database stays behind. in a dry run that's expected, the pending migrations
+/// are only logged and the caller should exit without starting, see [`Error::Outdated`](db::Error::Outdated)
+pub fn startup(store: &Store) -> db::Result<()> {
+	let options = Options::from_env();
+	let report = run(store, &options)?;
+
+	if report.to == latest() {
+		return Ok(());
+	}
+
+	if !options.dry_run {
+		log::error!("migrate: database stays at version {}, applied {:?}", report.to, report.applied);
+	}
+
+	Err(db::Error::Outdated { found: report.to, required: latest() })
+}
@@ -1,10 +1,14 @@
 //! contains database models and helper structs
 
 use serde::{Serialize, Deserialize};
-use chrono::{DateTime, offset::Utc};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Weekday, offset::Utc};
 
+use std::collections::HashMap;
 use std::convert::From;
 
+/// jednotlivé místnosti auditoria jako bitflagy, viz [`Reservation`]
+pub const ROOMS: [u8; 2] = [0b01, 0b10];
+
 /// Model rezervace, tak jak je uložena v databázi
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reservation {
@@ -12,8 +16,11 @@ pub struct Reservation {
 	pub name: String,
 	/// popis události
 	pub description: String,
-	/// "rezervujitel" události :^)
+	/// "rezervujitel" události :^), tedy ten, pro koho je rezervace
 	pub author: String,
+	/// kdo rezervaci vytvořil v zastoupení autora, viz [`Delegation`]
+	#[serde(default)]
+	pub requested_by: Option<String>,
 	/// místnosti, které si "rezervujitel" přeje zarezervovat
 	///
 	/// funguje na bázi bitflagů:
@@ -34,6 +41,27 @@ pub struct Reservation {
 	pub approved: bool,
 	/// počet lidí
 	pub people: u16,
+	/// tým, kterému rezervace patří, viz [`Team`]
+	#[serde(default)]
+	pub team: Option<u64>,
+	/// kdy se na rezervaci někdo odbavil (check-in)
+	#[serde(default)]
+	pub checked_in: Option<DateTime<Utc>>,
+	/// zda se na rezervaci nikdo neodbavil a zbytek termínu byl uvolněn
+	#[serde(default)]
+	pub no_show: bool,
+	/// zda byla rezervace zrušena (smazána), zrušené rezervace jsou v koši, viz [`crate::trash`]
+	#[serde(default)]
+	pub cancelled: bool,
+	/// kdy byla rezervace zrušena
+	#[serde(default)]
+	pub deleted_at: Option<DateTime<Utc>>,
+	/// kdo rezervaci zrušil (email)
+	#[serde(default)]
+	pub deleted_by: Option<String>,
+	/// zda šlo o zamítnutí žádosti schvalovatelem
+	#[serde(default)]
+	pub rejected: bool,
 }
 
 /// Model rezervace pro přidání do databáze
@@ -62,20 +90,41 @@ pub struct NewReservation {
 	pub layout: u8,
 	/// počet lidí
 	pub people: u16,
+	/// tým, kterému má rezervace patřit, viz [`Team`]
+	#[serde(default)]
+	pub team: Option<u64>,
+	/// email uživatele, v jehož zastoupení se rezervuje
+	#[serde(default)]
+	pub on_behalf_of: Option<String>,
+}
+
+impl Reservation {
+	/// zda rezervace koliduje s daným intervalem v daných místnostech
+	pub fn collides(&self, rooms: u8, begin_time: DateTime<Utc>, end_time: DateTime<Utc>) -> bool {
+		self.begin_time <= end_time && self.end_time >= begin_time && self.rooms & rooms != 0
+	}
 }
 
 impl From<NewReservation> for Reservation {
 	fn from(src: NewReservation) -> Reservation {
 		Reservation {
-			name:        src.name,
-			description: src.description,
-			author:      String::new(),
-			rooms:       src.rooms,
-			begin_time:  src.begin_time,
-			end_time:    src.end_time,
-			layout:      src.layout,
-			approved:    false,
-			people:      src.people,
+			name:         src.name,
+			description:  src.description,
+			author:       String::new(),
+			requested_by: None,
+			rooms:        src.rooms,
+			begin_time:   src.begin_time,
+			end_time:     src.end_time,
+			layout:       src.layout,
+			approved:     false,
+			people:       src.people,
+			team:         src.team,
+			checked_in:   None,
+			no_show:      false,
+			cancelled:    false,
+			deleted_at:   None,
+			deleted_by:   None,
+			rejected:     false,
 		}
 	}
 }
@@ -108,6 +157,521 @@ pub struct UpdateReservation {
 	pub people: Option<u16>,
 }
 
+/// výsledek hledání volného termínu
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Availability {
+	/// zda je termín volný
+	pub available: bool,
+	/// schválené rezervace, které s termínem kolidují
+	pub reservations: Vec<u64>,
+	/// výluky, které termín blokují
+	pub blackouts: Vec<u64>,
+	/// cizí blokace, které termín blokují
+	pub holds: Vec<u64>,
+	/// pravidla, která by rezervace porušila
+	pub violations: Vec<Violation>,
+}
+
+/// pravidlo rezervací, viz [`Policy`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+	/// konec rezervace musí být po jejím začátku (nelze udělit výjimku)
+	Interval,
+	/// rezervace musí být v otevírací době
+	OpeningHours,
+	/// minimální délka rezervace
+	MinDuration,
+	/// maximální délka rezervace
+	MaxDuration,
+	/// minimální předstih, se kterým se musí rezervovat
+	LeadTime,
+	/// jak daleko dopředu lze rezervovat
+	AdvanceHorizon,
+	/// začátek a konec rezervace musí být zarovnané na sloty
+	Granularity,
+}
+
+/// otevírací doba pro jeden den v týdnu
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpeningHours {
+	/// den v týdnu
+	pub weekday: Weekday,
+	/// otevírá se v (místní čas)
+	pub open: NaiveTime,
+	/// zavírá se v (místní čas)
+	pub close: NaiveTime,
+}
+
+/// pravidla rezervací jedné místnosti, tak jak jsou uložena v databázi
+///
+/// nenastavená pravidla se nevyhodnocují
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Policy {
+	/// otevírací doba, dny, které nejsou uvedeny, jsou zavřené;
+	/// prázdný seznam znamená bez omezení
+	#[serde(default)]
+	pub opening_hours: Vec<OpeningHours>,
+	/// posun místního času vůči UTC v minutách, pro otevírací dobu a sloty
+	#[serde(default)]
+	pub utc_offset: i32,
+	/// minimální délka rezervace v minutách
+	#[serde(default)]
+	pub min_duration: Option<i64>,
+	/// maximální délka rezervace v minutách
+	#[serde(default)]
+	pub max_duration: Option<i64>,
+	/// minimální předstih v minutách
+	#[serde(default)]
+	pub min_lead_time: Option<i64>,
+	/// jak daleko dopředu lze rezervovat, ve dnech
+	#[serde(default)]
+	pub max_advance: Option<i64>,
+	/// velikost slotu v minutách (např. 15)
+	#[serde(default)]
+	pub granularity: Option<i64>,
+	/// role, na které se dané pravidlo nevztahuje
+	#[serde(default)]
+	pub exempt: HashMap<Rule, Vec<String>>,
+}
+
+/// porušení pravidla rezervace
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Violation {
+	/// porušené pravidlo
+	pub rule: Rule,
+	/// místnost, jejíž pravidlo bylo porušeno
+	pub room: u8,
+	/// popis pro člověka
+	pub message: String,
+}
+
+/// komu patří nastavení kvóty
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum QuotaOwner {
+	/// výchozí kvóta pro všechny uživatele s danou rolí
+	Role(String),
+	/// kvóta konkrétního uživatele (email), přebíjí kvótu role
+	User(String),
+	/// kvóta týmu, vztahuje se na rezervace týmu
+	Team(u64),
+}
+
+/// kvóty rezervací, nenastavené kvóty nejsou omezené
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Quota {
+	/// maximální počet hodin rezervací v jednom týdnu
+	#[serde(default)]
+	pub max_hours_week: Option<u32>,
+	/// maximální počet hodin rezervací v jednom měsíci
+	#[serde(default)]
+	pub max_hours_month: Option<u32>,
+	/// maximální počet budoucích rezervací
+	#[serde(default)]
+	pub max_future: Option<u32>,
+	/// maximální počet budoucích neschválených rezervací
+	#[serde(default)]
+	pub max_pending: Option<u32>,
+	/// maximální počet aktivních blokací termínu, viz [`Hold`]
+	#[serde(default)]
+	pub max_holds: Option<u32>,
+}
+
+impl Quota {
+	/// doplní nenastavené kvóty z `other`
+	pub fn or(self, other: Quota) -> Quota {
+		Quota {
+			max_hours_week:  self.max_hours_week.or(other.max_hours_week),
+			max_hours_month: self.max_hours_month.or(other.max_hours_month),
+			max_future:      self.max_future.or(other.max_future),
+			max_pending:     self.max_pending.or(other.max_pending),
+			max_holds:       self.max_holds.or(other.max_holds),
+		}
+	}
+}
+
+/// jednotlivé kvóty
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+	/// hodiny za týden
+	HoursPerWeek,
+	/// hodiny za měsíc
+	HoursPerMonth,
+	/// počet budoucích rezervací
+	FutureReservations,
+	/// počet neschválených rezervací
+	PendingRequests,
+	/// počet aktivních blokací termínu
+	Holds,
+}
+
+/// čerpání kvót
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Usage {
+	/// hodiny rezervací v daném týdnu
+	pub hours_week: f64,
+	/// hodiny rezervací v daném měsíci
+	pub hours_month: f64,
+	/// počet budoucích rezervací
+	pub future: u32,
+	/// počet budoucích neschválených rezervací
+	pub pending: u32,
+	/// počet aktivních blokací termínu
+	#[serde(default)]
+	pub holds: u32,
+}
+
+/// překročení kvóty
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuotaViolation {
+	/// překročená kvóta
+	pub limit: Limit,
+	/// hodnota kvóty
+	pub max: u32,
+	/// čerpání včetně nové rezervace
+	pub usage: f64,
+}
+
+/// přehled kvót a jejich čerpání pro uživatele
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuotaReport {
+	/// platné kvóty
+	pub quota: Quota,
+	/// současné čerpání (týden a měsíc jsou ty aktuální)
+	pub usage: Usage,
+}
+
+/// Model týmu (oddělení), tak jak je uložen v databázi
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Team {
+	/// název týmu
+	pub name: String,
+	/// emaily členů
+	pub members: Vec<String>,
+	/// emaily správců týmu, správci jsou zároveň členy
+	pub admins: Vec<String>,
+}
+
+impl Team {
+	/// zda je uživatel členem týmu
+	pub fn is_member(&self, email: &str) -> bool {
+		self.is_admin(email) || self.members.iter().any(|m| m == email)
+	}
+
+	/// zda je uživatel správcem týmu
+	pub fn is_admin(&self, email: &str) -> bool {
+		self.admins.iter().any(|a| a == email)
+	}
+}
+
+/// Model zmocnění, tak jak je uloženo v databázi
+///
+/// `delegate` smí rezervovat v zastoupení `principal`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Delegation {
+	/// email uživatele, který zmocnění udělil
+	pub principal: String,
+	/// email zmocněného uživatele
+	pub delegate: String,
+	/// kdy bylo zmocnění uděleno
+	pub granted: DateTime<Utc>,
+}
+
+/// Model záznamu na čekací listině, tak jak je uložen v databázi
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaitlistEntry {
+	/// požadovaná rezervace (včetně autora)
+	pub reservation: Reservation,
+	/// kdy se uživatel zapsal na čekací listinu, určuje pořadí
+	pub created: DateTime<Utc>,
+	/// pokud se termín uvolnil a je uživateli nabídnut,
+	/// do kdy musí nabídku přijmout
+	pub offered_until: Option<DateTime<Utc>>,
+}
+
+/// Model dočasné blokace termínu, tak jak je uložena v databázi
+///
+/// blokace brání ostatním v rezervaci termínu, dokud nevyprší
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hold {
+	/// blokované místnosti, viz [`Reservation`]
+	pub rooms: u8,
+	/// počátek blokovaného termínu
+	pub begin_time: DateTime<Utc>,
+	/// konec blokovaného termínu
+	pub end_time: DateTime<Utc>,
+	/// kdo termín blokuje
+	pub author: String,
+	/// kdy blokace vyprší
+	pub expires: DateTime<Utc>,
+}
+
+impl Hold {
+	/// zda blokace koliduje s daným intervalem v daných místnostech
+	pub fn collides(&self, rooms: u8, begin_time: DateTime<Utc>, end_time: DateTime<Utc>) -> bool {
+		self.begin_time <= end_time && self.end_time >= begin_time && self.rooms & rooms != 0
+	}
+}
+
+/// Model blokace termínu pro přidání do databáze
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewHold {
+	/// blokované místnosti, viz [`Reservation`]
+	pub rooms: u8,
+	/// počátek blokovaného termínu
+	pub begin_time: DateTime<Utc>,
+	/// konec blokovaného termínu
+	pub end_time: DateTime<Utc>,
+}
+
+/// údaje pro převedení blokace na žádost o rezervaci
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConvertHold {
+	/// název události
+	pub name: String,
+	/// popis události
+	pub description: String,
+	/// rozložení nábytku v audioriu
+	pub layout: u8,
+	/// počet lidí
+	pub people: u16,
+	/// tým, kterému má rezervace patřit
+	#[serde(default)]
+	pub team: Option<u64>,
+}
+
+/// Model místnosti v registru místností
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Room {
+	/// název místnosti
+	pub name: String,
+	/// kód pro odbavení (check-in) bez přihlášení, např. z QR kódu u místnosti
+	#[serde(default)]
+	pub checkin_code: Option<String>,
+}
+
+/// Model zařízení v kiosk módu (tablet u dveří místnosti)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Device {
+	/// název zařízení
+	pub name: String,
+	/// místnost, kterou zařízení zobrazuje, viz [`Reservation`]
+	pub room: u8,
+	/// tajný token, kterým se zařízení autentizuje
+	pub token: String,
+	/// kdy bylo zařízení zaregistrováno
+	pub created: DateTime<Utc>,
+}
+
+/// Model zařízení pro přidání do databáze
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewDevice {
+	/// název zařízení
+	pub name: String,
+	/// místnost, kterou zařízení zobrazuje
+	pub room: u8,
+}
+
+/// rezervace, jak ji vidí kiosk, bez údajů o autorovi
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KioskEvent {
+	/// název události
+	pub name: String,
+	/// počáteční čas rezervace
+	pub begin_time: DateTime<Utc>,
+	/// čas, kdy rezervace končí
+	pub end_time: DateTime<Utc>,
+}
+
+impl From<Reservation> for KioskEvent {
+	fn from(src: Reservation) -> KioskEvent {
+		KioskEvent {
+			name:       src.name,
+			begin_time: src.begin_time,
+			end_time:   src.end_time,
+		}
+	}
+}
+
+/// stav místnosti pro kiosk
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KioskStatus {
+	/// bitflag místnosti
+	pub room: u8,
+	/// název místnosti
+	pub name: String,
+	/// právě probíhající rezervace
+	pub current: Option<KioskEvent>,
+	/// nejbližší následující rezervace
+	pub next: Option<KioskEvent>,
+}
+
+/// druh změny rezervace v živém kanálu změn
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+	/// nová rezervace
+	Created,
+	/// upravená rezervace
+	Updated,
+	/// schválená rezervace
+	Approved,
+	/// zamítnutá (schvalovatelem smazaná) žádost o rezervaci
+	Rejected,
+	/// smazaná rezervace
+	Deleted,
+}
+
+impl ChangeKind {
+	/// název změny, jak se posílá v kanálu
+	pub fn name(self) -> &'static str {
+		match self {
+			ChangeKind::Created => "created",
+			ChangeKind::Updated => "updated",
+			ChangeKind::Approved => "approved",
+			ChangeKind::Rejected => "rejected",
+			ChangeKind::Deleted => "deleted",
+		}
+	}
+}
+
+/// záznam živého kanálu změn rezervací
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Change {
+	/// druh změny
+	pub kind: ChangeKind,
+	/// id změněné rezervace
+	pub reservation_id: u64,
+	/// dotčené místnosti (před změnou i po ní)
+	pub rooms: u8,
+	/// rezervace po změně, u smazaných před smazáním
+	pub reservation: Option<Reservation>,
+	/// kdy ke změně došlo
+	pub time: DateTime<Utc>,
+}
+
+/// Model odběru webhooků, tak jak je uložen v databázi
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Webhook {
+	/// kam se události posílají (POST)
+	pub url: String,
+	/// odebírané druhy změn, viz [`ChangeKind`]
+	pub events: Vec<ChangeKind>,
+	/// tajemství pro HMAC podpis těla požadavku
+	pub secret: String,
+	/// kdy byl odběr vytvořen
+	pub created: DateTime<Utc>,
+}
+
+/// Model odběru webhooků pro přidání do databáze
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewWebhook {
+	/// kam se události posílají (POST)
+	pub url: String,
+	/// odebírané druhy změn
+	pub events: Vec<ChangeKind>,
+	/// tajemství pro HMAC podpis těla požadavku
+	pub secret: String,
+}
+
+/// stav doručení webhooku
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+	/// čeká na (další) pokus
+	Pending,
+	/// doručeno
+	Delivered,
+	/// všechny pokusy selhaly
+	Failed,
+}
+
+/// doručení jedné události jednomu odběru, slouží jako fronta i jako log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Delivery {
+	/// id odběru, viz [`Webhook`]
+	pub webhook: u64,
+	/// druh události, `ping` pro testovací události
+	pub event: String,
+	/// tělo požadavku (JSON)
+	pub payload: String,
+	/// stav doručení
+	pub status: DeliveryStatus,
+	/// počet provedených pokusů
+	pub attempts: u32,
+	/// kdy proběhne další pokus
+	pub next_attempt: DateTime<Utc>,
+	/// výsledek posledního pokusu (HTTP status nebo chyba)
+	pub last_response: Option<String>,
+	/// kdy byla událost zařazena do fronty
+	pub created: DateTime<Utc>,
+	/// kdy byla událost doručena
+	pub delivered: Option<DateTime<Utc>>,
+}
+
+/// změna jedné položky záznamu v auditním logu
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+	/// název položky
+	pub field: String,
+	/// hodnota před změnou (`null`, pokud záznam nebo položka neexistovaly)
+	pub before: serde_json::Value,
+	/// hodnota po změně (`null`, pokud byl záznam smazán)
+	pub after: serde_json::Value,
+}
+
+/// záznam auditního logu
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+	/// kdo akci provedl (email)
+	pub actor: String,
+	/// druh akce, např. `reservation.approve`
+	pub action: String,
+	/// čeho se akce týká, např. `reservation:42` nebo `user:jan@example.com`
+	pub target: String,
+	/// změněné položky
+	pub diff: Vec<FieldChange>,
+	/// kdy byla akce provedena
+	pub time: DateTime<Utc>,
+}
+
+/// jedna verze rezervace
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Revision {
+	/// rezervace v této verzi
+	pub reservation: Reservation,
+	/// kdo verzi vytvořil (email), `system` u změn provedených na pozadí
+	pub actor: String,
+	/// kdo rezervaci v této verzi schválil (email), `None` u neschválené verze
+	#[serde(default)]
+	pub approved_by: Option<String>,
+	/// kdy verze vznikla
+	pub time: DateTime<Utc>,
+}
+
+/// rozdíl dvou verzí rezervace
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevisionDiff {
+	/// starší verze, `None` pokud se porovnává s ničím (např. rezervace nebyla nikdy schválena)
+	pub from: Option<u32>,
+	/// novější verze
+	pub to: u32,
+	/// kdo schválil starší verzi (email)
+	#[serde(default)]
+	pub approved_by: Option<String>,
+	/// změněné položky
+	pub diff: Vec<FieldChange>,
+}
+
+/// statistika docházky na rezervace uživatele
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Attendance {
+	/// počet schválených rezervací, které už začaly
+	pub reservations: u32,
+	/// počet rezervací s odbavením
+	pub checked_in: u32,
+	/// počet rezervací, na které nikdo nepřišel
+	pub no_shows: u32,
+}
+
 /// Model usera
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(dead_code)]
@@ -119,3 +683,157 @@ pub struct User {
 	/// role
 	pub role: String,
 }
+
+/// jak často se výluka opakuje
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+	/// každý den
+	Daily,
+	/// každý týden
+	Weekly,
+	/// každý rok (svátky, celofiremní akce)
+	Yearly,
+}
+
+/// pravidlo opakování výluky
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recurrence {
+	/// frekvence opakování
+	pub frequency: Frequency,
+	/// poslední možný začátek opakování, `None` znamená navždy
+	pub until: Option<DateTime<Utc>>,
+}
+
+impl Recurrence {
+	/// vrací začátek `n`-tého opakování,
+	/// `None` pokud dané opakování neexistuje (29. února v nepřestupném roce)
+	pub fn nth(&self, start: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+		match self.frequency {
+			Frequency::Daily => Some(start + Duration::days(n.into())),
+			Frequency::Weekly => Some(start + Duration::weeks(n.into())),
+			Frequency::Yearly => start.with_year(start.year() + n as i32),
+		}
+	}
+
+	/// délka jedné periody, pokud je pevná
+	fn period(&self) -> Option<Duration> {
+		match self.frequency {
+			Frequency::Daily => Some(Duration::days(1)),
+			Frequency::Weekly => Some(Duration::weeks(1)),
+			Frequency::Yearly => None,
+		}
+	}
+}
+
+/// Model výluky (údržba, svátky, celofiremní akce), tak jak je uložena v databázi
+///
+/// po dobu výluky nelze dané místnosti rezervovat
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blackout {
+	/// místnosti, kterých se výluka týká, viz [`Reservation`]
+	pub rooms: u8,
+	/// počátek (prvního výskytu) výluky
+	pub begin_time: DateTime<Utc>,
+	/// konec (prvního výskytu) výluky
+	pub end_time: DateTime<Utc>,
+	/// důvod výluky
+	pub reason: String,
+	/// volitelné opakování
+	pub recurrence: Option<Recurrence>,
+	/// kdo výluku vytvořil
+	pub author: String,
+}
+
+impl Blackout {
+	/// vrací všechny výskyty výluky, které zasahují do intervalu `begin_time`..`end_time`
+	pub fn occurrences(&self, begin_time: DateTime<Utc>, end_time: DateTime<Utc>) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+		let length = self.end_time - self.begin_time;
+
+		let recurrence = match &self.recurrence {
+			Some(r) => r,
+			None if self.begin_time <= end_time && self.end_time >= begin_time =>
+				return vec![(self.begin_time, self.end_time)],
+			None => return vec![],
+		};
+
+		// přeskočíme výskyty, které skončily před začátkem intervalu
+		let mut n = match recurrence.period() {
+			Some(period) if begin_time > self.end_time =>
+				((begin_time - self.end_time).num_seconds() / period.num_seconds()) as u32,
+			_ => 0,
+		};
+
+		let mut res = vec![];
+		loop {
+			let start = match recurrence.nth(self.begin_time, n) {
+				Some(s) => s,
+				None => {
+					n += 1;
+					continue;
+				}
+			};
+
+			if start > end_time || recurrence.until.map(|u| start > u).unwrap_or(false) {
+				break;
+			}
+
+			if start + length >= begin_time {
+				res.push((start, start + length));
+			}
+
+			n += 1;
+		}
+
+		res
+	}
+
+	/// zda výluka blokuje daný interval v daných místnostech
+	pub fn blocks(&self, rooms: u8, begin_time: DateTime<Utc>, end_time: DateTime<Utc>) -> bool {
+		self.rooms & rooms != 0 && !self.occurrences(begin_time, end_time).is_empty()
+	}
+}
+
+/// Model výluky pro přidání do databáze
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewBlackout {
+	/// místnosti, kterých se výluka týká, viz [`Reservation`]
+	pub rooms: u8,
+	/// počátek (prvního výskytu) výluky
+	pub begin_time: DateTime<Utc>,
+	/// konec (prvního výskytu) výluky
+	pub end_time: DateTime<Utc>,
+	/// důvod výluky
+	pub reason: String,
+	/// volitelné opakování
+	#[serde(default)]
+	pub recurrence: Option<Recurrence>,
+	/// zda upozornit autory již schválených rezervací, do kterých výluka zasahuje
+	#[serde(default)]
+	pub notify_owners: bool,
+}
+
+impl From<NewBlackout> for Blackout {
+	fn from(src: NewBlackout) -> Blackout {
+		Blackout {
+			rooms:      src.rooms,
+			begin_time: src.begin_time,
+			end_time:   src.end_time,
+			reason:     src.reason,
+			recurrence: src.recurrence,
+			author:     String::new(),
+		}
+	}
+}
+
+/// Model upozornění čekajícího na odeslání (viz `mail.py`)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+	/// email příjemce
+	pub recipient: String,
+	/// předmět zprávy
+	pub subject: String,
+	/// text zprávy
+	pub text: String,
+	/// kdy bylo upozornění vytvořeno
+	pub created: DateTime<Utc>,
+}
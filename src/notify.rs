@@ -0,0 +1,69 @@
+//! a module for queueing notifications
+//!
+//! the backend doesn't send any emails by itself, notifications are stored
+//! in the [`Notifications`] outbox and picked up by the mailer (see `mail.py`)
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::offset::Utc;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Superadmin;
+
+use crate::db::{Database, table::Notifications, table::Audit};
+
+use crate::audit;
+use crate::models::{Notification, Reservation};
+use crate::response::Error;
+
+/// human-readable name of the given rooms, see [`crate::models::Reservation`]
+pub fn room_names(rooms: u8) -> &'static str {
+	match rooms {
+		0b01 => "Auditorium North",
+		0b10 => "Auditorium South",
+		_ => "Auditorium North and Auditorium South",
+	}
+}
+
+/// queues a notification for the given recipient
+pub fn queue(db: &mut Database<Notifications>, recipient: &str, subject: &str, text: String) -> Option<()> {
+	let notification = Notification {
+		recipient: recipient.to_string(),
+		subject: subject.to_string(),
+		text,
+		created: Utc::now(),
+	};
+
+	let key = db.get_key().ok()?;
+	db.write().insert(key, notification).ok().map(|_| ())
+}
+
+/// queues a notification about a reservation for its author
+/// and for whoever requested it on the author's behalf
+pub fn queue_reservation(db: &mut Database<Notifications>, reservation: &Reservation, subject: &str, text: String) -> Option<()> {
+	for recipient in Some(&reservation.author).into_iter().chain(reservation.requested_by.as_ref()) {
+		queue(db, recipient, subject, text.clone())?;
+	}
+
+	Some(())
+}
+
+/// get all notifications waiting to be sent
+#[get("/notifications", format = "application/json")]
+pub fn list(db: Database<Notifications>, _u: AuthToken<Superadmin>) -> Result<Json<Vec<(u64, Notification)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// acknowledge a sent notification, removing it from the outbox
+#[delete("/notifications/<id>")]
+pub fn acknowledge(id: u64, mut db: Database<Notifications>, mut audit: Database<Audit>, usr: AuthToken<Superadmin>) -> Option<()> {
+	let notification = db.read().get(id).ok()??;
+	db.write().delete(id).ok()?;
+
+	audit::record(&mut audit, &usr.user.email, "notification.acknowledge", format!("notification:{}", id), Some(&notification), None)
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, acknowledge]
+}
@@ -0,0 +1,188 @@
+//! a module containing the booking policy rules engine
+//!
+//! every room may have a [`Policy`], which is evaluated by [`check`] whenever
+//! a reservation is created, updated or imported and during availability search.
+//! a reservation spanning both rooms has to satisfy both policies
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Timelike, offset::Utc};
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{self, Noob};
+
+use crate::db::{self, Database, table::Policies};
+
+use crate::models::{Policy, Rule, Violation, User, ROOMS};
+use crate::response::Error;
+
+/// evaluates the policy of a single room for the given user role
+pub fn evaluate(
+	policy: &Policy,
+	room: u8,
+	role: &str,
+	begin_time: DateTime<Utc>,
+	end_time: DateTime<Utc>,
+	now: DateTime<Utc>,
+) -> Vec<Violation> {
+	let mut violations = vec![];
+	let mut violate = |rule: Rule, message: String| {
+		let exempt = policy.exempt.get(&rule).map(|r| r.iter().any(|r| roles::is_named(role, r))).unwrap_or(false);
+
+		if !exempt {
+			violations.push(Violation { rule, room, message });
+		}
+	};
+
+	let offset = FixedOffset::east(policy.utc_offset * 60);
+	let (begin, end) = (begin_time.with_timezone(&offset), end_time.with_timezone(&offset));
+	let duration = end_time - begin_time;
+
+	if !policy.opening_hours.is_empty() {
+		let fits = policy
+			.opening_hours
+			.iter()
+			.filter(|h| h.weekday == begin.weekday())
+			.any(|h| begin.date() == end.date() && h.open <= begin.time() && end.time() <= h.close);
+
+		if !fits {
+			violate(Rule::OpeningHours, "reservation is outside of opening hours".to_string());
+		}
+	}
+
+	if let Some(min) = policy.min_duration {
+		if duration < Duration::minutes(min) {
+			violate(Rule::MinDuration, format!("reservation must be at least {} minutes long", min));
+		}
+	}
+
+	if let Some(max) = policy.max_duration {
+		if duration > Duration::minutes(max) {
+			violate(Rule::MaxDuration, format!("reservation must be at most {} minutes long", max));
+		}
+	}
+
+	if let Some(lead) = policy.min_lead_time {
+		if begin_time - now < Duration::minutes(lead) {
+			violate(Rule::LeadTime, format!("reservation must be made at least {} minutes in advance", lead));
+		}
+	}
+
+	if let Some(days) = policy.max_advance {
+		if begin_time - now > Duration::days(days) {
+			violate(Rule::AdvanceHorizon, format!("reservation can't be made more than {} days in advance", days));
+		}
+	}
+
+	if let Some(slot) = policy.granularity.filter(|&g| g > 0) {
+		let misaligned = [begin, end]
+			.iter()
+			.any(|t| i64::from(t.num_seconds_from_midnight()) % (slot * 60) != 0 || t.nanosecond() != 0);
+
+		if misaligned {
+			violate(Rule::Granularity, format!("reservation must begin and end on {}-minute slots", slot));
+		}
+	}
+
+	violations
+}
+
+/// violations of the policies of all the given rooms by the interval
+pub fn violations(
+	db: &Database<Policies>,
+	user: &User,
+	rooms: u8,
+	begin_time: DateTime<Utc>,
+	end_time: DateTime<Utc>,
+) -> db::Result<Vec<Violation>> {
+	if begin_time >= end_time {
+		return Ok(vec![Violation {
+			rule:    Rule::Interval,
+			room:    rooms,
+			message: "reservation must end after it begins".to_string(),
+		}]);
+	}
+
+	let now = Utc::now();
+	let mut violations = vec![];
+
+	for room in ROOMS.iter().filter(|&room| rooms & room != 0) {
+		if let Some(policy) = db.read().get(room)? {
+			violations.append(&mut evaluate(&policy, *room, &user.role, begin_time, end_time, now));
+		}
+	}
+
+	Ok(violations)
+}
+
+/// checks the interval against the policies of all the given rooms, see [`violations`]
+pub fn check(
+	db: &Database<Policies>,
+	user: &User,
+	rooms: u8,
+	begin_time: DateTime<Utc>,
+	end_time: DateTime<Utc>,
+) -> Result<(), Error> {
+	let violations = violations(db, user, rooms, begin_time, end_time)?;
+
+	match violations.is_empty() {
+		true => Ok(()),
+		false => Err(Error::Policy(violations)),
+	}
+}
+
+/// vrací pravidla všech místností
+///
+/// GET /policies "application/json"
+#[get("/policies", format = "application/json")]
+pub fn list(db: Database<Policies>, _u: AuthToken<Noob>) -> Result<Json<Vec<(u8, Policy)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// vrací pravidla dané místnosti
+///
+/// GET /policies/<room> "application/json"
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+#[get("/policies/<room>")]
+pub fn get(room: u8, db: Database<Policies>, _u: AuthToken<Noob>) -> Result<Json<Policy>, Error> {
+	db.read().get(room)?.map(Json).ok_or(Error::NotFound)
+}
+
+/// nastaví pravidla dané místnosti
+///
+/// PUT /policies/<room> application/json
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+///
+/// data: [`Policy`]
+#[put("/policies/<room>", data = "<input>")]
+pub fn put(room: u8, input: Json<Policy>, mut db: Database<Policies>, usr: AuthToken<Noob>) -> Option<()> {
+	if !usr.manages_facility() || !ROOMS.contains(&room) {
+		return None;
+	}
+
+	db.write().insert(room, input.into_inner()).ok().map(|_| ())
+}
+
+/// odstraní pravidla dané místnosti
+///
+/// DELETE /policies/<room>
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+#[delete("/policies/<room>")]
+pub fn delete(room: u8, mut db: Database<Policies>, usr: AuthToken<Noob>) -> Option<()> {
+	if !usr.manages_facility() {
+		return None;
+	}
+
+	db.write().delete(room).ok()?.map(|_| ())
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, get, put, delete]
+}
@@ -0,0 +1,141 @@
+//! a module generating QR codes of rooms
+//!
+//! the codes point at the public agenda page of the room ([`crate::room::agenda`])
+//! or at its check-in page ([`crate::checkin::room_checkin`]), which contains the
+//! secret check-in code and is therefore only available to facility managers.
+//! absolute urls are built from `PUBLIC_URL` (`http://localhost:8000` by default)
+use rocket::Route;
+use rocket::http::ContentType;
+use rocket::response::content::{Content, Html};
+
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use qrcode::QrCode;
+use qrcode::render::svg;
+
+use std::env;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Noob;
+
+use crate::db::{Database, table::Rooms};
+
+use crate::models::ROOMS;
+use crate::response::Error;
+use crate::room;
+use crate::static_server::{escape, page};
+
+/// minimal size of the generated codes in pixels
+const SIZE: u32 = 200;
+
+/// base url of the application as seen by the users
+fn public_url() -> String {
+	env::var("PUBLIC_URL").unwrap_or_else(|_| "http://localhost:8000".to_string()).trim_end_matches('/').to_string()
+}
+
+/// url of the agenda page of the room
+pub fn agenda_url(room: u8) -> String {
+	format!("{}/rooms/{}/agenda", public_url(), room)
+}
+
+/// url of the check-in page of the room
+pub fn checkin_url(room: u8, code: &str) -> String {
+	// the code is set by facility managers, keep it url-safe
+	let code = code
+		.bytes()
+		.map(|b| match b {
+			b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+			_ => format!("%{:02X}", b),
+		})
+		.collect::<String>();
+
+	format!("{}/rooms/{}/checkin?code={}", public_url(), room, code)
+}
+
+/// renders the QR code of `data` as SVG
+pub fn svg(data: &str) -> Option<String> {
+	let code = QrCode::new(data.as_bytes()).ok()?;
+
+	Some(code.render::<svg::Color>().min_dimensions(SIZE, SIZE).build())
+}
+
+/// renders the QR code of `data` as PNG
+pub fn png(data: &str) -> Option<Vec<u8>> {
+	let code = QrCode::new(data.as_bytes()).ok()?;
+	let image = code.render::<Luma<u8>>().min_dimensions(SIZE, SIZE).build();
+
+	let mut buf = vec![];
+	DynamicImage::ImageLuma8(image).write_to(&mut buf, ImageOutputFormat::Png).ok()?;
+
+	Some(buf)
+}
+
+/// vrací QR kód místnosti
+///
+/// GET /rooms/<room>/qr?<target>&<format>
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+/// - `target`: `agenda` (výchozí) nebo `checkin`, kód pro odbavení smí jen správci budovy
+/// - `format`: `svg` (výchozí) nebo `png`
+#[get("/rooms/<room>/qr?<target>&<format>")]
+pub fn qr(
+	room: u8,
+	target: Option<String>,
+	format: Option<String>,
+	rooms: Database<Rooms>,
+	usr: Option<AuthToken<Noob>>,
+) -> Result<Content<Vec<u8>>, Error> {
+	if !ROOMS.contains(&room) {
+		return Err(Error::NotFound);
+	}
+
+	let url = match target.as_deref().unwrap_or("agenda") {
+		"agenda" => agenda_url(room),
+		"checkin" => {
+			if !usr.map(|u| u.manages_facility()).unwrap_or(false) {
+				return Err(Error::Forbidden);
+			}
+
+			let code = rooms.read().get(room)?.and_then(|r| r.checkin_code).ok_or(Error::NotFound)?;
+			checkin_url(room, &code)
+		}
+		_ => return Err(Error::NotFound),
+	};
+
+	// the url always fits into a QR code
+	match format.as_deref().unwrap_or("svg") {
+		"svg" => Ok(Content(ContentType::SVG, svg(&url).ok_or(Error::NotFound)?.into_bytes())),
+		"png" => Ok(Content(ContentType::PNG, png(&url).ok_or(Error::NotFound)?)),
+		_ => Err(Error::NotFound),
+	}
+}
+
+/// stránka s QR kódy programu všech místností z registru pro tisk
+///
+/// GET /rooms/qr
+#[get("/rooms/qr")]
+pub fn sheet(rooms: Database<Rooms>) -> Result<Html<String>, Error> {
+	let cells = room::registered(&rooms)?
+		.into_iter()
+		.map(|(r, registered)| {
+			Ok(format!(
+				"<div style=\"display: inline-block; width: 210px; text-align: center; page-break-inside: avoid;\">\
+				<div>{}</div>{}</div>",
+				escape(&registered.name),
+				svg(&agenda_url(r)).ok_or(Error::NotFound)?,
+			))
+		})
+		.collect::<Result<String, Error>>()?;
+
+	Ok(page("Auditorium room QR codes", cells))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![qr]
+}
+
+/// vrací stránky renderované na serveru, mountují se mimo `/api/`
+pub fn pages() -> Vec<Route> {
+	routes![sheet]
+}
@@ -0,0 +1,228 @@
+//! a module containing booking quotas
+//!
+//! quotas are configured per role and can be overridden per user (see [`QuotaOwner`]),
+//! they are enforced when creating and updating reservations
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, Datelike, offset::Utc};
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{Noob, Approver, Superadmin};
+
+use crate::db::{
+	self,
+	Database,
+	Ordered,
+	table::Quotas,
+	table::Reservations,
+	table::Users,
+	table::Holds,
+};
+
+use crate::hold;
+use crate::response::Error;
+
+use crate::models::{Quota, QuotaOwner, QuotaReport, QuotaViolation, Limit, Usage, Reservation, User};
+
+/// effective quota of the user, personal quotas take precedence over role quotas
+pub fn effective(db: &Database<Quotas>, user: &User) -> db::Result<Quota> {
+	let personal = db.read().get(QuotaOwner::User(user.email.clone()))?.unwrap_or_default();
+	let role = db.read().get(QuotaOwner::Role(user.role.clone()))?.unwrap_or_default();
+
+	Ok(personal.or(role))
+}
+
+/// computes the usage of the given reservations,
+/// hours are counted for the week and month containing `at`
+pub fn usage<'a, I>(reservations: I, at: DateTime<Utc>, now: DateTime<Utc>) -> Usage
+where
+	I: IntoIterator<Item = &'a Reservation>,
+{
+	let mut usage = Usage::default();
+
+	for r in reservations {
+		let hours = (r.end_time - r.begin_time).num_minutes() as f64 / 60.0;
+
+		if r.begin_time.iso_week() == at.iso_week() {
+			usage.hours_week += hours;
+		}
+
+		if r.begin_time.year() == at.year() && r.begin_time.month() == at.month() {
+			usage.hours_month += hours;
+		}
+
+		if r.end_time > now {
+			usage.future += 1;
+
+			if !r.approved {
+				usage.pending += 1;
+			}
+		}
+	}
+
+	usage
+}
+
+/// quotas exceeded by the given usage
+fn exceeded(quota: &Quota, usage: &Usage) -> Vec<QuotaViolation> {
+	[
+		(Limit::HoursPerWeek, quota.max_hours_week, usage.hours_week),
+		(Limit::HoursPerMonth, quota.max_hours_month, usage.hours_month),
+		(Limit::FutureReservations, quota.max_future, f64::from(usage.future)),
+		(Limit::PendingRequests, quota.max_pending, f64::from(usage.pending)),
+	]
+	.iter()
+	.filter_map(|&(limit, max, usage)| {
+		max.filter(|&max| usage > f64::from(max)).map(|max| QuotaViolation { limit, max, usage })
+	})
+	.collect()
+}
+
+/// usage of the reservations listed in `index` under `value` if `candidate` was added,
+/// `exclude` is the id of the reservation being updated, if any
+fn usage_with<O>(
+	reservations: &Database<Reservations>,
+	index: &str,
+	value: &O,
+	candidate: &Reservation,
+	exclude: Option<u64>,
+) -> db::Result<Usage>
+where
+	O: Ordered + ?Sized,
+{
+	let matching = reservations
+		.read()
+		.find(index, value)?
+		.filter(|(id, _)| Some(*id) != exclude)
+		.map(|(_, r)| r)
+		.collect::<Vec<Reservation>>();
+
+	Ok(usage(matching.iter().chain(Some(candidate)), candidate.begin_time, Utc::now()))
+}
+
+/// checks whether `candidate` fits into the quotas of its owner
+/// and of its team, `exclude` is the id of the reservation being updated, if any
+pub fn check(
+	quotas: &Database<Quotas>,
+	reservations: &Database<Reservations>,
+	owner: &User,
+	candidate: &Reservation,
+	exclude: Option<u64>,
+) -> Result<(), Error> {
+	let own = usage_with(reservations, "author", &owner.email, candidate, exclude)?;
+	let mut violations = exceeded(&effective(quotas, owner)?, &own);
+
+	if let Some(team) = candidate.team {
+		let quota = quotas.read().get(QuotaOwner::Team(team))?.unwrap_or_default();
+		let usage = usage_with(reservations, "team", &team, candidate, exclude)?;
+
+		violations.append(&mut exceeded(&quota, &usage));
+	}
+
+	match violations.is_empty() {
+		true => Ok(()),
+		false => Err(Error::Quota(violations)),
+	}
+}
+
+/// current quotas and usage of the user
+fn report(
+	quotas: &Database<Quotas>,
+	reservations: &Database<Reservations>,
+	holds: &Database<Holds>,
+	user: &User,
+) -> db::Result<QuotaReport> {
+	let now = Utc::now();
+	let own = reservations
+		.read()
+		.find("author", &user.email)?
+		.map(|(_, r)| r)
+		.collect::<Vec<Reservation>>();
+
+	let mut usage = usage(&own, now, now);
+	usage.holds = hold::count(holds, &user.email);
+
+	Ok(QuotaReport { quota: effective(quotas, user)?, usage })
+}
+
+/// kvóty a jejich čerpání přihlášeného uživatele
+///
+/// GET /quota "application/json"
+#[get("/quota", format = "application/json")]
+pub fn mine(
+	quotas: Database<Quotas>,
+	reservations: Database<Reservations>,
+	holds: Database<Holds>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<QuotaReport>, Error> {
+	Ok(Json(report(&quotas, &reservations, &holds, &usr.user)?))
+}
+
+/// kvóty a jejich čerpání daného uživatele
+///
+/// GET /quota/<email> "application/json"
+#[get("/quota/<email>", format = "application/json")]
+pub fn of_user(
+	email: String,
+	quotas: Database<Quotas>,
+	reservations: Database<Reservations>,
+	holds: Database<Holds>,
+	users: Database<Users>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<QuotaReport>, Error> {
+	if usr.user.email != email && !usr.is::<Approver>() {
+		return Err(Error::Forbidden);
+	}
+
+	let user = users.read().get(&email)?.ok_or(Error::NotFound)?;
+
+	Ok(Json(report(&quotas, &reservations, &holds, &user)?))
+}
+
+/// get all configured quotas
+#[get("/quotas", format = "application/json")]
+pub fn list(db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Result<Json<Vec<(QuotaOwner, Quota)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// set quotas of a role
+#[put("/quotas/role/<role>", data = "<input>")]
+pub fn put_role(role: String, input: Json<Quota>, mut db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().insert(QuotaOwner::Role(role), input.into_inner()).ok().map(|_| ())
+}
+
+/// set personal quotas of a user
+#[put("/quotas/user/<email>", data = "<input>")]
+pub fn put_user(email: String, input: Json<Quota>, mut db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().insert(QuotaOwner::User(email), input.into_inner()).ok().map(|_| ())
+}
+
+/// set quotas of a team
+#[put("/quotas/team/<id>", data = "<input>")]
+pub fn put_team(id: u64, input: Json<Quota>, mut db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().insert(QuotaOwner::Team(id), input.into_inner()).ok().map(|_| ())
+}
+
+/// remove quotas of a role
+#[delete("/quotas/role/<role>")]
+pub fn delete_role(role: String, mut db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().delete(QuotaOwner::Role(role)).ok()?.map(|_| ())
+}
+
+/// remove personal quotas of a user
+#[delete("/quotas/user/<email>")]
+pub fn delete_user(email: String, mut db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().delete(QuotaOwner::User(email)).ok()?.map(|_| ())
+}
+
+/// remove quotas of a team
+#[delete("/quotas/team/<id>")]
+pub fn delete_team(id: u64, mut db: Database<Quotas>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().delete(QuotaOwner::Team(id)).ok()?.map(|_| ())
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![mine, of_user, list, put_role, put_user, put_team, delete_role, delete_user, delete_team]
+}
@@ -1,6 +1,101 @@
+//! chybové odpovědi API
+//!
+//! chyby se posílají jako JSON s atributem `result` (viz README)
+//! a odpovídajícím HTTP statusem
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket_contrib::json::Json;
 
-use rocket::response;
+use crate::db;
+use crate::models::{Violation, QuotaViolation};
 
-// dodelam
+/// chyba vrácená endpointem
+#[derive(Debug, Clone)]
+pub enum Error {
+	/// rezervace nebyla nalezena (`result: 1`)
+	NotFound,
+	/// termín je již obsazený (`result: 2`)
+	Conflict,
+	/// rezervace porušuje pravidla místnosti (`result: 3`)
+	Policy(Vec<Violation>),
+	/// uživatel nemá oprávnění (`result: 4`)
+	Forbidden,
+	/// chyba databáze (`result: 5`)
+	Database,
+	/// rezervace by překročila kvóty uživatele (`result: 6`)
+	Quota(Vec<QuotaViolation>),
+	/// záznam byl mezitím změněn, `If-Match` nesouhlasí (`result: 7`)
+	PreconditionFailed,
+	/// chybí hlavička `If-Match` (`result: 8`)
+	PreconditionRequired,
+	/// termín je volný, na čekací listinu nepatří (`result: 9`)
+	Free,
+}
 
-pub enum Response {}
+impl Error {
+	/// číslo chyby pro atribut `result`
+	pub fn code(&self) -> u8 {
+		match self {
+			Error::NotFound => 1,
+			Error::Conflict => 2,
+			Error::Policy(_) => 3,
+			Error::Forbidden => 4,
+			Error::Database => 5,
+			Error::Quota(_) => 6,
+			Error::PreconditionFailed => 7,
+			Error::PreconditionRequired => 8,
+			Error::Free => 9,
+		}
+	}
+
+	/// HTTP status odpovídající chybě
+	pub fn status(&self) -> Status {
+		match self {
+			Error::NotFound => Status::NotFound,
+			Error::Conflict => Status::Conflict,
+			Error::Policy(_) => Status::UnprocessableEntity,
+			Error::Forbidden => Status::Forbidden,
+			Error::Database => Status::InternalServerError,
+			Error::Quota(_) => Status::UnprocessableEntity,
+			Error::PreconditionFailed => Status::PreconditionFailed,
+			Error::PreconditionRequired => Status::PreconditionRequired,
+			Error::Free => Status::UnprocessableEntity,
+		}
+	}
+}
+
+impl From<db::Error> for Error {
+	fn from(e: db::Error) -> Self {
+		match e {
+			db::Error::NotFound => Error::NotFound,
+			db::Error::Conflict => Error::PreconditionFailed,
+			e => {
+				log::error!("api: {}", e);
+				Error::Database
+			}
+		}
+	}
+}
+
+impl<'r> Responder<'r> for Error {
+	fn respond_to(self, req: &Request) -> response::Result<'r> {
+		let status = self.status();
+		let body = match self {
+			Error::Policy(violations) => serde_json::json!({ "result": 3, "violations": violations }),
+			Error::Quota(violations) => serde_json::json!({ "result": 6, "violations": violations }),
+			e => serde_json::json!({ "result": e.code() }),
+		};
+
+		Response::build_from(Json(body).respond_to(req)?).status(status).ok()
+	}
+}
+
+/// odpověď s hlavičkou `ETag`, viz [`crate::db::tag`]
+pub struct Tagged<R>(pub R, pub String);
+
+impl<'r, R: Responder<'r>> Responder<'r> for Tagged<R> {
+	fn respond_to(self, req: &Request) -> response::Result<'r> {
+		Response::build_from(self.0.respond_to(req)?).header(Header::new("ETag", format!("\"{}\"", self.1))).ok()
+	}
+}
@@ -0,0 +1,162 @@
+//! a module containing the room registry
+//!
+//! rooms are keyed by their bitflag (see [`crate::models::ROOMS`]) and managed
+//! by facility managers. a room may have a check-in code, which allows checking
+//! in to its reservations without logging in, see [`crate::checkin`].
+//! every room also has a public agenda page, see [`agenda`]
+use rocket::Route;
+use rocket::response::content::Html;
+use rocket_contrib::json::Json;
+
+use chrono::{Duration, FixedOffset, offset::Utc};
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Noob;
+
+use crate::db::{
+	self,
+	Database,
+	table::Rooms,
+	table::Reservations,
+	table::Policies,
+};
+
+use crate::models::{Reservation, Room, ROOMS};
+use crate::notify;
+use crate::response::Error;
+use crate::static_server::{escape, page};
+
+/// how many days ahead the agenda page shows
+const AGENDA_DAYS: i64 = 7;
+
+/// name of the room from the registry, falls back to the built-in names
+pub fn name(db: &Database<Rooms>, room: u8) -> db::Result<String> {
+	Ok(db.read().get(room)?.map(|r| r.name).unwrap_or_else(|| notify::room_names(room).to_string()))
+}
+
+/// all rooms of the registry, ordered by their bitflag
+pub fn registered(db: &Database<Rooms>) -> db::Result<Vec<(u8, Room)>> {
+	db.read().try_iter().collect()
+}
+
+/// local time offset of the room, taken from its policy
+pub fn offset(policies: &Database<Policies>, room: u8) -> db::Result<FixedOffset> {
+	Ok(FixedOffset::east(policies.read().get(room)?.map(|p| p.utc_offset).unwrap_or(0) * 60))
+}
+
+/// check-in codes are only visible to facility managers
+fn visible(mut room: Room, usr: &AuthToken<Noob>) -> Room {
+	if !usr.manages_facility() {
+		room.checkin_code = None;
+	}
+
+	room
+}
+
+/// vrací registr místností
+///
+/// GET /rooms "application/json"
+#[get("/rooms", format = "application/json")]
+pub fn list(db: Database<Rooms>, usr: AuthToken<Noob>) -> Result<Json<Vec<(u8, Room)>>, Error> {
+	Ok(Json(registered(&db)?.into_iter().map(|(id, r)| (id, visible(r, &usr))).collect::<Vec<(u8, Room)>>()))
+}
+
+/// vrací danou místnost
+///
+/// GET /rooms/<room> "application/json"
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+#[get("/rooms/<room>")]
+pub fn get(room: u8, db: Database<Rooms>, usr: AuthToken<Noob>) -> Result<Json<Room>, Error> {
+	db.read().get(room)?.map(|r| Json(visible(r, &usr))).ok_or(Error::NotFound)
+}
+
+/// nastaví údaje dané místnosti
+///
+/// PUT /rooms/<room> application/json
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+///
+/// data: [`Room`]
+#[put("/rooms/<room>", data = "<input>")]
+pub fn put(room: u8, input: Json<Room>, mut db: Database<Rooms>, usr: AuthToken<Noob>) -> Option<()> {
+	if !usr.manages_facility() || !ROOMS.contains(&room) {
+		return None;
+	}
+
+	db.write().insert(room, input.into_inner()).ok().map(|_| ())
+}
+
+/// odstraní místnost z registru
+///
+/// DELETE /rooms/<room>
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+#[delete("/rooms/<room>")]
+pub fn delete(room: u8, mut db: Database<Rooms>, usr: AuthToken<Noob>) -> Option<()> {
+	if !usr.manages_facility() {
+		return None;
+	}
+
+	db.write().delete(room).ok()?.map(|_| ())
+}
+
+/// veřejná stránka s programem místnosti na nejbližší dny
+///
+/// GET /rooms/<room>/agenda
+///
+/// parametry:
+/// - `room`: bitflag místnosti, viz [`crate::models::Reservation`]
+#[get("/rooms/<room>/agenda")]
+pub fn agenda(
+	room: u8,
+	rooms: Database<Rooms>,
+	reservations: Database<Reservations>,
+	policies: Database<Policies>,
+) -> Result<Html<String>, Error> {
+	if !ROOMS.contains(&room) {
+		return Err(Error::NotFound);
+	}
+
+	let now = Utc::now();
+	let offset = offset(&policies, room)?;
+
+	let mut events = reservations
+		.read()
+		.find("approved", &true)?
+		.map(|(_, r)| r)
+		.filter(|r| r.collides(room, now, now + Duration::days(AGENDA_DAYS)))
+		.collect::<Vec<Reservation>>();
+	events.sort_by_key(|r| r.begin_time);
+
+	let rows = events
+		.iter()
+		.map(|r| format!(
+			"<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+			r.begin_time.with_timezone(&offset).format("%a %d.%m. %H:%M"),
+			r.end_time.with_timezone(&offset).format("%H:%M"),
+			escape(&r.name),
+		))
+		.collect::<String>();
+
+	Ok(page(
+		&name(&rooms, room)?,
+		match rows.is_empty() {
+			true => "<p>Nothing booked in the next days.</p>".to_string(),
+			false => format!("<table>{}</table>", rows),
+		},
+	))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, get, put, delete]
+}
+
+/// vrací stránky renderované na serveru, mountují se mimo `/api/`
+pub fn pages() -> Vec<Route> {
+	routes![agenda]
+}
@@ -5,6 +5,8 @@
 //! - [`frontend`] -> soubory ze složky __frontend/build__
 //! - [`not_found`] -> 404 soubor
 //!
+//! a také pomocné funkce pro stránky renderované na serveru, viz [`page`]
+//!
 //! přidání nové statické routy:
 //! ```no_run
 //! #[get("/url/<path..>")]
@@ -17,6 +19,7 @@
 //! následně je zapotřebí routu zapnout v main.rs
 use std::path::{Path, PathBuf};
 use rocket::response::NamedFile;
+use rocket::response::content::Html;
 
 /// servuje index
 #[get("/")]
@@ -41,3 +44,21 @@ pub fn favicon() -> Option<NamedFile> {
 pub fn not_found() -> NamedFile {
 	NamedFile::open("frontend/404.html").expect("404.html not found")
 }
+
+/// escapuje text pro vložení do HTML
+pub fn escape(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// obalí tělo stránky renderované na serveru, `title` se escapuje
+pub fn page(title: &str, body: String) -> Html<String> {
+	Html(format!(
+		"<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+		<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+		<title>{title}</title>\
+		<style>body {{ font-family: Arial, Helvetica, sans-serif; margin: 1em; }}</style>\
+		</head><body><h1>{title}</h1>{body}</body></html>",
+		title = escape(title),
+		body = body,
+	))
+}
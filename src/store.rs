@@ -0,0 +1,651 @@
+//! a module containing storage backends
+//!
+//! the data live in named trees, ordered maps of byte keys to byte values
+//! (see [`RawTree`]), provided by a [`Backend`]: [`SledBackend`] stores them
+//! on disk, [`SqliteBackend`] in a SQLite database, which can be queried for
+//! reports, [`MemoryBackend`] only in memory (e.g. for tests). typed access
+//! to the trees is in [`crate::db`]
+//!
+//! the [`Store`] handle is kept in Rocket's managed state, request guards
+//! take it from there and background jobs get a clone, see [`crate::init`]
+use sled::{ConflictableTransactionError, ConflictableTransactionResult, TransactionError, Transactional, TransactionalTree};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::{Bound, Deref};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+
+use crate::db::{Error, Result};
+
+/// locks a lock of the store
+macro_rules! locked {
+	($lock:expr) => {
+		$lock.expect("a lock of the store has been poisoned")
+	};
+}
+
+mod sqlite;
+
+pub use sqlite::SqliteBackend;
+
+/// a raw record, key and value
+pub type Record = (Vec<u8>, Vec<u8>);
+
+/// a change of a tree, see [`RawTree::watch`]
+#[derive(Debug, Clone)]
+pub enum Event {
+	/// the key has been set to the value
+	Insert(Vec<u8>, Vec<u8>),
+	/// the key has been removed
+	Remove(Vec<u8>),
+}
+
+/// an ordered map of bytes to bytes
+pub trait RawTree: Send + Sync {
+	/// name of the tree
+	fn name(&self) -> &str;
+	/// the value of the key
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+	/// sets the value of the key, returns the previous one
+	fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>>;
+	/// removes the key, returns the previous value
+	fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+	/// sets the value to `new` (or removes it with `None`) only if it's still `old`,
+	/// returns whether it was
+	fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> Result<bool>;
+	/// records with keys in the range, in the order of the keys
+	fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn DoubleEndedIterator<Item = Result<Record>>>;
+	/// all records, in the order of the keys
+	fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = Result<Record>>> {
+		self.range((Bound::Unbounded, Bound::Unbounded))
+	}
+	/// number of records
+	fn len(&self) -> usize;
+	/// whether there are no records
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	/// removes all records
+	fn clear(&self) -> Result<()>;
+	/// changes of the tree from now on, the iterator blocks until the next one
+	fn watch(&self) -> Box<dyn Iterator<Item = Event> + Send>;
+	/// makes the writes durable
+	fn flush(&self) -> Result<()>;
+}
+
+/// why a transaction stopped, see [`Backend::transaction`]
+#[derive(Debug)]
+pub enum TxError {
+	/// the transaction gave up, nothing is written
+	Abort,
+	/// a concurrent transaction got in the way, the transaction is run again
+	Conflict,
+	/// the storage failed, nothing is written
+	Storage(Error),
+}
+
+impl From<Error> for TxError {
+	fn from(e: Error) -> Self {
+		TxError::Storage(e)
+	}
+}
+
+/// result of an operation in a transaction
+pub type TxResult<T> = std::result::Result<T, TxError>;
+
+/// a tree in a transaction, see [`Backend::transaction`]
+pub trait TxView {
+	/// the value of the key, including writes of the transaction
+	fn get(&self, key: &[u8]) -> TxResult<Option<Vec<u8>>>;
+	/// sets the value of the key
+	fn insert(&self, key: &[u8], value: &[u8]) -> TxResult<()>;
+	/// removes the key
+	fn remove(&self, key: &[u8]) -> TxResult<()>;
+}
+
+/// a transaction body, gets views of the trees in the order they were named
+pub type TxBody<'a> = dyn Fn(&[&dyn TxView]) -> TxResult<()> + 'a;
+
+/// a storage backend, a set of named trees
+pub trait Backend: Send + Sync {
+	/// opens (or creates) the tree
+	fn open_tree(&self, name: &str) -> Result<Arc<dyn RawTree>>;
+	/// names of all trees
+	fn tree_names(&self) -> Vec<String>;
+	/// a new unique id, ids grow
+	fn generate_id(&self) -> Result<u64>;
+	/// makes sure ids generated from now on are at least `next`, e.g. after copying data
+	fn reserve_ids(&self, next: u64) -> Result<()>;
+	/// makes all writes durable
+	fn flush(&self) -> Result<()>;
+	/// runs `body` on the named trees atomically, returns `false` if it aborted
+	///
+	/// `body` may run several times. the trees are locked meanwhile, so it mustn't
+	/// touch them outside of the transaction. always name the trees in the same
+	/// order to avoid deadlocks
+	fn transaction(&self, trees: &[&str], body: &TxBody) -> Result<bool>;
+}
+
+/// where the data are stored
+#[derive(Debug, Clone)]
+pub enum Storage {
+	/// a sled database in the directory
+	Sled(PathBuf),
+	/// a SQLite database in the file
+	Sqlite(PathBuf),
+	/// in memory, lost when the server stops
+	Memory,
+}
+
+impl Storage {
+	/// `memory`, `sqlite:<file>` or the directory of a sled database, see `DATABASE_URL`
+	pub fn parse(url: &str) -> Self {
+		match url {
+			"memory" => Storage::Memory,
+			url if url.starts_with("sqlite:") => Storage::Sqlite(PathBuf::from(&url["sqlite:".len()..])),
+			path => Storage::Sled(PathBuf::from(path)),
+		}
+	}
+}
+
+/// a handle to the storage backend, clones share it
+///
+/// writes go through a gate which [`Store::snapshot`] closes while it reads
+#[derive(Clone)]
+pub struct Store(Arc<Gated>);
+
+/// contents of all trees, see [`Store::snapshot`]
+pub type Snapshot = Vec<(String, Vec<Record>)>;
+
+impl Store {
+	/// wraps a backend
+	pub fn new<B: Backend + 'static>(backend: B) -> Self {
+		Store(Arc::new(Gated { backend: Box::new(backend), gate: Arc::default() }))
+	}
+
+	/// all records of all trees as of a single moment
+	///
+	/// writes and transactions wait until it's read, reads go on
+	pub fn snapshot(&self) -> Result<Snapshot> {
+		let _paused = locked!(self.0.gate.write());
+		let mut trees = vec![];
+
+		for name in self.0.backend.tree_names() {
+			let records = self.0.backend.open_tree(&name)?.iter().collect::<Result<Vec<_>>>()?;
+			trees.push((name, records));
+		}
+
+		Ok(trees)
+	}
+
+	/// opens the storage
+	pub fn open(storage: &Storage) -> Result<Self> {
+		match storage {
+			Storage::Sled(path) => Ok(Store::new(SledBackend(sled::open(path)?))),
+			Storage::Sqlite(path) => Ok(Store::new(SqliteBackend::open(path)?)),
+			Storage::Memory => Ok(Store::memory()),
+		}
+	}
+
+	/// a new empty store in memory
+	pub fn memory() -> Self {
+		Store::new(MemoryBackend::default())
+	}
+}
+
+impl Deref for Store {
+	type Target = dyn Backend;
+
+	fn deref(&self) -> &Self::Target {
+		&*self.0
+	}
+}
+
+/// a backend whose writes can be paused, see [`Store::snapshot`]
+struct Gated {
+	backend: Box<dyn Backend>,
+	/// held for reading by writes, for writing by snapshots
+	gate:    Arc<RwLock<()>>,
+}
+
+/// a tree of a [`Gated`] backend
+struct GatedTree {
+	tree: Arc<dyn RawTree>,
+	gate: Arc<RwLock<()>>,
+}
+
+impl RawTree for GatedTree {
+	fn name(&self) -> &str {
+		self.tree.name()
+	}
+
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		self.tree.get(key)
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+		let _open = locked!(self.gate.read());
+		self.tree.insert(key, value)
+	}
+
+	fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		let _open = locked!(self.gate.read());
+		self.tree.remove(key)
+	}
+
+	fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> Result<bool> {
+		let _open = locked!(self.gate.read());
+		self.tree.compare_and_swap(key, old, new)
+	}
+
+	fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn DoubleEndedIterator<Item = Result<Record>>> {
+		self.tree.range(range)
+	}
+
+	fn len(&self) -> usize {
+		self.tree.len()
+	}
+
+	fn clear(&self) -> Result<()> {
+		let _open = locked!(self.gate.read());
+		self.tree.clear()
+	}
+
+	fn watch(&self) -> Box<dyn Iterator<Item = Event> + Send> {
+		self.tree.watch()
+	}
+
+	fn flush(&self) -> Result<()> {
+		self.tree.flush()
+	}
+}
+
+impl Backend for Gated {
+	fn open_tree(&self, name: &str) -> Result<Arc<dyn RawTree>> {
+		Ok(Arc::new(GatedTree { tree: self.backend.open_tree(name)?, gate: self.gate.clone() }))
+	}
+
+	fn tree_names(&self) -> Vec<String> {
+		self.backend.tree_names()
+	}
+
+	fn generate_id(&self) -> Result<u64> {
+		self.backend.generate_id()
+	}
+
+	fn reserve_ids(&self, next: u64) -> Result<()> {
+		self.backend.reserve_ids(next)
+	}
+
+	fn flush(&self) -> Result<()> {
+		self.backend.flush()
+	}
+
+	fn transaction(&self, trees: &[&str], body: &TxBody) -> Result<bool> {
+		let _open = locked!(self.gate.read());
+		self.backend.transaction(trees, body)
+	}
+}
+
+/// a backend storing the trees in a sled database
+pub struct SledBackend(pub sled::Db);
+
+/// a tree of a sled database
+struct SledTree {
+	tree: sled::Tree,
+	name: String,
+}
+
+impl RawTree for SledTree {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		Ok(self.tree.get(key)?.map(|v| v.to_vec()))
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+		Ok(self.tree.insert(key, value)?.map(|v| v.to_vec()))
+	}
+
+	fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		Ok(self.tree.remove(key)?.map(|v| v.to_vec()))
+	}
+
+	fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> Result<bool> {
+		Ok(self.tree.compare_and_swap(key, old, new)?.is_ok())
+	}
+
+	fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn DoubleEndedIterator<Item = Result<Record>>> {
+		Box::new(self.tree.range(range).map(|res| Ok(res.map(|(k, v)| (k.to_vec(), v.to_vec()))?)))
+	}
+
+	fn len(&self) -> usize {
+		self.tree.len()
+	}
+
+	fn clear(&self) -> Result<()> {
+		Ok(self.tree.clear()?)
+	}
+
+	fn watch(&self) -> Box<dyn Iterator<Item = Event> + Send> {
+		Box::new(self.tree.watch_prefix(vec![]).map(|event| match event {
+			sled::Event::Insert(k, v) => Event::Insert(k.to_vec(), v.to_vec()),
+			sled::Event::Remove(k) => Event::Remove(k.to_vec()),
+		}))
+	}
+
+	fn flush(&self) -> Result<()> {
+		self.tree.flush()?;
+		Ok(())
+	}
+}
+
+impl TxView for TransactionalTree {
+	fn get(&self, key: &[u8]) -> TxResult<Option<Vec<u8>>> {
+		Ok(conflictable(TransactionalTree::get(self, key))?.map(|v| v.to_vec()))
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> TxResult<()> {
+		conflictable(TransactionalTree::insert(self, key, value)).map(|_| ())
+	}
+
+	fn remove(&self, key: &[u8]) -> TxResult<()> {
+		conflictable(TransactionalTree::remove(self, key)).map(|_| ())
+	}
+}
+
+/// converts errors of sled transaction operations, whose type isn't public
+fn conflictable<T, E>(result: std::result::Result<T, E>) -> TxResult<T>
+where
+	ConflictableTransactionError<()>: From<E>,
+{
+	result.map_err(|e| match ConflictableTransactionError::<()>::from(e) {
+		ConflictableTransactionError::Abort(()) => TxError::Abort,
+		ConflictableTransactionError::Conflict => TxError::Conflict,
+		ConflictableTransactionError::Storage(e) => TxError::Storage(e.into()),
+	})
+}
+
+/// runs a transaction body on sled views, a storage error is kept in `failed`
+fn run(body: &TxBody, views: &[&TransactionalTree], failed: &RefCell<Option<Error>>) -> ConflictableTransactionResult<(), ()> {
+	let views = views.iter().map(|v| *v as &dyn TxView).collect::<Vec<_>>();
+
+	match body(&views) {
+		Ok(()) => Ok(()),
+		Err(TxError::Abort) => Err(ConflictableTransactionError::Abort(())),
+		Err(TxError::Conflict) => Err(ConflictableTransactionError::Conflict),
+		Err(TxError::Storage(e)) => {
+			*failed.borrow_mut() = Some(e);
+			Err(ConflictableTransactionError::Abort(()))
+		}
+	}
+}
+
+/// runs `$go` in a sled transaction over the slice of trees, matching it against the tuples
+///
+/// more trees than the longest tuple are an error
+macro_rules! tuple_transactions {
+	($trees:expr, $go:ident; $(($($t:ident),+)),+ $(,)?) => {
+		match $trees {
+			[a] => a.transaction(|a| $go(&[a])),
+			$([$($t),+] => ($($t),+).transaction(|($($t),+)| $go(&[$($t),+])),)+
+			trees => return Err(Error::Io(format!("transactions over {} trees aren't supported", trees.len()).into())),
+		}
+	};
+}
+
+impl Backend for SledBackend {
+	fn open_tree(&self, name: &str) -> Result<Arc<dyn RawTree>> {
+		Ok(Arc::new(SledTree { tree: self.0.open_tree(name)?, name: name.to_string() }))
+	}
+
+	fn tree_names(&self) -> Vec<String> {
+		self.0.tree_names().iter().map(|n| String::from_utf8_lossy(n).to_string()).collect()
+	}
+
+	fn generate_id(&self) -> Result<u64> {
+		Ok(self.0.generate_id()?)
+	}
+
+	/// sled can't set its counter, the ids up to `next` are used up
+	fn reserve_ids(&self, next: u64) -> Result<()> {
+		while self.0.generate_id()? + 1 < next {}
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<()> {
+		self.0.flush()?;
+		Ok(())
+	}
+
+	fn transaction(&self, trees: &[&str], body: &TxBody) -> Result<bool> {
+		let trees = trees.iter().map(|n| self.0.open_tree(n)).collect::<sled::Result<Vec<sled::Tree>>>()?;
+		let failed = RefCell::new(None);
+
+		let go = |views: &[&TransactionalTree]| run(body, views, &failed);
+
+		// sled has transactions over a single tree and over tuples of trees
+		let result = tuple_transactions!(trees.as_slice(), go;
+			(a, b),
+			(a, b, c),
+			(a, b, c, d),
+			(a, b, c, d, e),
+			(a, b, c, d, e, f),
+			(a, b, c, d, e, f, g),
+			(a, b, c, d, e, f, g, h),
+			(a, b, c, d, e, f, g, h, i),
+			(a, b, c, d, e, f, g, h, i, j),
+			(a, b, c, d, e, f, g, h, i, j, k),
+			(a, b, c, d, e, f, g, h, i, j, k, l),
+		);
+
+		match result {
+			Ok(()) => Ok(true),
+			Err(TransactionError::Abort(())) => match failed.into_inner() {
+				Some(e) => Err(e),
+				None => Ok(false),
+			},
+			Err(TransactionError::Storage(e)) => Err(e.into()),
+		}
+	}
+}
+
+/// a backend keeping the trees in memory, the data are lost when it's dropped
+#[derive(Default)]
+pub struct MemoryBackend {
+	trees: Mutex<BTreeMap<String, Arc<MemoryTree>>>,
+	ids:   AtomicU64,
+	/// held for reading by writes and for writing by transactions
+	lock:  Arc<RwLock<()>>,
+}
+
+/// a tree of the memory backend
+struct MemoryTree {
+	name:     String,
+	data:     RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+	watchers: Mutex<Vec<mpsc::Sender<Event>>>,
+	lock:     Arc<RwLock<()>>,
+}
+
+impl MemoryTree {
+	/// writes (or with `None` removes) the value, returns the previous one
+	fn write(&self, data: &mut BTreeMap<Vec<u8>, Vec<u8>>, key: &[u8], value: Option<&[u8]>) -> Option<Vec<u8>> {
+		let (old, event) = match value {
+			Some(value) => (data.insert(key.to_vec(), value.to_vec()), Event::Insert(key.to_vec(), value.to_vec())),
+			None => (data.remove(key), Event::Remove(key.to_vec())),
+		};
+
+		locked!(self.watchers.lock()).retain(|w| w.send(event.clone()).is_ok());
+		old
+	}
+}
+
+impl RawTree for MemoryTree {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		Ok(locked!(self.data.read()).get(key).cloned())
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+		let _write = locked!(self.lock.read());
+		Ok(self.write(&mut locked!(self.data.write()), key, Some(value)))
+	}
+
+	fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		let _write = locked!(self.lock.read());
+		Ok(self.write(&mut locked!(self.data.write()), key, None))
+	}
+
+	fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> Result<bool> {
+		let _write = locked!(self.lock.read());
+		let mut data = locked!(self.data.write());
+
+		if data.get(key).map(|v| v.as_slice()) != old {
+			return Ok(false);
+		}
+
+		self.write(&mut data, key, new);
+		Ok(true)
+	}
+
+	fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn DoubleEndedIterator<Item = Result<Record>>> {
+		// an empty range would panic in BTreeMap::range
+		let empty = match (&range.0, &range.1) {
+			(Bound::Included(a), Bound::Excluded(b)) | (Bound::Excluded(a), Bound::Included(b)) | (Bound::Excluded(a), Bound::Excluded(b)) => a >= b,
+			(Bound::Included(a), Bound::Included(b)) => a > b,
+			_ => false,
+		};
+
+		if empty {
+			return Box::new(Vec::new().into_iter());
+		}
+
+		let records = locked!(self.data.read()).range(range).map(|(k, v)| Ok((k.clone(), v.clone()))).collect::<Vec<_>>();
+		Box::new(records.into_iter())
+	}
+
+	fn len(&self) -> usize {
+		locked!(self.data.read()).len()
+	}
+
+	fn clear(&self) -> Result<()> {
+		let _write = locked!(self.lock.read());
+		let mut data = locked!(self.data.write());
+
+		for key in data.keys().cloned().collect::<Vec<_>>() {
+			self.write(&mut data, &key, None);
+		}
+
+		Ok(())
+	}
+
+	fn watch(&self) -> Box<dyn Iterator<Item = Event> + Send> {
+		let (tx, rx) = mpsc::channel();
+		locked!(self.watchers.lock()).push(tx);
+
+		Box::new(rx.into_iter())
+	}
+
+	fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// a tree of the memory backend in a transaction, writes are applied on commit
+struct MemoryView<'a> {
+	tree:   &'a MemoryTree,
+	writes: RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl<'a> TxView for MemoryView<'a> {
+	fn get(&self, key: &[u8]) -> TxResult<Option<Vec<u8>>> {
+		match self.writes.borrow().get(key) {
+			Some(written) => Ok(written.clone()),
+			None => Ok(self.tree.get(key)?),
+		}
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> TxResult<()> {
+		self.writes.borrow_mut().insert(key.to_vec(), Some(value.to_vec()));
+		Ok(())
+	}
+
+	fn remove(&self, key: &[u8]) -> TxResult<()> {
+		self.writes.borrow_mut().insert(key.to_vec(), None);
+		Ok(())
+	}
+}
+
+impl MemoryBackend {
+	/// the tree, created if it doesn't exist
+	fn tree(&self, name: &str) -> Arc<MemoryTree> {
+		let mut trees = locked!(self.trees.lock());
+		let tree = trees.entry(name.to_string()).or_insert_with(|| {
+			Arc::new(MemoryTree {
+				name:     name.to_string(),
+				data:     RwLock::default(),
+				watchers: Mutex::default(),
+				lock:     self.lock.clone(),
+			})
+		});
+
+		tree.clone()
+	}
+}
+
+impl Backend for MemoryBackend {
+	fn open_tree(&self, name: &str) -> Result<Arc<dyn RawTree>> {
+		Ok(self.tree(name))
+	}
+
+	fn tree_names(&self) -> Vec<String> {
+		locked!(self.trees.lock()).keys().cloned().collect()
+	}
+
+	fn generate_id(&self) -> Result<u64> {
+		Ok(self.ids.fetch_add(1, Ordering::SeqCst))
+	}
+
+	fn reserve_ids(&self, next: u64) -> Result<()> {
+		self.ids.fetch_max(next, Ordering::SeqCst);
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+
+	/// transactions run one at a time and exclude all other writes
+	fn transaction(&self, trees: &[&str], body: &TxBody) -> Result<bool> {
+		let trees = trees.iter().map(|n| self.tree(n)).collect::<Vec<_>>();
+		let _transaction = locked!(self.lock.write());
+
+		loop {
+			let views = trees.iter().map(|tree| MemoryView { tree, writes: RefCell::default() }).collect::<Vec<_>>();
+
+			match body(&views.iter().map(|v| v as &dyn TxView).collect::<Vec<_>>()) {
+				Ok(()) => (),
+				Err(TxError::Abort) => return Ok(false),
+				Err(TxError::Conflict) => continue,
+				Err(TxError::Storage(e)) => return Err(e),
+			}
+
+			for view in views {
+				let mut data = locked!(view.tree.data.write());
+
+				for (key, value) in view.writes.into_inner() {
+					view.tree.write(&mut data, &key, value.as_deref());
+				}
+			}
+
+			return Ok(true);
+		}
+	}
+}
@@ -0,0 +1,351 @@
+//! a storage backend keeping the trees in a SQLite database
+//!
+//! all trees share the table `kv_records`, keys sort the same way as in sled
+//! since SQLite compares blobs byte by byte. next to the raw value every record
+//! has its value as JSON (`NULL` if it isn't representable) and every tree gets
+//! a view of the same name, so the data can be queried for reports, e.g.
+//! `SELECT json_extract(json, '$.name') FROM "reservation"`
+//!
+//! the schema of the database is versioned by `PRAGMA user_version` and
+//! upgraded when it's opened, see [`SCHEMA`]
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, TransactionBehavior};
+use rusqlite::types::Value;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use super::{Backend, Event, RawTree, Record, TxBody, TxError, TxResult, TxView};
+use crate::db::{Error, Result};
+
+/// upgrades of the schema of the database, the version is the position in the list
+pub const SCHEMA: &[&str] = &[
+	// 1: trees, records and ids
+	"CREATE TABLE kv_trees (name TEXT PRIMARY KEY NOT NULL);
+	CREATE TABLE kv_records (
+		tree  TEXT NOT NULL,
+		key   BLOB NOT NULL,
+		value BLOB NOT NULL,
+		json  TEXT,
+		PRIMARY KEY (tree, key)
+	) WITHOUT ROWID;
+	CREATE TABLE kv_ids (next INTEGER NOT NULL);
+	INSERT INTO kv_ids (next) VALUES (0);",
+];
+
+impl From<rusqlite::Error> for Error {
+	fn from(e: rusqlite::Error) -> Self {
+		Error::Io(Box::new(e))
+	}
+}
+
+/// the value as JSON, if it's representable
+fn json(value: &[u8]) -> Option<String> {
+	serde_cbor::from_slice::<serde_json::Value>(value).ok().map(|v| v.to_string())
+}
+
+/// a quoted SQL identifier
+fn ident(name: &str) -> String {
+	format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// a quoted SQL string
+fn literal(text: &str) -> String {
+	format!("'{}'", text.replace('\'', "''"))
+}
+
+/// upgrades the schema of the database to the latest version
+fn upgrade(conn: &mut Connection) -> Result<()> {
+	let version: usize = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+
+	if version > SCHEMA.len() {
+		return Err(Error::Schema { found: version as u32, supported: SCHEMA.len() as u32 });
+	}
+
+	for (i, sql) in SCHEMA.iter().enumerate().skip(version) {
+		let tx = conn.transaction()?;
+		tx.execute_batch(sql)?;
+		tx.pragma_update(None, "user_version", i + 1)?;
+		tx.commit()?;
+
+		log::info!("sqlite: upgraded the schema to version {}", i + 1);
+	}
+
+	Ok(())
+}
+
+/// reads the value of the key
+fn get(conn: &Connection, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+	let mut select = conn.prepare_cached("SELECT value FROM kv_records WHERE tree = ?1 AND key = ?2")?;
+	Ok(select.query_row(params![tree, key], |r| r.get(0)).optional()?)
+}
+
+/// writes (or with `None` removes) the value
+fn put(conn: &Connection, tree: &str, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+	match value {
+		Some(value) => conn
+			.prepare_cached("INSERT OR REPLACE INTO kv_records (tree, key, value, json) VALUES (?1, ?2, ?3, ?4)")?
+			.execute(params![tree, key, value, json(value)])?,
+		None => conn.prepare_cached("DELETE FROM kv_records WHERE tree = ?1 AND key = ?2")?.execute(params![tree, key])?,
+	};
+
+	Ok(())
+}
+
+/// a backend storing the trees in a SQLite database
+///
+/// there's a single connection, operations take turns
+pub struct SqliteBackend {
+	conn:  Arc<Mutex<Connection>>,
+	trees: Mutex<BTreeMap<String, Arc<SqliteTree>>>,
+}
+
+/// a tree of a SQLite database
+struct SqliteTree {
+	name:     String,
+	conn:     Arc<Mutex<Connection>>,
+	/// watchers in this process, changes made by others aren't seen
+	watchers: Mutex<Vec<mpsc::Sender<Event>>>,
+}
+
+impl SqliteTree {
+	/// tells the watchers about the change
+	fn notify(&self, event: Event) {
+		locked!(self.watchers.lock()).retain(|w| w.send(event.clone()).is_ok());
+	}
+
+	/// writes (or with `None` removes) the value, returns the previous one
+	fn write(&self, key: &[u8], value: Option<&[u8]>) -> Result<Option<Vec<u8>>> {
+		let conn = locked!(self.conn.lock());
+		let old = get(&conn, &self.name, key)?;
+		put(&conn, &self.name, key, value)?;
+		drop(conn);
+
+		self.notify(match value {
+			Some(value) => Event::Insert(key.to_vec(), value.to_vec()),
+			None => Event::Remove(key.to_vec()),
+		});
+
+		Ok(old)
+	}
+}
+
+impl RawTree for SqliteTree {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		get(&locked!(self.conn.lock()), &self.name, key)
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+		self.write(key, Some(value))
+	}
+
+	fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		self.write(key, None)
+	}
+
+	fn compare_and_swap(&self, key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> Result<bool> {
+		let conn = locked!(self.conn.lock());
+
+		if get(&conn, &self.name, key)?.as_deref() != old {
+			return Ok(false);
+		}
+
+		put(&conn, &self.name, key, new)?;
+		drop(conn);
+
+		self.notify(match new {
+			Some(value) => Event::Insert(key.to_vec(), value.to_vec()),
+			None => Event::Remove(key.to_vec()),
+		});
+
+		Ok(true)
+	}
+
+	fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn DoubleEndedIterator<Item = Result<Record>>> {
+		let mut sql = "SELECT key, value FROM kv_records WHERE tree = ?".to_string();
+		let mut bounds = vec![];
+
+		for (bound, inclusive, exclusive) in [(range.0, ">=", ">"), (range.1, "<=", "<")] {
+			match bound {
+				Bound::Included(key) => bounds.push((inclusive, key)),
+				Bound::Excluded(key) => bounds.push((exclusive, key)),
+				Bound::Unbounded => (),
+			}
+		}
+
+		for (op, _) in &bounds {
+			sql.push_str(&format!(" AND key {} ?", op));
+		}
+		sql.push_str(" ORDER BY key");
+
+		// the name is bound as text, keys as blobs
+		let values = std::iter::once(Value::Text(self.name.clone())).chain(bounds.into_iter().map(|(_, key)| Value::Blob(key)));
+
+		let conn = locked!(self.conn.lock());
+		let records = conn.prepare_cached(&sql).and_then(|mut select| {
+			let mut rows = select.query(params_from_iter(values))?;
+			let mut records = vec![];
+
+			while let Some(row) = rows.next()? {
+				records.push(Ok((row.get(0)?, row.get(1)?)));
+			}
+
+			Ok(records)
+		});
+
+		match records {
+			Ok(records) => Box::new(records.into_iter()),
+			Err(e) => Box::new(vec![Err(e.into())].into_iter()),
+		}
+	}
+
+	fn len(&self) -> usize {
+		locked!(self.conn.lock())
+			.query_row("SELECT COUNT(*) FROM kv_records WHERE tree = ?1", params![self.name], |r| r.get::<_, i64>(0))
+			.map_err(|e| log::error!("sqlite: failed to count the records of {}: {}", self.name, e))
+			.unwrap_or(0) as usize
+	}
+
+	fn clear(&self) -> Result<()> {
+		let keys = self.iter().map(|r| r.map(|(k, _)| k)).collect::<Result<Vec<_>>>()?;
+		locked!(self.conn.lock()).execute("DELETE FROM kv_records WHERE tree = ?1", params![self.name])?;
+
+		for key in keys {
+			self.notify(Event::Remove(key));
+		}
+
+		Ok(())
+	}
+
+	fn watch(&self) -> Box<dyn Iterator<Item = Event> + Send> {
+		let (tx, rx) = mpsc::channel();
+		locked!(self.watchers.lock()).push(tx);
+
+		Box::new(rx.into_iter())
+	}
+
+	fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// a tree of a SQLite database in a transaction, the changes are told to watchers on commit
+struct SqliteView<'a> {
+	tree:   &'a SqliteTree,
+	conn:   &'a Connection,
+	events: RefCell<Vec<Event>>,
+}
+
+impl<'a> TxView for SqliteView<'a> {
+	fn get(&self, key: &[u8]) -> TxResult<Option<Vec<u8>>> {
+		Ok(get(self.conn, &self.tree.name, key)?)
+	}
+
+	fn insert(&self, key: &[u8], value: &[u8]) -> TxResult<()> {
+		put(self.conn, &self.tree.name, key, Some(value))?;
+		self.events.borrow_mut().push(Event::Insert(key.to_vec(), value.to_vec()));
+		Ok(())
+	}
+
+	fn remove(&self, key: &[u8]) -> TxResult<()> {
+		put(self.conn, &self.tree.name, key, None)?;
+		self.events.borrow_mut().push(Event::Remove(key.to_vec()));
+		Ok(())
+	}
+}
+
+impl SqliteBackend {
+	/// opens (or creates) the database in the file and upgrades its schema
+	pub fn open(path: &Path) -> Result<Self> {
+		let mut conn = Connection::open(path)?;
+		conn.busy_timeout(Duration::from_secs(5))?;
+		upgrade(&mut conn)?;
+
+		Ok(SqliteBackend { conn: Arc::new(Mutex::new(conn)), trees: Mutex::default() })
+	}
+
+	/// the tree, created if it doesn't exist
+	fn tree(&self, name: &str) -> Result<Arc<SqliteTree>> {
+		let mut trees = locked!(self.trees.lock());
+
+		if let Some(tree) = trees.get(name) {
+			return Ok(tree.clone());
+		}
+
+		let conn = locked!(self.conn.lock());
+		conn.execute("INSERT OR IGNORE INTO kv_trees (name) VALUES (?1)", params![name])?;
+		conn.execute_batch(&format!(
+			"CREATE VIEW IF NOT EXISTS {} AS SELECT key, json FROM kv_records WHERE tree = {}",
+			ident(name),
+			literal(name),
+		))?;
+
+		let tree = Arc::new(SqliteTree { name: name.to_string(), conn: self.conn.clone(), watchers: Mutex::default() });
+		trees.insert(name.to_string(), tree.clone());
+
+		Ok(tree)
+	}
+}
+
+impl Backend for SqliteBackend {
+	fn open_tree(&self, name: &str) -> Result<Arc<dyn RawTree>> {
+		Ok(self.tree(name)?)
+	}
+
+	fn tree_names(&self) -> Vec<String> {
+		let conn = locked!(self.conn.lock());
+		let names = conn
+			.prepare_cached("SELECT name FROM kv_trees ORDER BY name")
+			.and_then(|mut select| select.query_map([], |r| r.get(0))?.collect::<rusqlite::Result<Vec<String>>>());
+
+		names.map_err(|e| log::error!("sqlite: failed to list the trees: {}", e)).unwrap_or_default()
+	}
+
+	fn generate_id(&self) -> Result<u64> {
+		let conn = locked!(self.conn.lock());
+		Ok(conn.query_row("UPDATE kv_ids SET next = next + 1 RETURNING next - 1", [], |r| r.get::<_, i64>(0))? as u64)
+	}
+
+	fn reserve_ids(&self, next: u64) -> Result<()> {
+		locked!(self.conn.lock()).execute("UPDATE kv_ids SET next = MAX(next, ?1)", params![next as i64])?;
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+
+	/// transactions are SQLite transactions, they run one at a time
+	fn transaction(&self, trees: &[&str], body: &TxBody) -> Result<bool> {
+		let trees = trees.iter().map(|n| self.tree(n)).collect::<Result<Vec<_>>>()?;
+		let mut conn = locked!(self.conn.lock());
+
+		loop {
+			let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+			let views = trees.iter().map(|tree| SqliteView { tree, conn: &tx, events: RefCell::default() }).collect::<Vec<_>>();
+
+			match body(&views.iter().map(|v| v as &dyn TxView).collect::<Vec<_>>()) {
+				Ok(()) => (),
+				Err(TxError::Abort) => return Ok(false),
+				Err(TxError::Conflict) => continue,
+				Err(TxError::Storage(e)) => return Err(e),
+			}
+
+			let events = views.into_iter().map(|v| v.events.into_inner()).collect::<Vec<_>>();
+			tx.commit()?;
+
+			for (tree, events) in trees.iter().zip(events) {
+				events.into_iter().for_each(|e| tree.notify(e));
+			}
+
+			return Ok(true);
+		}
+	}
+}
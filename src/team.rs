@@ -0,0 +1,156 @@
+//! a module containing team management
+//!
+//! reservations can be owned by a team, in which case any member
+//! of the team can edit or cancel them. team membership is managed
+//! by the team admins and superadmins
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use std::cell::Cell;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{Noob, Superadmin};
+
+use crate::db::{Database, table::Teams};
+
+use crate::models::Team;
+use crate::response::Error;
+
+/// whether the user may manage the team
+fn can_manage(team: &Team, usr: &AuthToken<Noob>) -> bool {
+	team.is_admin(&usr.user.email) || usr.is::<Superadmin>()
+}
+
+/// changes the team if the user is allowed to manage it
+///
+/// the check and the change are a single update of the current version,
+/// so concurrent changes of the team aren't lost
+fn modify<F: Fn(&mut Team)>(id: u64, db: &mut Database<Teams>, usr: &AuthToken<Noob>, fun: F) -> Result<(), Error> {
+	let outcome = Cell::new(Ok(()));
+
+	db.write().update::<_, Team, _>(id, |team| {
+		let mut team = match team {
+			Some(team) => team,
+			None => {
+				outcome.set(Err(Error::NotFound));
+				return None;
+			}
+		};
+
+		if !can_manage(&team, usr) {
+			outcome.set(Err(Error::Forbidden));
+			return Some(team);
+		}
+
+		outcome.set(Ok(()));
+		fun(&mut team);
+		Some(team)
+	})?;
+
+	outcome.into_inner()
+}
+
+/// vrací všechny týmy
+///
+/// GET /teams "application/json"
+#[get("/teams", format = "application/json")]
+pub fn list(db: Database<Teams>, _u: AuthToken<Noob>) -> Result<Json<Vec<(u64, Team)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// vrátí JSON daného týmu
+///
+/// GET /teams/<id>
+///
+/// parametry:
+/// - `id`: identifikátor týmu
+#[get("/teams/<id>")]
+pub fn get(id: u64, db: Database<Teams>, _u: AuthToken<Noob>) -> Result<Json<Team>, Error> {
+	db.read().get(id)?.map(Json).ok_or(Error::NotFound)
+}
+
+/// vytvoří tým, vrací jeho id
+///
+/// POST /teams application/json
+///
+/// data: [`Team`]
+#[post("/teams", data = "<input>")]
+pub fn post(input: Json<Team>, mut db: Database<Teams>, _u: AuthToken<Superadmin>) -> Option<Json<u64>> {
+	let id = db.get_key().ok()?;
+
+	db.write().insert(id, input.into_inner()).ok()?;
+
+	Some(Json(id))
+}
+
+/// smaže tým
+///
+/// DELETE /teams/<id>
+///
+/// parametry:
+/// - `id`: identifikátor týmu
+#[delete("/teams/<id>")]
+pub fn delete(id: u64, mut db: Database<Teams>, _u: AuthToken<Superadmin>) -> Option<()> {
+	db.write().delete(id).ok()?.map(|_| ())
+}
+
+/// přidá člena do týmu
+///
+/// POST /teams/<id>/members/<email>
+///
+/// parametry:
+/// - `id`: identifikátor týmu
+/// - `email`: email nového člena
+#[post("/teams/<id>/members/<email>")]
+pub fn add_member(id: u64, email: String, mut db: Database<Teams>, usr: AuthToken<Noob>) -> Result<(), Error> {
+	modify(id, &mut db, &usr, |team| {
+		if !team.members.contains(&email) {
+			team.members.push(email.clone());
+		}
+	})
+}
+
+/// odebere člena z týmu
+///
+/// DELETE /teams/<id>/members/<email>
+///
+/// parametry:
+/// - `id`: identifikátor týmu
+/// - `email`: email odebíraného člena
+#[delete("/teams/<id>/members/<email>")]
+pub fn remove_member(id: u64, email: String, mut db: Database<Teams>, usr: AuthToken<Noob>) -> Result<(), Error> {
+	modify(id, &mut db, &usr, |team| team.members.retain(|m| m != &email))
+}
+
+/// udělá z uživatele správce týmu
+///
+/// POST /teams/<id>/admins/<email>
+///
+/// parametry:
+/// - `id`: identifikátor týmu
+/// - `email`: email nového správce
+#[post("/teams/<id>/admins/<email>")]
+pub fn add_admin(id: u64, email: String, mut db: Database<Teams>, usr: AuthToken<Noob>) -> Result<(), Error> {
+	modify(id, &mut db, &usr, |team| {
+		if !team.admins.contains(&email) {
+			team.admins.push(email.clone());
+		}
+	})
+}
+
+/// odebere správce týmu
+///
+/// DELETE /teams/<id>/admins/<email>
+///
+/// parametry:
+/// - `id`: identifikátor týmu
+/// - `email`: email odebíraného správce
+#[delete("/teams/<id>/admins/<email>")]
+pub fn remove_admin(id: u64, email: String, mut db: Database<Teams>, usr: AuthToken<Noob>) -> Result<(), Error> {
+	modify(id, &mut db, &usr, |team| team.admins.retain(|a| a != &email))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, get, post, delete, add_member, remove_member, add_admin, remove_admin]
+}
@@ -0,0 +1,140 @@
+//! a module containing the trash of deleted reservations
+//!
+//! deleting a reservation (see [`crate::booking::delete`]) doesn't remove it, it's
+//! cancelled together with who deleted it and when, see [`discard`]. it's gone from all
+//! listings and conflict checks, but approvers can look into the trash and restore
+//! it as long as its term is still free. the background job purges reservations
+//! which have been in the trash longer than `TRASH_RETENTION_DAYS`, see [`purge`]
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, Duration, offset::Utc};
+
+use std::env;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Approver;
+
+use crate::db::{
+	self,
+	Database,
+	table::Reservations,
+	table::Blackouts,
+	table::Holds,
+};
+
+use crate::booking::{self, Save};
+use crate::models::Reservation;
+use crate::response::Error;
+use crate::store::Store;
+
+/// how long deleted reservations stay in the trash, `TRASH_RETENTION_DAYS` (30 by default)
+pub fn retention() -> Duration {
+	Duration::days(env::var("TRASH_RETENTION_DAYS").ok().and_then(|d| d.parse().ok()).unwrap_or(30))
+}
+
+/// cancels the reservation if its version tag is still `tag`, see [`booking::save`]
+pub(crate) fn discard(
+	reservations: &Database<Reservations>,
+	id: u64,
+	(event, tag): (&Reservation, &str),
+	actor: &str,
+	rejected: bool,
+) -> Result<(), Error> {
+	let mut deleted = event.clone();
+	deleted.cancelled = true;
+	deleted.deleted_at = Some(Utc::now());
+	deleted.deleted_by = Some(actor.to_string());
+	deleted.rejected = rejected;
+
+	let action = if rejected { "reservation.reject" } else { "reservation.delete" };
+	let write = Save { id, before: Some((event, tag)), after: &deleted, actor, action, from: None };
+
+	booking::save(write, reservations, || Ok(()))
+}
+
+/// background job purging reservations which have been in the trash longer than `retention`
+///
+/// their revisions are kept, see [`crate::history`]
+pub fn purge(store: &Store, retention: Duration) -> Result<(), Error> {
+	let cutoff = Utc::now() - retention;
+	let mut db = Database::<Reservations>::open(store).ok_or(Error::Database)?;
+
+	let old = db.read().find_range("deleted_at", ..cutoff)?.map(|(id, _)| id).collect::<Vec<u64>>();
+	let mut purged = 0;
+
+	for id in old {
+		let tag = match db.read().get_tagged(id)? {
+			Some((r, tag)) if r.cancelled => tag,
+			_ => continue,
+		};
+
+		match db.write().swap_tagged(id, &tag, None) {
+			Ok(()) => purged += 1,
+			// restored in the meantime
+			Err(db::Error::Conflict) | Err(db::Error::NotFound) => (),
+			Err(e) => return Err(e.into()),
+		}
+	}
+
+	if purged > 0 {
+		log::info!("trash: purged {} reservations", purged);
+	}
+
+	Ok(())
+}
+
+/// vrací smazané rezervace v koši
+///
+/// GET /trash "application/json"
+#[get("/trash", format = "application/json")]
+pub fn list(db: Database<Reservations>, _u: AuthToken<Approver>) -> Result<Json<Vec<(u64, Reservation)>>, Error> {
+	Ok(Json(db.read().find_range::<DateTime<Utc>, _>("deleted_at", ..)?.collect::<Vec<(u64, Reservation)>>()))
+}
+
+/// obnoví smazanou rezervaci z koše
+///
+/// POST /trash/<id>/restore
+///
+/// rezervace se obnoví pod svým původním id a ve stavu, v jakém byla smazána,
+/// pouze pokud je její termín stále volný
+///
+/// parametry:
+/// - `id`: id smazané rezervace
+#[post("/trash/<id>/restore")]
+pub fn restore(
+	id: u64,
+	db: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	usr: AuthToken<Approver>,
+) -> Result<(), Error> {
+	let (deleted, tag) = db.read().get_tagged(id)?.filter(|(r, _)| r.cancelled).ok_or(Error::NotFound)?;
+
+	let mut restored = deleted.clone();
+	restored.cancelled = false;
+	restored.deleted_at = None;
+	restored.deleted_by = None;
+	restored.rejected = false;
+
+	let write = Save {
+		id,
+		before: Some((&deleted, &tag)),
+		after:  &restored,
+		actor:  &usr.user.email,
+		action: "reservation.restore",
+		from:   None,
+	};
+	booking::save(write, &db, || {
+		let (rooms, begin_time, end_time) = (restored.rooms, restored.begin_time, restored.end_time);
+		match booking::has_conflict(rooms, begin_time, end_time, &restored.author, &db, &blackouts, &holds)? {
+			true => Err(Error::Conflict),
+			false => Ok(()),
+		}
+	})
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, restore]
+}
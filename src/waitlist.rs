@@ -0,0 +1,316 @@
+//! a module containing the waitlist
+//!
+//! when a slot is taken, users can join a waitlist for it. whenever a blocking
+//! reservation is cancelled, rejected or changed, [`release`] goes through
+//! the waitlist in the order of joining and either promotes the first request
+//! whose slot got free to a pending reservation, or, if `WAITLIST_OFFER_MINUTES`
+//! is set, offers the slot to its author for a limited time
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{Duration, offset::Utc};
+
+use std::env;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::{Noob, Approver};
+
+use crate::db::{
+	self,
+	Database,
+	table::Waitlist,
+	table::Reservations,
+	table::Blackouts,
+	table::Policies,
+	table::Quotas,
+	table::Teams,
+	table::Users,
+	table::Delegations,
+	table::Notifications,
+	table::Holds,
+};
+
+use crate::booking::{Origin, Save};
+use crate::models::{NewReservation, Reservation, WaitlistEntry};
+use crate::response::Error;
+use crate::store::Store;
+use crate::{booking, delegation, history, notify, quota};
+
+/// for how long a free slot is offered, `None` means automatic promotion
+fn offer_duration() -> Option<Duration> {
+	env::var("WAITLIST_OFFER_MINUTES").ok()?.parse().ok().map(Duration::minutes)
+}
+
+/// whether the user may manage the waitlist entry, i.e. is its author or their delegate
+///
+/// whoever joined on behalf of the author loses the entry once the delegation is revoked
+fn owns(entry: &WaitlistEntry, email: &str, delegations: &Database<Delegations>) -> db::Result<bool> {
+	Ok(entry.reservation.author == email || delegation::allows(delegations, &entry.reservation.author, email)?)
+}
+
+/// whether the slot of the reservation is free of approved reservations, blackouts and holds
+fn is_free(
+	reservation: &Reservation,
+	reservations: &Database<Reservations>,
+	blackouts: &Database<Blackouts>,
+	holds: &Database<Holds>,
+) -> Result<bool, Error> {
+	let (rooms, begin_time, end_time) = (reservation.rooms, reservation.begin_time, reservation.end_time);
+
+	Ok(!booking::has_conflict(rooms, begin_time, end_time, &reservation.author, reservations, blackouts, holds)?)
+}
+
+/// saves the waitlisted request as a pending reservation and removes the entry, returns its id
+///
+/// a slot which is no longer free is an [`Error::Conflict`], an entry promoted
+/// (or left) in the meantime an [`Error::NotFound`]
+fn promote(
+	id: u64,
+	entry: &WaitlistEntry,
+	reservations: &Database<Reservations>,
+	blackouts: &Database<Blackouts>,
+	holds: &Database<Holds>,
+	notifications: &mut Database<Notifications>,
+	actor: &str,
+) -> Result<u64, Error> {
+	let new_id = reservations.get_key()?;
+
+	let write = Save {
+		id:     new_id,
+		before: None,
+		after:  &entry.reservation,
+		actor,
+		action: "reservation.promote",
+		from:   Some(Origin::Waitlist(id)),
+	};
+	booking::save(write, reservations, || match is_free(&entry.reservation, reservations, blackouts, holds)? {
+		true => Ok(()),
+		false => Err(Error::Conflict),
+	})?;
+
+	notify::queue_reservation(
+		notifications,
+		&entry.reservation,
+		"Your waitlisted booking of auditorium is now pending",
+		format!(
+			"The slot of {} from {} to {} got free, your request \"{}\" is now waiting for approval",
+			notify::room_names(entry.reservation.rooms),
+			entry.reservation.begin_time,
+			entry.reservation.end_time,
+			entry.reservation.name,
+		),
+	)
+	.ok_or(Error::Database)?;
+
+	Ok(new_id)
+}
+
+/// goes through the waitlist and promotes (or offers) requests whose slot got free
+///
+/// only the first request for a slot is promoted, later ones keep waiting.
+/// expired offers and requests for slots in the past are dropped
+pub fn release(
+	waitlist: &mut Database<Waitlist>,
+	reservations: &Database<Reservations>,
+	blackouts: &Database<Blackouts>,
+	holds: &Database<Holds>,
+	notifications: &mut Database<Notifications>,
+) -> Result<(), Error> {
+	let now = Utc::now();
+
+	let mut entries = waitlist.read().try_iter().collect::<Result<Vec<(u64, WaitlistEntry)>, _>>()?;
+	entries.sort_by_key(|(_, e)| e.created);
+
+	let (stale, entries): (Vec<_>, Vec<_>) = entries
+		.into_iter()
+		.partition(|(_, e)| e.reservation.end_time < now || e.offered_until.map(|u| u < now).unwrap_or(false));
+
+	for (id, _) in stale {
+		waitlist.write().delete(id)?;
+	}
+
+	// slots already offered to someone are held for them
+	let mut taken = entries
+		.iter()
+		.filter(|(_, e)| e.offered_until.is_some())
+		.map(|(_, e)| e.reservation.clone())
+		.collect::<Vec<Reservation>>();
+
+	for (id, mut entry) in entries.into_iter().filter(|(_, e)| e.offered_until.is_none()) {
+		let r = &entry.reservation;
+
+		if !is_free(r, reservations, blackouts, holds)? || taken.iter().any(|t| t.collides(r.rooms, r.begin_time, r.end_time)) {
+			continue;
+		}
+
+		taken.push(r.clone());
+
+		match offer_duration() {
+			Some(duration) => {
+				entry.offered_until = Some(now + duration);
+				waitlist.write().insert(id, &entry)?;
+
+				notify::queue_reservation(
+					notifications,
+					&entry.reservation,
+					"A waitlisted slot of auditorium is available",
+					format!(
+						"The slot of {} from {} to {} got free, accept it before {} to request the reservation \"{}\"",
+						notify::room_names(entry.reservation.rooms),
+						entry.reservation.begin_time,
+						entry.reservation.end_time,
+						now + duration,
+						entry.reservation.name,
+					),
+				)
+				.ok_or(Error::Database)?;
+			}
+			None => match promote(id, &entry, reservations, blackouts, holds, notifications, history::SYSTEM) {
+				// taken or accepted in the meantime
+				Err(Error::Conflict) | Err(Error::NotFound) => (),
+				promoted => {
+					promoted?;
+				}
+			},
+		}
+	}
+
+	Ok(())
+}
+
+/// background job dropping expired offers and offering their slots to the next in line
+pub fn expire(store: &Store) -> Result<(), Error> {
+	release(
+		&mut Database::<Waitlist>::open(store).ok_or(Error::Database)?,
+		&Database::<Reservations>::open(store).ok_or(Error::Database)?,
+		&Database::<Blackouts>::open(store).ok_or(Error::Database)?,
+		&Database::<Holds>::open(store).ok_or(Error::Database)?,
+		&mut Database::<Notifications>::open(store).ok_or(Error::Database)?,
+	)
+}
+
+/// vrací záznamy čekací listiny přihlášeného uživatele, schvalovatelům všechny
+///
+/// GET /waitlist "application/json"
+#[get("/waitlist", format = "application/json")]
+pub fn list(
+	db: Database<Waitlist>,
+	delegations: Database<Delegations>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<Vec<(u64, WaitlistEntry)>>, Error> {
+	let mut entries = vec![];
+
+	for record in db.read().try_iter() {
+		let (id, entry) = record?;
+
+		if usr.is::<Approver>() || owns(&entry, &usr.user.email, &delegations)? {
+			entries.push((id, entry));
+		}
+	}
+
+	Ok(Json(entries))
+}
+
+/// zapíše uživatele na čekací listinu pro obsazený termín, vrací id záznamu
+///
+/// volný termín se odmítne ([`Error::Free`]), rezervuje se přes `POST /events`
+///
+/// POST /waitlist application/json
+///
+/// data: [`NewReservation`]
+#[post("/waitlist", data = "<input>")]
+pub fn join(
+	input: Json<NewReservation>,
+	mut db: Database<Waitlist>,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	policies: Database<Policies>,
+	quotas: Database<Quotas>,
+	teams: Database<Teams>,
+	users: Database<Users>,
+	delegations: Database<Delegations>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<u64>, Error> {
+	let (owner, reservation) =
+		booking::prepare(input.into_inner(), &usr.user, &users, &delegations, &teams, &policies)?;
+
+	// a free slot would be promoted or offered right away, the id of the entry would mean nothing
+	if is_free(&reservation, &reservations, &blackouts, &holds)? {
+		return Err(Error::Free);
+	}
+
+	quota::check(&quotas, &reservations, &owner, &reservation, None)?;
+
+	let id = db.get_key()?;
+	db.write().insert(id, WaitlistEntry { reservation, created: Utc::now(), offered_until: None })?;
+
+	Ok(Json(id))
+}
+
+/// přijme nabídnutý termín, vrací id nové (neschválené) rezervace
+///
+/// POST /waitlist/<id>/accept
+///
+/// parametry:
+/// - `id`: identifikátor záznamu čekací listiny
+#[post("/waitlist/<id>/accept")]
+pub fn accept(
+	id: u64,
+	db: Database<Waitlist>,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	delegations: Database<Delegations>,
+	mut notifications: Database<Notifications>,
+	usr: AuthToken<Noob>,
+) -> Result<Json<u64>, Error> {
+	let entry = db.read().get(id)?.ok_or(Error::NotFound)?;
+
+	if !owns(&entry, &usr.user.email, &delegations)? {
+		return Err(Error::Forbidden);
+	}
+
+	match entry.offered_until {
+		Some(until) if until >= Utc::now() => (),
+		_ => return Err(Error::Conflict),
+	}
+
+	let new_id = promote(id, &entry, &reservations, &blackouts, &holds, &mut notifications, &usr.user.email)?;
+
+	Ok(Json(new_id))
+}
+
+/// odhlásí záznam z čekací listiny (nebo odmítne nabídnutý termín)
+///
+/// DELETE /waitlist/<id>
+///
+/// parametry:
+/// - `id`: identifikátor záznamu čekací listiny
+#[delete("/waitlist/<id>")]
+pub fn leave(
+	id: u64,
+	mut db: Database<Waitlist>,
+	reservations: Database<Reservations>,
+	blackouts: Database<Blackouts>,
+	holds: Database<Holds>,
+	delegations: Database<Delegations>,
+	mut notifications: Database<Notifications>,
+	usr: AuthToken<Noob>,
+) -> Result<(), Error> {
+	let entry = db.read().get(id)?.ok_or(Error::NotFound)?;
+
+	if !usr.is::<Approver>() && !owns(&entry, &usr.user.email, &delegations)? {
+		return Err(Error::Forbidden);
+	}
+
+	db.write().delete(id)?;
+
+	// a declined offer goes to the next in line
+	release(&mut db, &reservations, &blackouts, &holds, &mut notifications)
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, join, accept, leave]
+}
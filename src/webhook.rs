@@ -0,0 +1,233 @@
+//! a module containing outgoing webhooks
+//!
+//! superadmins subscribe urls to kinds of reservation changes (see [`crate::feed`]).
+//! every matching change is queued as a [`Delivery`] and POSTed as JSON by the
+//! [`dispatch`] job. the body is signed by HMAC-SHA256 with the secret of the
+//! subscription, the signature is sent in the `X-Webhook-Signature` header as
+//! `sha256=<hex>`. failed deliveries are retried with exponential backoff,
+//! deliveries stay in the database as a log for 30 days
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use chrono::{DateTime, Duration, offset::Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use std::time;
+
+use crate::auth::AuthToken;
+use crate::auth::roles::Superadmin;
+
+use crate::db::{
+	Database,
+	table::Webhooks,
+	table::Deliveries,
+	table::Audit,
+};
+
+use crate::audit;
+use crate::models::{Change, Delivery, DeliveryStatus, NewWebhook, Webhook};
+use crate::response::Error;
+use crate::store::Store;
+
+/// after how many attempts a delivery fails for good
+pub const MAX_ATTEMPTS: u32 = 8;
+
+/// delay before the first retry, doubles with every attempt
+const BACKOFF_SECONDS: i64 = 30;
+
+/// how long delivered and failed deliveries are kept
+const LOG_DAYS: i64 = 30;
+
+/// request timeout of a single attempt
+const TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// hex encoded HMAC-SHA256 of `body`
+pub fn sign(secret: &str, body: &str) -> String {
+	let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+	mac.update(body.as_bytes());
+
+	mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// a new delivery of `payload` waiting for its first attempt
+fn delivery(webhook: u64, event: &str, payload: String) -> Delivery {
+	let now = Utc::now();
+
+	Delivery {
+		webhook,
+		event: event.to_string(),
+		payload,
+		status: DeliveryStatus::Pending,
+		attempts: 0,
+		next_attempt: now,
+		last_response: None,
+		created: now,
+		delivered: None,
+	}
+}
+
+/// POSTs the delivery to the webhook, returns the HTTP status or the error
+pub fn send(webhook: &Webhook, id: u64, delivery: &Delivery) -> Result<u16, String> {
+	let response = ureq::post(&webhook.url)
+		.timeout(TIMEOUT)
+		.set("Content-Type", "application/json")
+		.set("X-Webhook-Event", &delivery.event)
+		.set("X-Webhook-Delivery", &id.to_string())
+		.set("X-Webhook-Signature", &format!("sha256={}", sign(&webhook.secret, &delivery.payload)))
+		.send_string(&delivery.payload);
+
+	match response {
+		Ok(r) => Ok(r.status()),
+		Err(ureq::Error::Status(status, _)) => Err(format!("HTTP {}", status)),
+		Err(e) => Err(e.to_string()),
+	}
+}
+
+/// makes one attempt to deliver, records its result and schedules the next one
+pub fn attempt(webhook: &Webhook, id: u64, delivery: &mut Delivery, now: DateTime<Utc>) {
+	delivery.attempts += 1;
+
+	match send(webhook, id, delivery) {
+		Ok(status) => {
+			delivery.status = DeliveryStatus::Delivered;
+			delivery.delivered = Some(now);
+			delivery.last_response = Some(format!("HTTP {}", status));
+		}
+		Err(e) => {
+			delivery.last_response = Some(e);
+
+			if delivery.attempts >= MAX_ATTEMPTS {
+				delivery.status = DeliveryStatus::Failed;
+			} else {
+				delivery.next_attempt = now + Duration::seconds(BACKOFF_SECONDS << (delivery.attempts - 1));
+			}
+		}
+	}
+}
+
+/// queues the change for every webhook subscribed to its kind
+pub fn enqueue(store: &Store, seq: u64, change: &Change) -> Option<()> {
+	let webhooks = Database::<Webhooks>::open(store)?;
+	let mut deliveries = Database::<Deliveries>::open(store)?;
+
+	let payload = serde_json::to_string(&serde_json::json!({ "id": seq, "change": change })).ok()?;
+
+	for (id, _) in webhooks.read().iter().filter(|(_, w)| w.events.contains(&change.kind)) {
+		let key = deliveries.get_key().ok()?;
+		deliveries.write().insert(key, delivery(id, change.kind.name(), payload.clone())).ok()?;
+	}
+
+	Some(())
+}
+
+/// background job attempting due deliveries and dropping old ones from the log
+pub fn dispatch(store: &Store) -> Result<(), Error> {
+	let now = Utc::now();
+	let webhooks = Database::<Webhooks>::open(store).ok_or(Error::Database)?;
+	let mut deliveries = Database::<Deliveries>::open(store).ok_or(Error::Database)?;
+
+	let (pending, done): (Vec<_>, Vec<_>) = deliveries
+		.read()
+		.iter()
+		.partition(|(_, d): &(u64, Delivery)| d.status == DeliveryStatus::Pending);
+
+	for (id, mut d) in pending.into_iter().filter(|(_, d)| d.next_attempt <= now) {
+		match webhooks.read().get(d.webhook) {
+			Ok(Some(webhook)) => attempt(&webhook, id, &mut d, now),
+			// the subscription is gone
+			Ok(None) => d.status = DeliveryStatus::Failed,
+			// tried again on the next run
+			Err(e) => {
+				log::error!("webhook: failed to read the subscription of delivery {}: {}", id, e);
+				continue;
+			}
+		}
+
+		deliveries.write().insert(id, &d)?;
+	}
+
+	for (id, _) in done.into_iter().filter(|(_, d)| d.created < now - Duration::days(LOG_DAYS)) {
+		deliveries.write().delete(id)?;
+	}
+
+	Ok(())
+}
+
+/// get all webhook subscriptions
+#[get("/webhooks", format = "application/json")]
+pub fn list(db: Database<Webhooks>, _u: AuthToken<Superadmin>) -> Result<Json<Vec<(u64, Webhook)>>, Error> {
+	Ok(Json(db.read().try_iter().collect::<Result<_, _>>()?))
+}
+
+/// subscribe a url to reservation changes, returns id of the subscription
+#[post("/webhooks", data = "<input>")]
+pub fn subscribe(
+	input: Json<NewWebhook>,
+	mut db: Database<Webhooks>,
+	mut audit: Database<Audit>,
+	usr: AuthToken<Superadmin>,
+) -> Result<Json<u64>, Error> {
+	let NewWebhook { url, events, secret } = input.into_inner();
+	let webhook = Webhook { url, events, secret, created: Utc::now() };
+
+	let id = db.get_key()?;
+	db.write().insert(id, &webhook)?;
+
+	audit::record(&mut audit, &usr.user.email, "webhook.subscribe", format!("webhook:{}", id), None, Some(&audited(&webhook)))
+		.ok_or(Error::Database)?;
+
+	Ok(Json(id))
+}
+
+/// remove a webhook subscription, its pending deliveries fail
+#[delete("/webhooks/<id>")]
+pub fn unsubscribe(id: u64, mut db: Database<Webhooks>, mut audit: Database<Audit>, usr: AuthToken<Superadmin>) -> Option<()> {
+	let webhook = db.read().get(id).ok()??;
+	db.write().delete(id).ok()?;
+
+	audit::record(&mut audit, &usr.user.email, "webhook.unsubscribe", format!("webhook:{}", id), Some(&audited(&webhook)), None)
+}
+
+/// the subscription as recorded in the audit log, without its secret
+fn audited(webhook: &Webhook) -> Webhook {
+	Webhook { secret: "[redacted]".to_string(), ..webhook.clone() }
+}
+
+/// get the delivery log of a webhook subscription
+#[get("/webhooks/<id>/deliveries", format = "application/json")]
+pub fn deliveries(id: u64, db: Database<Deliveries>, _u: AuthToken<Superadmin>) -> Result<Json<Vec<(u64, Delivery)>>, Error> {
+	let all = db.read().try_iter().collect::<Result<Vec<(u64, Delivery)>, _>>()?;
+
+	let mut log = all.into_iter().filter(|(_, d)| d.webhook == id).collect::<Vec<(u64, Delivery)>>();
+	log.sort_by_key(|(_, d)| d.created);
+
+	Ok(Json(log))
+}
+
+/// send a test event to a webhook right away, returns the logged delivery
+///
+/// a failed test event is retried like any other delivery
+#[post("/webhooks/<id>/test")]
+pub fn test(
+	id: u64,
+	webhooks: Database<Webhooks>,
+	mut deliveries: Database<Deliveries>,
+	_u: AuthToken<Superadmin>,
+) -> Result<Json<(u64, Delivery)>, Error> {
+	let webhook = webhooks.read().get(id)?.ok_or(Error::NotFound)?;
+
+	let payload = serde_json::json!({ "id": null, "change": null, "webhook": id }).to_string();
+	let mut d = delivery(id, "ping", payload);
+
+	let key = deliveries.get_key()?;
+	attempt(&webhook, key, &mut d, Utc::now());
+	deliveries.write().insert(key, &d)?;
+
+	Ok(Json((key, d)))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, subscribe, unsubscribe, deliveries, test]
+}
@@ -0,0 +1,75 @@
+extern crate backend;
+
+use backend::backup::{self, Error};
+use backend::migrate::{self, Options};
+use backend::store::Store;
+
+use std::env;
+use std::fs;
+
+#[test]
+pub fn test_backup() {
+	let db = Store::memory();
+	migrate::run(&db, &Options::default()).unwrap();
+	db.open_tree("reservation").unwrap().insert(b"key", b"value").unwrap();
+
+	let dir = env::temp_dir().join(format!("booking-backup-{}", std::process::id()));
+	let (name, manifest) = backup::create(&db, &dir).unwrap();
+	assert_eq!(manifest.schema, migrate::latest());
+	assert_eq!(manifest.trees["reservation"], 1);
+
+	let path = dir.join(&name);
+	assert_eq!(backup::verify(&path).unwrap().checksum, manifest.checksum);
+
+	// restore into a fresh store
+	let fresh = Store::memory();
+	backup::restore(&fresh, &path).unwrap();
+	assert_eq!(fresh.open_tree("reservation").unwrap().get(b"key").unwrap(), Some(b"value".to_vec()));
+	assert_eq!(migrate::version(&fresh).unwrap(), migrate::latest());
+	assert!(fresh.generate_id().unwrap() >= manifest.next_id);
+
+	// only into an empty one
+	assert!(matches!(backup::restore(&db, &path), Err(Error::NotEmpty)));
+
+	// a damaged archive is refused
+	let mut raw = fs::read(&path).unwrap();
+	let last = raw.len() - 1;
+	raw[last] ^= 0xff;
+	let damaged = dir.join("damaged.snap");
+	fs::write(&damaged, raw).unwrap();
+	assert!(matches!(backup::restore(&Store::memory(), &damaged), Err(Error::Invalid(_))));
+
+	for _ in 0..3 {
+		backup::create(&db, &dir).unwrap();
+	}
+	assert_eq!(backup::list(&dir).unwrap().len(), 4);
+	assert_eq!(backup::prune(&dir, 2).unwrap().len(), 2);
+	assert_eq!(backup::list(&dir).unwrap().len(), 2);
+	assert!(!backup::list(&dir).unwrap().contains(&name));
+
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+pub fn test_restore_ids() {
+	let db = Store::memory();
+	migrate::run(&db, &Options::default()).unwrap();
+	for _ in 0..3 {
+		let id = db.generate_id().unwrap();
+		db.open_tree("reservation").unwrap().insert(&id.to_be_bytes(), b"restored").unwrap();
+	}
+
+	let dir = env::temp_dir().join(format!("booking-backup-ids-{}", std::process::id()));
+	let (name, _) = backup::create(&db, &dir).unwrap();
+
+	let fresh = Store::memory();
+	backup::restore(&fresh, &dir.join(&name)).unwrap();
+
+	// records created after the restore get new keys and don't overwrite the restored ones
+	let id = fresh.generate_id().unwrap();
+	let tree = fresh.open_tree("reservation").unwrap();
+	assert_eq!(tree.insert(&id.to_be_bytes(), b"new").unwrap(), None);
+	assert_eq!(tree.iter().count(), 4);
+
+	let _ = fs::remove_dir_all(&dir);
+}
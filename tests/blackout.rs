@@ -0,0 +1,73 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::{Availability, Notification};
+
+use common::{auth, client, create, event, SUPERADMIN};
+
+fn blackout(cli: &Client, email: &str, body: &str) -> Result<u64, Status> {
+	let mut res = cli.post("/api/blackouts").header(ContentType::JSON).header(auth(email)).body(body).dispatch();
+
+	match res.status() {
+		Status::Ok => Ok(res.body_string().unwrap().parse().unwrap()),
+		status => Err(status),
+	}
+}
+
+fn notifications(cli: &Client) -> Vec<(u64, Notification)> {
+	let mut res = cli.get("/admin/notifications").header(ContentType::JSON).header(auth(SUPERADMIN)).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_blackouts() {
+	let cli = client(Config::memory());
+
+	let id = create(&cli, "hozdic@example.com", 1, "2031-12-12T10:00:00Z", "2031-12-12T11:00:00Z").unwrap();
+	let (_, tag) = event(&cli, id);
+	cli.post(format!("/api/events/{}/approve", id)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+	let before = notifications(&cli).len();
+
+	let closure = r#"{"rooms":1,"begin_time":"2031-12-12T00:00:00Z","end_time":"2031-12-13T00:00:00Z","reason":"painting","notify_owners":true}"#;
+	assert_eq!(blackout(&cli, "hozdic@example.com", closure), Err(Status::Forbidden));
+
+	let backwards = r#"{"rooms":1,"begin_time":"2031-12-13T00:00:00Z","end_time":"2031-12-12T00:00:00Z","reason":"x"}"#;
+	assert_eq!(blackout(&cli, SUPERADMIN, backwards), Err(Status::UnprocessableEntity));
+
+	// owners of approved reservations in the way get notified
+	let closed = blackout(&cli, SUPERADMIN, closure).unwrap();
+	let queued = notifications(&cli).into_iter().skip(before).map(|(_, n)| n).collect::<Vec<_>>();
+	assert_eq!(queued.len(), 1);
+	assert_eq!(queued[0].recipient, "hozdic@example.com");
+	assert!(queued[0].text.contains("painting"));
+
+	// blackouts are hard conflicts, weekly ones block every week
+	assert_eq!(create(&cli, "someone@example.com", 1, "2031-12-12T14:00:00Z", "2031-12-12T15:00:00Z"), Err(Status::Conflict));
+	assert!(create(&cli, "someone@example.com", 2, "2031-12-12T14:00:00Z", "2031-12-12T15:00:00Z").is_ok());
+
+	let weekly = r#"{"rooms":2,"begin_time":"2031-12-01T08:00:00Z","end_time":"2031-12-01T09:00:00Z","reason":"cleaning","recurrence":{"frequency":"Weekly","until":null}}"#;
+	let cleaning = blackout(&cli, SUPERADMIN, weekly).unwrap();
+	assert_eq!(create(&cli, "someone@example.com", 2, "2031-12-15T08:30:00Z", "2031-12-15T09:30:00Z"), Err(Status::Conflict));
+
+	let mut res = cli.get("/api/events/availability/2/2031-12-22T08:00:00Z/2031-12-22T09:00:00Z").header(auth("someone@example.com")).dispatch();
+	let availability: Availability = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert!(!availability.available);
+	assert_eq!(availability.blackouts, vec![cleaning]);
+
+	let res = cli.delete(format!("/api/blackouts/{}", closed)).header(auth("hozdic@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Forbidden);
+	let res = cli.delete(format!("/api/blackouts/{}", closed)).header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	let res = cli.delete(format!("/api/blackouts/{}", closed)).header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::NotFound);
+	assert!(create(&cli, "someone@example.com", 1, "2031-12-12T14:00:00Z", "2031-12-12T15:00:00Z").is_ok());
+}
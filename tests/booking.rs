@@ -1,32 +1,55 @@
 extern crate rocket;
 extern crate backend;
+extern crate base64;
 extern crate serde_json;
-use rocket::local::Client;
-use rocket::http::ContentType;
+extern crate chrono;
+
+mod common;
+
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+
+use common::{auth, client, event};
 
 #[test]
 pub fn test_booking() {
-	let cli = Client::new(backend::init()).expect("wtf? the virtual client failed");
-	assert_eq!(cli.get("/rgi/events/").dispatch().body_string().unwrap(), "[]");
+	let cli = client(Config::memory());
+
+	let list = || cli.get("/api/events").header(ContentType::JSON).dispatch().body_string().unwrap();
+	assert_eq!(list(), "[]");
+
+	let mut res = cli
+		.post("/api/events")
+		.header(ContentType::JSON)
+		.header(auth("hozdic@example.com"))
+		.body(r#"{ "name": "test", "description": "test stuff", "begin_time": "2031-12-12T12:30:00Z", "end_time": "2031-12-12T13:25:00Z", "rooms": 3, "layout": 0, "people": 30}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	let id: u64 = res.body_string().unwrap().parse().unwrap();
 
-	let mut req = cli.post("/rgi/events/").header(ContentType::JSON);
-	req.set_body(r#"{ "name": "test", "description": "test stuff", "author": "hozdic", "begin_time": "2019-12-12T12:30", "end_time": "2019-12-13T13:25", "rooms": 3, "layout": 0, "people": 30}"#);
-	assert_eq!(req.dispatch().body_string().unwrap(), r#"{"result": 0, "id": 1}"#);
+	let (v, tag) = event(&cli, id);
+	assert!(!v.approved);
 
-	let v: backend::db::Reservation =
-		serde_json::from_str(&cli.get("/rgi/events/1").dispatch().body_string().unwrap()).unwrap();
-	assert_eq!(v.approved, 0);
+	let res = cli.post(format!("/api/events/{}/approve", id)).header(auth("approver@example.com")).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
 
-	cli.post("/rgi/events/1/approve/").header(ContentType::JSON).dispatch();
+	let (v, tag) = event(&cli, id);
+	assert!(v.approved);
 
-	let v: backend::db::Reservation =
-		serde_json::from_str(&cli.get("/rgi/events/1").dispatch().body_string().unwrap()).unwrap();
-	assert_eq!(v.approved, 1);
+	// every instance gets its own store
+	let other = client(Config::memory());
+	assert_eq!(other.get("/api/events").header(ContentType::JSON).dispatch().body_string().unwrap(), "[]");
 
-	let mut req = cli.post("/rgi/events/").header(ContentType::JSON);
-	req.set_body(r#"{ "name": "test2", "description": "test stuff2", "author": "hozdic2", "begin_time": "2019-12-12T11:30", "end_time": "2019-12-13T13:25", "rooms": 3, "layout": 0, "people": 100}"#);
-	assert_eq!(req.dispatch().body_string().unwrap(), r#"{"result": 2}"#);
+	let res = cli
+		.post("/api/events")
+		.header(ContentType::JSON)
+		.header(auth("hozdic2@example.com"))
+		.body(r#"{ "name": "test2", "description": "test stuff2", "begin_time": "2031-12-12T11:30:00Z", "end_time": "2031-12-12T13:00:00Z", "rooms": 3, "layout": 0, "people": 100}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::Conflict);
 
-	cli.delete("/rgi/events/1").header(ContentType::JSON).dispatch();
-	assert_eq!(cli.get("/rgi/events/").dispatch().body_string().unwrap(), "[]");
+	let res = cli.delete(format!("/api/events/{}", id)).header(auth("hozdic@example.com")).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(list(), "[]");
 }
@@ -0,0 +1,76 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate serde_json;
+extern crate chrono;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Status};
+
+use backend::Config;
+use backend::bulk::ImportReport;
+
+use common::{auth, client, SUPERADMIN};
+
+fn import(cli: &Client, path: &str, body: &str) -> ImportReport {
+	let mut res = cli.post(path).header(auth(SUPERADMIN)).body(body).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_bulk() {
+	let cli = client(Config::memory());
+
+	let users = "name,email,role\nHozdic,hozdic@example.com,Noob\nBroken,broken,Noob\nBoss,boss@example.com,Overlord\n";
+	let report = import(&cli, "/admin/import/users/csv", users);
+	assert_eq!(report.valid, 1);
+	assert_eq!(report.errors.iter().map(|e| e.line).collect::<Vec<_>>(), vec![3, 4]);
+	assert!(report.imported.is_empty());
+
+	let report = import(&cli, "/admin/import/users/csv?dry_run=true", "name,email,role\nHozdic,hozdic@example.com,Noob\n");
+	assert!(report.dry_run && report.errors.is_empty() && report.imported.is_empty());
+
+	let report = import(&cli, "/admin/import/users/csv", "name,email,role\nHozdic,hozdic@example.com,Noob\n");
+	assert_eq!(report.imported, vec!["hozdic@example.com"]);
+
+	let reservation = |name: &str, begin: &str, end: &str| {
+		format!(
+			r#"{{"name":"{}","description":"","author":"hozdic@example.com","rooms":3,"begin_time":"{}","end_time":"{}","layout":0,"approved":true,"people":10}}"#,
+			name, begin, end,
+		)
+	};
+	let rows = [
+		reservation("first", "2031-12-12T10:00:00Z", "2031-12-12T11:00:00Z"),
+		reservation("overlapping", "2031-12-12T10:30:00Z", "2031-12-12T11:30:00Z"),
+		reservation("second", "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z"),
+	];
+
+	// the second row collides with the first one
+	let report = import(&cli, "/admin/import/reservations/jsonl", &rows.join("\n"));
+	assert_eq!((report.valid, report.errors.len(), report.errors[0].line), (2, 1, 2));
+	assert!(report.imported.is_empty());
+
+	let report = import(&cli, "/admin/import/reservations/jsonl", &[&rows[0], "", &rows[2]].join("\n"));
+	assert_eq!(report.imported.len(), 2);
+
+	let mut res = cli.get("/admin/export/reservations/csv?approved=true&from=2031-12-12T11:30:00Z").header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.content_type(), Some(ContentType::CSV));
+	let csv = res.body_string().unwrap();
+	assert_eq!(csv.lines().count(), 2);
+	assert!(csv.starts_with("id,name,") && csv.contains(",second,"));
+
+	// an export imports back, into free terms only
+	let mut res = cli.get("/admin/export/reservations/csv").header(auth(SUPERADMIN)).dispatch();
+	let report = import(&cli, "/admin/import/reservations/csv?dry_run=true", &res.body_string().unwrap());
+	assert_eq!((report.valid, report.errors.len()), (0, 2));
+
+	let mut res = cli.get("/admin/export/users/jsonl").header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.body_string().unwrap().lines().count(), 2);
+
+	let res = cli.get("/admin/export/users/xlsx").header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::NotFound);
+}
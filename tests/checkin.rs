@@ -0,0 +1,105 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::checkin;
+use backend::db::{Database, table::Reservations};
+use backend::models::Revision;
+use backend::store::Store;
+
+use chrono::{Duration, offset::Utc};
+
+use common::{actions, auth, client, create, event, reservation, SUPERADMIN};
+
+fn revisions(cli: &Client, id: u64) -> Vec<(u32, Revision)> {
+	let mut res = cli.get(format!("/api/events/{}/history", id)).header(ContentType::JSON).header(auth("someone@example.com")).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_checkin() {
+	let cli = client(Config::memory());
+
+	let begin = Utc::now() + Duration::minutes(5);
+	let (begin, end) = (begin.to_rfc3339(), (begin + Duration::hours(1)).to_rfc3339());
+	let id = create(&cli, "hozdic@example.com", 1, &begin, &end).unwrap();
+
+	// only approved reservations are checked in
+	let res = cli.post(format!("/api/events/{}/checkin", id)).header(auth("hozdic@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Conflict);
+
+	let (_, tag) = event(&cli, id);
+	let res = cli.post(format!("/api/events/{}/approve", id)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let (_, tag) = event(&cli, id);
+	let res = cli.post(format!("/api/events/{}/checkin", id)).header(auth("someone@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Forbidden);
+	let res = cli.post(format!("/api/events/{}/checkin", id)).header(auth("hozdic@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	// the check-in is a new version, edits of the one before it fail
+	let (checked_in, _) = event(&cli, id);
+	assert!(checked_in.checked_in.is_some());
+	let res = cli
+		.patch(format!("/api/events/{}", id))
+		.header(ContentType::JSON)
+		.header(auth("hozdic@example.com"))
+		.header(Header::new("If-Match", tag))
+		.body(r#"{"name":"renamed"}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::PreconditionFailed);
+
+	let revisions = revisions(&cli, id);
+	assert_eq!(revisions.iter().map(|(n, _)| *n).collect::<Vec<u32>>(), vec![1, 2, 3]);
+	assert_eq!(revisions[2].1.actor, "hozdic@example.com");
+	assert!(revisions[2].1.reservation.checked_in.is_some());
+	assert_eq!(actions(&cli, &format!("reservation:{}", id)), vec!["reservation.create", "reservation.approve", "reservation.checkin"]);
+
+	// checking in again changes nothing
+	let res = cli.post(format!("/api/events/{}/checkin", id)).header(auth("hozdic@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(actions(&cli, &format!("reservation:{}", id)).len(), 3);
+}
+
+#[test]
+pub fn test_no_show() {
+	let cli = client(Config::memory());
+	let store = cli.rocket().state::<Store>().unwrap();
+
+	// written before versioning, it has no revisions yet
+	let mut db = Database::<Reservations>::open(store).unwrap();
+	let id = db.get_key().unwrap();
+	db.write().insert(id, reservation("hozdic@example.com", Utc::now() - Duration::minutes(30), true)).unwrap();
+	let checked_in = db.get_key().unwrap();
+	let mut other = reservation("hozdic@example.com", Utc::now() - Duration::minutes(30), true);
+	other.rooms = 2;
+	other.checked_in = Some(Utc::now() - Duration::minutes(20));
+	db.write().insert(checked_in, other).unwrap();
+
+	checkin::release(store).unwrap();
+
+	let (released, _) = event(&cli, id);
+	assert!(released.no_show);
+	assert!(released.end_time <= Utc::now());
+	assert!(!event(&cli, checked_in).0.no_show);
+
+	let revisions = revisions(&cli, id);
+	assert_eq!(revisions.len(), 2);
+	assert_eq!((revisions[0].1.actor.as_str(), revisions[0].1.reservation.no_show), ("system", false));
+	assert_eq!((revisions[1].1.actor.as_str(), revisions[1].1.reservation.no_show), ("system", true));
+	assert_eq!(actions(&cli, &format!("reservation:{}", id)), vec!["reservation.no_show"]);
+	assert!(actions(&cli, &format!("reservation:{}", checked_in)).is_empty());
+
+	// released only once
+	checkin::release(store).unwrap();
+	assert_eq!(actions(&cli, &format!("reservation:{}", id)).len(), 1);
+}
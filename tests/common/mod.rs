@@ -0,0 +1,98 @@
+//! helpers shared by the integration tests
+#![allow(dead_code)]
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::{Client, LocalResponse};
+
+use backend::Config;
+use backend::models::Reservation;
+
+use chrono::{DateTime, Duration, offset::Utc};
+
+use std::env;
+
+/// the superadmin created by [`client`]
+pub const SUPERADMIN: &str = "approver@example.com";
+
+/// a client of a backend with the given storage and a superadmin [`SUPERADMIN`]
+pub fn client(config: Config) -> Client {
+	env::set_var("SA_SECRET", "secret");
+	let cli = Client::new(backend::init(config).expect("failed to start the backend")).expect("wtf? the virtual client failed");
+	cli.post(format!("/admin/generate_sa/{}/secret", SUPERADMIN)).dispatch();
+
+	cli
+}
+
+/// the `Authorization` header of the user
+pub fn auth(email: &str) -> Header<'static> {
+	let token = base64::encode(&format!(r#"{{"name":"{}","email":"{}"}}"#, email, email));
+	Header::new("Authorization", format!("Bearer {}", token))
+}
+
+/// requests the rooms for the user at `path` (`/api/events`, `/api/waitlist`, …),
+/// `extra` are further fields of the body, e.g. `"team":1`
+pub fn request<'c>(cli: &'c Client, path: &str, email: &str, rooms: u8, begin: &str, end: &str, extra: &str) -> LocalResponse<'c> {
+	let extra = if extra.is_empty() { String::new() } else { format!(",{}", extra) };
+
+	cli.post(path.to_string())
+		.header(ContentType::JSON)
+		.header(auth(email))
+		.body(format!(
+			r#"{{"name":"test","description":"test","rooms":{},"begin_time":"{}","end_time":"{}","layout":0,"people":1{}}}"#,
+			rooms, begin, end, extra
+		))
+		.dispatch()
+}
+
+/// the id returned by a successful request, or the status of the failure
+pub fn id(mut res: LocalResponse) -> Result<u64, Status> {
+	match res.status() {
+		Status::Ok => Ok(res.body_string().unwrap().parse().unwrap()),
+		status => Err(status),
+	}
+}
+
+/// books the rooms for the user, returns the id of the reservation or the status of the failure
+pub fn create(cli: &Client, email: &str, rooms: u8, begin: &str, end: &str) -> Result<u64, Status> {
+	id(request(cli, "/api/events", email, rooms, begin, end, ""))
+}
+
+/// the reservation and its `ETag`
+pub fn event(cli: &Client, id: u64) -> (Reservation, String) {
+	let mut res = cli.get(format!("/api/events/{}", id)).header(auth("someone@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	let tag = res.headers().get_one("ETag").unwrap().to_string();
+
+	(serde_json::from_str(&res.body_string().unwrap()).unwrap(), tag)
+}
+
+/// actions of the audit log entries of the target, e.g. `reservation:1`, oldest first
+pub fn actions(cli: &Client, target: &str) -> Vec<String> {
+	let mut res = cli.get(format!("/admin/audit?target={}", target)).header(ContentType::JSON).header(auth(SUPERADMIN)).dispatch();
+	let entries: Vec<(u64, serde_json::Value)> = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+
+	entries.into_iter().map(|(_, e)| e["action"].as_str().unwrap().to_string()).collect()
+}
+
+/// a reservation of the first room for an hour, for tests writing to the tables directly
+pub fn reservation(author: &str, begin_time: DateTime<Utc>, approved: bool) -> Reservation {
+	Reservation {
+		name:         "x".to_string(),
+		description:  "x".to_string(),
+		author:       author.to_string(),
+		requested_by: None,
+		rooms:        1,
+		begin_time,
+		end_time:     begin_time + Duration::hours(1),
+		layout:       0,
+		approved,
+		people:       1,
+		team:         None,
+		checked_in:   None,
+		no_show:      false,
+		cancelled:    false,
+		deleted_at:   None,
+		deleted_by:   None,
+		rejected:     false,
+	}
+}
@@ -0,0 +1,160 @@
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate rocket;
+extern crate serde_json;
+
+mod common;
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::Client;
+
+use backend::Config;
+use backend::bulk::ImportReport;
+use backend::models::Reservation;
+use backend::store::Storage;
+
+use common::{auth, client, create, event, SUPERADMIN};
+
+use std::env;
+use std::fs;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+const THREADS: usize = 8;
+
+/// every kind of storage, the in-memory one serializes all writes,
+/// sled and SQLite have to rely on their transactions
+fn storages(name: &str) -> Vec<Config> {
+	let path = |kind| {
+		let path = env::temp_dir().join(format!("booking-concurrency-{}-{}.{}", name, std::process::id(), kind));
+		let _ = fs::remove_dir_all(&path);
+		let _ = fs::remove_file(&path);
+		path
+	};
+
+	vec![
+		Config::memory(),
+		Config { storage: Storage::Sled(path("sled")) },
+		Config { storage: Storage::Sqlite(path("sqlite")) },
+	]
+}
+
+/// runs `request` with every value from many threads at once, returns the statuses
+fn hammer<T, F>(cli: Arc<Client>, values: Vec<T>, request: F) -> Vec<Status>
+where
+	T: Send + 'static,
+	F: Fn(&Client, T) -> Status + Send + Sync + 'static,
+{
+	let barrier = Arc::new(Barrier::new(values.len()));
+	let request = Arc::new(request);
+
+	let threads = values
+		.into_iter()
+		.map(|v| {
+			let (cli, barrier, request) = (cli.clone(), barrier.clone(), request.clone());
+			thread::spawn(move || {
+				barrier.wait();
+				request(&cli, v)
+			})
+		})
+		.collect::<Vec<_>>();
+
+	threads.into_iter().map(|t| t.join().unwrap()).collect()
+}
+
+/// only one of overlapping requests gets approved
+fn approvals(config: Config) {
+	let cli = client(config);
+
+	let (begin, end) = ("2031-03-01T10:00:00Z", "2031-03-01T12:00:00Z");
+	let requests = (0..THREADS)
+		.map(|i| {
+			let id = create(&cli, &format!("user{}@example.com", i), 1, begin, end).unwrap();
+			(id, event(&cli, id).1)
+		})
+		.collect::<Vec<_>>();
+	let ids = requests.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+	let cli = Arc::new(cli);
+	let statuses = hammer(cli.clone(), requests, |cli, (id, tag)| {
+		cli.post(format!("/api/events/{}/approve", id))
+			.header(auth("approver@example.com"))
+			.header(Header::new("If-Match", tag))
+			.dispatch()
+			.status()
+	});
+
+	assert_eq!(statuses.iter().filter(|s| **s == Status::Ok).count(), 1, "{:?}", statuses);
+	assert_eq!(statuses.iter().filter(|s| **s == Status::Conflict).count(), THREADS - 1, "{:?}", statuses);
+	assert_eq!(ids.into_iter().filter(|id| event(&cli, *id).0.approved).count(), 1);
+}
+
+/// only one edit of the same version goes through
+fn updates(config: Config) {
+	let cli = client(config);
+
+	let id = create(&cli, "owner@example.com", 1, "2031-04-01T10:00:00Z", "2031-04-01T11:00:00Z").unwrap();
+	let (_, tag) = event(&cli, id);
+
+	let statuses = hammer(Arc::new(cli), (0..THREADS).collect(), move |cli, i| {
+		cli.patch(format!("/api/events/{}", id))
+			.header(ContentType::JSON)
+			.header(auth("owner@example.com"))
+			.header(Header::new("If-Match", tag.clone()))
+			.body(format!(r#"{{"name":"edit {}"}}"#, i))
+			.dispatch()
+			.status()
+	});
+
+	assert_eq!(statuses.iter().filter(|s| **s == Status::Ok).count(), 1, "{:?}", statuses);
+	assert_eq!(statuses.iter().filter(|s| **s == Status::PreconditionFailed).count(), THREADS - 1, "{:?}", statuses);
+}
+
+/// of imports of the same terms, one imports all its rows and the others none
+fn imports(config: Config) {
+	let cli = client(config);
+
+	let row = |i: usize| {
+		format!(
+			r#"{{"name":"import","description":"","author":"{}","rooms":1,"begin_time":"2031-05-0{}T10:00:00Z","end_time":"2031-05-0{}T11:00:00Z","layout":0,"approved":true,"people":1}}"#,
+			SUPERADMIN, i, i,
+		)
+	};
+	let rows = (1..=3).map(row).collect::<Vec<_>>().join("\n");
+
+	let reports = Arc::new(Mutex::new(vec![]));
+	let collected = reports.clone();
+	let cli = Arc::new(cli);
+	let statuses = hammer(cli.clone(), vec![rows; THREADS], move |cli, rows| {
+		let mut res = cli.post("/admin/import/reservations/jsonl").header(auth(SUPERADMIN)).body(rows).dispatch();
+		let report: ImportReport = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+		collected.lock().unwrap().push(report.imported.len());
+
+		res.status()
+	});
+
+	let mut imported = reports.lock().unwrap().clone();
+	imported.sort();
+	assert!(statuses.iter().all(|s| *s == Status::Ok), "{:?}", statuses);
+	assert_eq!(imported, [vec![0; THREADS - 1], vec![3]].concat());
+
+	let mut res = cli.get("/api/events").header(ContentType::JSON).dispatch();
+	let events: Vec<(u64, Reservation)> = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(events.len(), 3);
+}
+
+#[test]
+pub fn test_concurrent_approvals() {
+	storages("approvals").into_iter().for_each(approvals);
+}
+
+#[test]
+pub fn test_concurrent_updates() {
+	storages("updates").into_iter().for_each(updates);
+}
+
+#[test]
+pub fn test_concurrent_imports() {
+	storages("imports").into_iter().for_each(imports);
+}
@@ -0,0 +1,91 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::WaitlistEntry;
+
+use common::{auth, client, create, event, SUPERADMIN};
+
+const PRINCIPAL: &str = "boss@example.com";
+const DELEGATE: &str = "assistant@example.com";
+
+fn on_behalf(cli: &Client, path: &str, begin: &str, end: &str) -> Result<u64, Status> {
+	let mut res = cli
+		.post(path)
+		.header(ContentType::JSON)
+		.header(auth(DELEGATE))
+		.body(format!(
+			r#"{{"name":"board","description":"","rooms":1,"begin_time":"{}","end_time":"{}","layout":0,"people":5,"on_behalf_of":"{}"}}"#,
+			begin, end, PRINCIPAL,
+		))
+		.dispatch();
+
+	match res.status() {
+		Status::Ok => Ok(res.body_string().unwrap().parse().unwrap()),
+		status => Err(status),
+	}
+}
+
+fn rename(cli: &Client, email: &str, id: u64) -> Status {
+	let (_, tag) = event(cli, id);
+
+	cli.patch(format!("/api/events/{}", id))
+		.header(ContentType::JSON)
+		.header(auth(email))
+		.header(Header::new("If-Match", tag))
+		.body(r#"{"name":"renamed"}"#)
+		.dispatch()
+		.status()
+}
+
+fn waitlist(cli: &Client, email: &str) -> Vec<(u64, WaitlistEntry)> {
+	let mut res = cli.get("/api/waitlist").header(ContentType::JSON).header(auth(email)).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_delegation() {
+	let cli = client(Config::memory());
+
+	// both users exist once they log in
+	create(&cli, PRINCIPAL, 2, "2031-12-01T10:00:00Z", "2031-12-01T11:00:00Z").unwrap();
+	assert_eq!(on_behalf(&cli, "/api/events", "2031-12-12T10:00:00Z", "2031-12-12T11:00:00Z"), Err(Status::Forbidden));
+
+	let res = cli.post(format!("/api/delegations/{}", DELEGATE)).header(auth(PRINCIPAL)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let id = on_behalf(&cli, "/api/events", "2031-12-12T10:00:00Z", "2031-12-12T11:00:00Z").unwrap();
+	let (booked, _) = event(&cli, id);
+	assert_eq!((booked.author.as_str(), booked.requested_by.as_deref()), (PRINCIPAL, Some(DELEGATE)));
+	assert_eq!(rename(&cli, DELEGATE, id), Status::Ok);
+
+	// a request for a taken slot waits on behalf of the principal
+	let taken = create(&cli, "other@example.com", 1, "2031-12-13T10:00:00Z", "2031-12-13T11:00:00Z").unwrap();
+	let (_, tag) = event(&cli, taken);
+	cli.post(format!("/api/events/{}/approve", taken)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+
+	let entry = on_behalf(&cli, "/api/waitlist", "2031-12-13T10:00:00Z", "2031-12-13T11:00:00Z").unwrap();
+	assert_eq!(waitlist(&cli, DELEGATE).len(), 1);
+
+	let res = cli.delete(format!("/api/delegations/{}", DELEGATE)).header(auth(PRINCIPAL)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	// "requested_by" alone doesn't give any rights
+	assert_eq!(rename(&cli, DELEGATE, id), Status::Forbidden);
+	let res = cli.delete(format!("/api/events/{}", id)).header(auth(DELEGATE)).header(Header::new("If-Match", "*")).dispatch();
+	assert_eq!(res.status(), Status::Forbidden);
+
+	assert!(waitlist(&cli, DELEGATE).is_empty());
+	assert_eq!(cli.delete(format!("/api/waitlist/{}", entry)).header(auth(DELEGATE)).dispatch().status(), Status::Forbidden);
+
+	assert_eq!(rename(&cli, PRINCIPAL, id), Status::Ok);
+	assert_eq!(cli.delete(format!("/api/waitlist/{}", entry)).header(auth(PRINCIPAL)).dispatch().status(), Status::Ok);
+}
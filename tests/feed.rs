@@ -0,0 +1,108 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::{Client, LocalResponse};
+use rocket::http::{Header, Status};
+
+use backend::Config;
+use backend::feed::{self, Feed};
+use backend::models::Change;
+use backend::store::Store;
+
+use common::{auth, client, create, event, SUPERADMIN};
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// a client with the change feed running, as it is after launch
+fn feed_client() -> Client {
+	let cli = client(Config::memory());
+	feed::start(cli.rocket().state::<Store>().unwrap().clone(), cli.rocket().state::<Feed>().unwrap().clone());
+
+	cli
+}
+
+/// the next event of the stream, its id and name and the change
+fn next_event(res: &mut LocalResponse) -> (u64, String, Change) {
+	let body = res.body().expect("the stream has a body").into_inner();
+
+	loop {
+		let mut event = vec![];
+		let mut byte = [0];
+		while !event.ends_with(b"\n\n") {
+			match body.read(&mut byte) {
+				Ok(0) => panic!("the stream ended"),
+				Ok(_) => event.push(byte[0]),
+				Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+				Err(e) => panic!("{}", e),
+			}
+		}
+
+		let event = String::from_utf8(event).unwrap();
+		let field = |name: &str| event.lines().find_map(|l| l.strip_prefix(name)).map(|v| v.trim().to_string());
+
+		// keep-alive comments have no fields
+		if let (Some(id), Some(name), Some(data)) = (field("id:"), field("event:"), field("data:")) {
+			return (id.parse().unwrap(), name, serde_json::from_str(&data).unwrap());
+		}
+	}
+}
+
+#[test]
+pub fn test_feed() {
+	let cli = feed_client();
+
+	let mut res = cli.get("/api/events/feed").header(auth("someone@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let id = create(&cli, "hozdic@example.com", 1, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+	let (created, name, change) = next_event(&mut res);
+	assert_eq!((name.as_str(), change.reservation_id), ("created", id));
+	assert!(change.reservation.is_some());
+
+	let (_, tag) = event(&cli, id);
+	let approved = cli.post(format!("/api/events/{}/approve", id)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(approved.status(), Status::Ok);
+	assert_eq!(next_event(&mut res).1, "approved");
+
+	// a reconnecting client gets what it missed
+	let mut resumed = cli.get("/api/events/feed").header(auth("someone@example.com")).header(Header::new("Last-Event-ID", created.to_string())).dispatch();
+	let (_, name, change) = next_event(&mut resumed);
+	assert_eq!((name.as_str(), change.reservation_id), ("approved", id));
+
+	// only changes of the rooms listened to
+	let mut south = cli.get("/api/events/feed?room=2").header(auth("someone@example.com")).dispatch();
+	create(&cli, "hozdic@example.com", 1, "2031-12-13T12:00:00Z", "2031-12-13T13:00:00Z").unwrap();
+	let other = create(&cli, "hozdic@example.com", 2, "2031-12-13T12:00:00Z", "2031-12-13T13:00:00Z").unwrap();
+	assert_eq!(next_event(&mut south).2.reservation_id, other);
+
+	// anonymous clients don't get anything
+	assert_eq!(cli.get("/api/events/feed").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+pub fn test_feed_disconnect() {
+	let cli = feed_client();
+	let feed = cli.rocket().state::<Feed>().unwrap();
+
+	let streams = (0..4).map(|_| cli.get("/api/events/feed").header(auth("someone@example.com")).dispatch()).collect::<Vec<_>>();
+	assert_eq!(feed.listeners(), 4);
+	drop(streams);
+
+	// the next change notices the clients are gone
+	create(&cli, "hozdic@example.com", 1, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+	for _ in 0..100 {
+		if feed.listeners() == 0 {
+			return;
+		}
+		thread::sleep(Duration::from_millis(20));
+	}
+
+	panic!("{} disconnected clients are still listening", feed.listeners());
+}
@@ -0,0 +1,87 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::db::{Database, table::Reservations};
+use backend::models::{Revision, RevisionDiff};
+use backend::store::Store;
+
+use chrono::{TimeZone, offset::Utc};
+
+use common::{auth, client, create, event, reservation, SUPERADMIN};
+
+fn since_approved(cli: &Client, id: u64) -> RevisionDiff {
+	let mut res = cli.get(format!("/api/events/{}/since_approved", id)).header(ContentType::JSON).header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+fn rename(cli: &Client, id: u64, name: &str) {
+	let (_, tag) = event(cli, id);
+	let res = cli
+		.patch(format!("/api/events/{}", id))
+		.header(ContentType::JSON)
+		.header(auth("hozdic@example.com"))
+		.header(Header::new("If-Match", tag))
+		.body(format!(r#"{{"name":"{}"}}"#, name))
+		.dispatch();
+	assert_eq!(res.status(), Status::Ok);
+}
+
+#[test]
+pub fn test_history() {
+	let cli = client(Config::memory());
+
+	let id = create(&cli, "hozdic@example.com", 1, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+
+	// never approved, compared with nothing
+	let diff = since_approved(&cli, id);
+	assert_eq!((diff.from, diff.to, diff.approved_by), (None, 1, None));
+
+	let (_, tag) = event(&cli, id);
+	let res = cli.post(format!("/api/events/{}/approve", id)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	rename(&cli, id, "renamed");
+
+	let diff = since_approved(&cli, id);
+	assert_eq!((diff.from, diff.to, diff.approved_by.as_deref()), (Some(2), 3, Some(SUPERADMIN)));
+	assert!(diff.diff.iter().any(|c| c.field == "name" && c.before == "test" && c.after == "renamed"));
+
+	let mut res = cli.get(format!("/api/events/{}/history", id)).header(ContentType::JSON).header(auth("hozdic@example.com")).dispatch();
+	let revisions: Vec<(u32, Revision)> = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	let actors = revisions.iter().map(|(_, r)| (r.actor.as_str(), r.approved_by.as_deref())).collect::<Vec<_>>();
+	assert_eq!(actors, vec![("hozdic@example.com", None), (SUPERADMIN, Some(SUPERADMIN)), ("hozdic@example.com", None)]);
+
+	let mut res = cli.get(format!("/api/events/{}/diff?from=1&to=3", id)).header(ContentType::JSON).header(auth("hozdic@example.com")).dispatch();
+	let diff: RevisionDiff = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(diff.diff.iter().map(|c| c.field.as_str()).collect::<Vec<&str>>(), vec!["name"]);
+
+	// only approvers see what changed since the approval
+	let res = cli.get(format!("/api/events/{}/since_approved", id)).header(ContentType::JSON).header(auth("hozdic@example.com")).dispatch();
+	assert_ne!(res.status(), Status::Ok);
+}
+
+#[test]
+pub fn test_history_before_versioning() {
+	let cli = client(Config::memory());
+	let store = cli.rocket().state::<Store>().unwrap();
+
+	let mut db = Database::<Reservations>::open(store).unwrap();
+	let id = db.get_key().unwrap();
+	db.write().insert(id, reservation("hozdic@example.com", Utc.ymd(2031, 12, 12).and_hms(12, 0, 0), true)).unwrap();
+
+	// the stored state becomes the first revision, its approver is unknown
+	rename(&cli, id, "renamed");
+
+	let diff = since_approved(&cli, id);
+	assert_eq!((diff.from, diff.to, diff.approved_by.as_deref()), (Some(1), 2, Some("system")));
+	assert!(diff.diff.iter().any(|c| c.field == "name"));
+}
@@ -0,0 +1,86 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+
+use serde_json::Value;
+
+use common::{auth, client, create, event, SUPERADMIN};
+
+fn hold(cli: &Client, email: &str, rooms: u8, begin: &str, end: &str) -> Result<u64, Status> {
+	let mut res = cli
+		.post("/api/holds")
+		.header(ContentType::JSON)
+		.header(auth(email))
+		.body(format!(r#"{{"rooms":{},"begin_time":"{}","end_time":"{}"}}"#, rooms, begin, end))
+		.dispatch();
+
+	match res.status() {
+		Status::Ok => Ok(res.body_string().unwrap().parse().unwrap()),
+		status => Err(status),
+	}
+}
+
+fn convert(cli: &Client, email: &str, id: u64) -> Result<u64, Status> {
+	let mut res = cli
+		.post(format!("/api/holds/{}/convert", id))
+		.header(ContentType::JSON)
+		.header(auth(email))
+		.body(r#"{"name":"held","description":"held","layout":0,"people":1}"#)
+		.dispatch();
+
+	match res.status() {
+		Status::Ok => Ok(res.body_string().unwrap().parse().unwrap()),
+		status => Err(status),
+	}
+}
+
+#[test]
+pub fn test_holds() {
+	let cli = client(Config::memory());
+
+	let id = hold(&cli, "hozdic@example.com", 1, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+
+	// the slot is blocked for others, but not for the author
+	assert_eq!(create(&cli, "someone@example.com", 1, "2031-12-12T12:30:00Z", "2031-12-12T13:30:00Z"), Err(Status::Conflict));
+	assert_eq!(hold(&cli, "someone@example.com", 1, "2031-12-12T12:30:00Z", "2031-12-12T13:30:00Z"), Err(Status::Conflict));
+	assert_eq!(convert(&cli, "someone@example.com", id), Err(Status::Forbidden));
+
+	let reservation = convert(&cli, "hozdic@example.com", id).unwrap();
+	assert_eq!(convert(&cli, "hozdic@example.com", id), Err(Status::NotFound));
+	assert_eq!(cli.get("/api/holds").header(ContentType::JSON).header(auth("hozdic@example.com")).dispatch().body_string().unwrap(), "[]");
+
+	let (converted, _) = event(&cli, reservation);
+	assert_eq!((converted.author.as_str(), converted.name.as_str(), converted.approved), ("hozdic@example.com", "held", false));
+
+	let mut res = cli
+		.get(format!("/admin/audit?target=reservation:{}", reservation))
+		.header(ContentType::JSON)
+		.header(auth(SUPERADMIN))
+		.dispatch();
+	let entries: Vec<(u64, Value)> = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(entries.len(), 1);
+	assert_eq!(entries[0].1["action"], "hold.convert");
+
+	// approved reservations block new holds
+	let (_, tag) = event(&cli, reservation);
+	let res = cli.post(format!("/api/events/{}/approve", reservation)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(hold(&cli, "someone@example.com", 1, "2031-12-12T12:30:00Z", "2031-12-12T13:30:00Z"), Err(Status::Conflict));
+	assert!(hold(&cli, "someone@example.com", 2, "2031-12-12T12:30:00Z", "2031-12-12T13:30:00Z").is_ok());
+
+	// a blackout declared over a held slot wins over the hold
+	let id = hold(&cli, "hozdic@example.com", 1, "2031-12-19T12:00:00Z", "2031-12-19T13:00:00Z").unwrap();
+	let closure = r#"{"rooms":1,"begin_time":"2031-12-19T08:00:00Z","end_time":"2031-12-19T18:00:00Z","reason":"maintenance"}"#;
+	let res = cli.post("/api/blackouts").header(ContentType::JSON).header(auth(SUPERADMIN)).body(closure).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(convert(&cli, "hozdic@example.com", id), Err(Status::Conflict));
+}
@@ -0,0 +1,128 @@
+extern crate backend;
+extern crate chrono;
+extern crate base64;
+extern crate rocket;
+extern crate serde_json;
+
+mod common;
+
+use rocket::http::{ContentType, Status};
+
+use backend::Config;
+use backend::db::{self, FromOrdered, OrderedKeys, Table, TreeMan, TxTree, ordered, table::Reservations, table::Revisions};
+use backend::models::Reservation;
+use backend::store::Store;
+
+use chrono::{TimeZone, offset::Utc};
+
+use common::{actions, auth, client, create, reservation, SUPERADMIN};
+
+fn ids(records: impl Iterator<Item = (u64, Reservation)>) -> Vec<u64> {
+	records.map(|(id, _)| id).collect()
+}
+
+#[test]
+pub fn test_indexes() {
+	let mut tree = TreeMan::<u64, Reservation, OrderedKeys>::open_indexed(&Store::memory(), "r", Reservations::indexes()).unwrap();
+	let day = |d| Utc.ymd(2031, 1, d).and_hms(10, 0, 0);
+
+	tree.insert(1, reservation("a@example.com", day(3), false)).unwrap();
+	tree.insert(2, reservation("ab@example.com", day(1), true)).unwrap();
+	tree.insert(3, reservation("b@other.com", day(2), true)).unwrap();
+
+	assert_eq!(ids(tree.find("author", "a@example.com").unwrap()), vec![1]);
+	assert_eq!(ids(tree.find_prefix("author", "a").unwrap()), vec![1, 2]);
+	assert_eq!(ids(tree.find_range("begin_time", day(1)..day(3)).unwrap()), vec![2, 3]);
+	assert_eq!(ids(tree.find_range("begin_time", day(2)..).unwrap()), vec![3, 1]);
+	assert_eq!(ids(tree.find("approved", &true).unwrap()), vec![2, 3]);
+
+	tree.update::<_, Reservation, _>(1, |r| r.map(|r| Reservation { approved: true, ..r })).unwrap();
+	tree.delete(2).unwrap();
+
+	assert_eq!(ids(tree.find("approved", &true).unwrap()), vec![1, 3]);
+	assert!(tree.find_prefix("author", "ab").unwrap().next().is_none());
+
+	assert_eq!(tree.rebuild_indexes().unwrap(), 2);
+	assert_eq!(ids(tree.find_range("begin_time", ..=day(3)).unwrap()), vec![3, 1]);
+
+	// an unknown index is an error, not a panic
+	match tree.find("color", "red") {
+		Err(db::Error::UnknownIndex { index, .. }) => assert_eq!(index, "color"),
+		other => panic!("unexpected result: {:?}", other.map(ids)),
+	}
+	let plain = TreeMan::<u64, Reservation, OrderedKeys>::open(&Store::memory(), "p").unwrap();
+	assert!(plain.find("approved", &true).is_err());
+}
+
+#[test]
+pub fn test_ordered_keys() {
+	let mut tree = TreeMan::<<Revisions as Table>::Key, u8, OrderedKeys>::open(&Store::memory(), "r").unwrap();
+
+	for key in &[(300, 2), (2, 1), (300, 1), (70_000, 1), (2, 256)] {
+		tree.insert(key, 0).unwrap();
+	}
+
+	let keys = |records: Vec<((u64, u32), u8)>| records.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+	assert_eq!(keys(tree.iter().collect()), vec![(2, 1), (2, 256), (300, 1), (300, 2), (70_000, 1)]);
+	assert_eq!(keys(tree.scan_prefix(&300u64).collect()), vec![(300, 1), (300, 2)]);
+	assert_eq!(keys(tree.range((2, 256)..(300, 2)).collect()), vec![(2, 256), (300, 1)]);
+	assert_eq!(tree.first().unwrap().unwrap().0, (2, 1));
+	assert_eq!(tree.last().unwrap().unwrap().0, (70_000, 1));
+
+	let text = "a\u{0}b".to_string();
+	assert_eq!(String::decode_ordered(&mut &ordered(&text)[..]), Some(text.clone()));
+	assert!(ordered("a") < ordered(&text) && ordered(&text) < ordered("ab"));
+	assert!(ordered(&-1i64) < ordered(&0i64));
+}
+
+#[test]
+pub fn test_corrupt_records() {
+	let store = Store::memory();
+	let tree = TreeMan::<u64, Reservation, OrderedKeys>::open_indexed(&store, Reservations::name(), Reservations::indexes()).unwrap();
+	store.open_tree(Reservations::name()).unwrap().insert(&ordered(&1u64), &[0xff]).unwrap();
+
+	// a corrupt record is an error, not a missing one
+	assert!(matches!(tree.get(1), Err(db::Error::Corrupt { .. })));
+
+	let read = store.transaction(&[Reservations::name()], &|t| TxTree::<Reservations>::new(t[0]).get(1).map(|_| ()));
+	assert!(matches!(read, Err(db::Error::Corrupt { .. })));
+
+	// but it can be overwritten
+	let trees = [Reservations::name(), "reservation.index"];
+	let write = store.transaction(&trees, &|t| TxTree::<Reservations>::indexed(t[0], t[1]).insert(1, reservation("a@example.com", Utc::now(), false)));
+	assert!(write.unwrap());
+	assert!(tree.get(1).unwrap().is_some());
+
+	// a write leaving the index behind is refused
+	let unindexed = store.transaction(&[Reservations::name()], &|t| TxTree::<Reservations>::new(t[0]).remove(1));
+	assert!(matches!(unindexed, Err(db::Error::UnknownIndex { .. })));
+	assert!(tree.get(1).unwrap().is_some());
+}
+
+#[test]
+pub fn test_corrupt_listing() {
+	let cli = client(Config::memory());
+	create(&cli, "hozdic@example.com", 1, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+
+	let store = cli.rocket().state::<Store>().unwrap();
+	store.open_tree(Reservations::name()).unwrap().insert(&ordered(&99u64), &[0xff]).unwrap();
+
+	// an unreadable record fails the listing instead of going missing from it
+	assert_eq!(cli.get("/api/events").header(ContentType::JSON).dispatch().status(), Status::InternalServerError);
+}
+
+#[test]
+pub fn test_rebuild_route() {
+	let cli = client(Config::memory());
+	create(&cli, "hozdic@example.com", 1, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+
+	let res = cli.post("/admin/indexes/rebuild").header(auth("hozdic@example.com")).dispatch();
+	assert_ne!(res.status(), Status::Ok);
+
+	let mut res = cli.post("/admin/indexes/rebuild").header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(res.body_string().unwrap(), r#"[["reservation",1],["device",0]]"#);
+
+	assert_eq!(actions(&cli, "table:reservation"), vec!["indexes.rebuild"]);
+	assert_eq!(actions(&cli, "table:device"), vec!["indexes.rebuild"]);
+}
@@ -0,0 +1,63 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::{Device, KioskStatus};
+
+use common::{auth, client, event, SUPERADMIN};
+
+fn device(token: &str) -> Header<'static> {
+	Header::new("Authorization", format!("Device {}", token))
+}
+
+fn book(cli: &Client, token: &str) -> Result<u64, Status> {
+	let mut res = cli.post("/api/kiosk/book?name=standup").header(device(token)).dispatch();
+
+	match res.status() {
+		Status::Ok => Ok(res.body_string().unwrap().parse().unwrap()),
+		status => Err(status),
+	}
+}
+
+#[test]
+pub fn test_kiosk() {
+	let cli = client(Config::memory());
+
+	let register = |email: &str| cli.post("/api/devices").header(ContentType::JSON).header(auth(email)).body(r#"{"name":"door","room":1}"#).dispatch();
+	assert_eq!(register("hozdic@example.com").status(), Status::Forbidden);
+
+	let (id, registered): (u64, Device) = serde_json::from_str(&register(SUPERADMIN).body_string().unwrap()).unwrap();
+	assert_eq!(book(&cli, "wrong"), Err(Status::Forbidden));
+
+	// quotas of the device apply to walk-ins too
+	let res = cli
+		.put(format!("/api/quotas/user/kiosk-{}", id))
+		.header(ContentType::JSON)
+		.header(auth(SUPERADMIN))
+		.body(r#"{"max_future":0}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(book(&cli, &registered.token), Err(Status::UnprocessableEntity));
+
+	cli.delete(format!("/api/quotas/user/kiosk-{}", id)).header(auth(SUPERADMIN)).dispatch();
+	let reservation = book(&cli, &registered.token).unwrap();
+
+	let (booked, _) = event(&cli, reservation);
+	assert_eq!((booked.name.as_str(), booked.author, booked.approved), ("standup", format!("kiosk-{}", id), true));
+	assert!(booked.checked_in.is_some());
+
+	let mut res = cli.get("/api/kiosk").header(ContentType::JSON).header(device(&registered.token)).dispatch();
+	let status: KioskStatus = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(status.current.map(|e| e.name), Some("standup".to_string()));
+
+	// the room is taken now
+	assert_eq!(book(&cli, &registered.token), Err(Status::Conflict));
+}
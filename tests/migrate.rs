@@ -0,0 +1,54 @@
+extern crate backend;
+extern crate serde_cbor;
+
+use backend::db::{self, Table, ordered, table::Schema};
+use backend::migrate::{self, Options};
+use backend::store::{Storage, Store};
+
+use std::env;
+
+#[test]
+pub fn test_migrate() {
+	let db = Store::memory();
+
+	// data stored before versioning
+	db.open_tree("reservation").unwrap().insert(&serde_cbor::to_vec(&1u64).unwrap(), &[0xa0]).unwrap();
+
+	let report = migrate::run(&db, &Options { dry_run: true, backup: None }).unwrap();
+	assert_eq!((report.from, report.to), (0, 0));
+	assert!(db.open_tree(Schema::name()).unwrap().is_empty());
+
+	let backups = env::temp_dir().join(format!("booking-migrate-{}.backups", std::process::id()));
+	let report = migrate::run(&db, &Options { dry_run: false, backup: Some(backups.clone()) }).unwrap();
+	assert_eq!((report.from, report.to), (0, migrate::latest()));
+	assert_eq!(report.applied.len(), migrate::MIGRATIONS.len());
+
+	let backup = Store::open(&Storage::Sled(report.backup.unwrap())).unwrap();
+	assert_eq!(backup.open_tree("reservation").unwrap().len(), 1);
+
+	// the key has been re-encoded
+	assert!(db.open_tree("reservation").unwrap().get(&ordered(&1u64)).unwrap().is_some());
+
+	let report = migrate::run(&db, &Options::default()).unwrap();
+	assert!(report.applied.is_empty());
+
+	let _ = std::fs::remove_dir_all(&backups);
+}
+
+#[test]
+pub fn test_startup_dry_run() {
+	let db = Store::memory();
+	db.open_tree("reservation").unwrap().insert(&serde_cbor::to_vec(&1u64).unwrap(), &[0xa0]).unwrap();
+
+	// a dry run reports that the schema stays behind instead of panicking
+	env::set_var("MIGRATE_DRY_RUN", "1");
+	match migrate::startup(&db) {
+		Err(db::Error::Outdated { found, required }) => assert_eq!((found, required), (0, migrate::latest())),
+		other => panic!("unexpected result of a dry run: {:?}", other),
+	}
+	assert!(db.open_tree(Schema::name()).unwrap().is_empty());
+
+	env::set_var("MIGRATE_DRY_RUN", "0");
+	migrate::startup(&db).unwrap();
+	assert_eq!(migrate::version(&db).unwrap(), migrate::latest());
+}
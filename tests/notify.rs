@@ -0,0 +1,58 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::Notification;
+
+use common::{actions, auth, client, create, event, id, request, SUPERADMIN};
+
+const PRINCIPAL: &str = "boss@example.com";
+const DELEGATE: &str = "assistant@example.com";
+
+fn notifications(cli: &Client) -> Vec<(u64, Notification)> {
+	let mut res = cli.get("/admin/notifications").header(ContentType::JSON).header(auth(SUPERADMIN)).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_notifications() {
+	let cli = client(Config::memory());
+
+	// both users exist once they log in
+	create(&cli, PRINCIPAL, 2, "2031-12-01T10:00:00Z", "2031-12-01T11:00:00Z").unwrap();
+	cli.post(format!("/api/delegations/{}", DELEGATE)).header(auth(PRINCIPAL)).dispatch();
+
+	let on_behalf = format!(r#""on_behalf_of":"{}""#, PRINCIPAL);
+	let booked = id(request(&cli, "/api/events", DELEGATE, 1, "2031-12-12T10:00:00Z", "2031-12-12T11:00:00Z", &on_behalf)).unwrap();
+	let (_, tag) = event(&cli, booked);
+	cli.post(format!("/api/events/{}/approve", booked)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+	let before = notifications(&cli).len();
+
+	// both the author and whoever booked on their behalf get notified
+	let closure = r#"{"rooms":1,"begin_time":"2031-12-12T00:00:00Z","end_time":"2031-12-13T00:00:00Z","reason":"painting","notify_owners":true}"#;
+	let res = cli.post("/api/blackouts").header(ContentType::JSON).header(auth(SUPERADMIN)).body(closure).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let queued = notifications(&cli).into_iter().skip(before).collect::<Vec<_>>();
+	assert_eq!(queued.iter().map(|(_, n)| n.recipient.as_str()).collect::<Vec<_>>(), vec![PRINCIPAL, DELEGATE]);
+
+	// the mailer acknowledges sent notifications
+	let (sent, _) = queued[0];
+	let res = cli.delete(format!("/admin/notifications/{}", sent)).header(auth(PRINCIPAL)).dispatch();
+	assert_ne!(res.status(), Status::Ok);
+	let res = cli.delete(format!("/admin/notifications/{}", sent)).header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert!(notifications(&cli).iter().all(|(id, _)| *id != sent));
+	assert_eq!(actions(&cli, &format!("notification:{}", sent)), vec!["notification.acknowledge"]);
+
+	let res = cli.delete(format!("/admin/notifications/{}", sent)).header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::NotFound);
+}
@@ -0,0 +1,75 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Status};
+
+use backend::Config;
+use backend::models::{Availability, Rule};
+
+use serde_json::Value;
+
+use common::{auth, client, request, SUPERADMIN};
+
+/// tries to book the first room, returns the broken rules
+fn broken(cli: &Client, email: &str, begin: &str, end: &str) -> Vec<Rule> {
+	let mut res = request(cli, "/api/events", email, 1, begin, end, "");
+
+	if res.status() == Status::Ok {
+		return vec![];
+	}
+
+	assert_eq!(res.status(), Status::UnprocessableEntity);
+	let body: Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(body["result"], 3);
+
+	serde_json::from_value(body["violations"].as_array().unwrap().iter().map(|v| v["rule"].clone()).collect()).unwrap()
+}
+
+#[test]
+pub fn test_policies() {
+	let cli = client(Config::memory());
+
+	// open on Fridays 8:00-18:00 local time (UTC+1), bookings of at most two hours in 15-minute slots
+	let policy = r#"{
+		"opening_hours": [{"weekday":"Fri","open":"08:00:00","close":"18:00:00"}],
+		"utc_offset": 60,
+		"max_duration": 120,
+		"granularity": 15,
+		"exempt": {"MaxDuration": ["Approver"]}
+	}"#;
+	let res = cli.put("/api/policies/1").header(ContentType::JSON).header(auth("hozdic@example.com")).body(policy).dispatch();
+	assert_eq!(res.status(), Status::NotFound);
+	let res = cli.put("/api/policies/1").header(ContentType::JSON).header(auth(SUPERADMIN)).body(policy).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let user = "hozdic@example.com";
+	assert_eq!(broken(&cli, user, "2031-12-12T06:00:00Z", "2031-12-12T08:00:00Z"), vec![Rule::OpeningHours]);
+	assert_eq!(broken(&cli, user, "2031-12-13T09:00:00Z", "2031-12-13T10:00:00Z"), vec![Rule::OpeningHours]);
+	assert_eq!(broken(&cli, user, "2031-12-12T09:10:00Z", "2031-12-12T10:00:00Z"), vec![Rule::Granularity]);
+	assert_eq!(broken(&cli, user, "2031-12-12T08:00:00Z", "2031-12-12T11:00:00Z"), vec![Rule::MaxDuration]);
+	assert_eq!(broken(&cli, user, "2031-12-12T10:00:00Z", "2031-12-12T09:00:00Z"), vec![Rule::Interval]);
+
+	// approvers are exempt from the maximal duration, but not from the rest
+	assert!(broken(&cli, SUPERADMIN, "2031-12-12T08:00:00Z", "2031-12-12T11:00:00Z").is_empty());
+	assert_eq!(broken(&cli, SUPERADMIN, "2031-12-12T06:00:00Z", "2031-12-12T07:00:00Z"), vec![Rule::OpeningHours]);
+
+	// the other room has no policy
+	let mut res = cli.get("/api/events/availability/2/2031-12-13T09:10:00Z/2031-12-13T13:00:00Z").header(auth(user)).dispatch();
+	let availability: Availability = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert!(availability.available && availability.violations.is_empty());
+
+	let mut res = cli.get("/api/events/availability/1/2031-12-13T09:10:00Z/2031-12-13T13:00:00Z").header(auth(user)).dispatch();
+	let availability: Availability = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert!(!availability.available);
+	assert_eq!(availability.violations.len(), 3);
+
+	let res = cli.delete("/api/policies/1").header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert!(broken(&cli, user, "2031-12-13T09:10:00Z", "2031-12-13T10:00:00Z").is_empty());
+}
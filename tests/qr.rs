@@ -0,0 +1,36 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::http::{ContentType, Status};
+
+use backend::Config;
+
+use common::{auth, client, SUPERADMIN};
+
+#[test]
+pub fn test_sheet() {
+	let cli = client(Config::memory());
+
+	let sheet = || cli.get("/rooms/qr").dispatch().body_string().unwrap();
+	assert!(!sheet().contains("<svg"));
+
+	let res = cli.put("/api/rooms/2").header(ContentType::JSON).header(auth(SUPERADMIN)).body(r#"{"name":"Small hall"}"#).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	// only the rooms of the registry, under their registered names
+	let page = sheet();
+	assert_eq!(page.matches("<svg").count(), 1);
+	assert!(page.contains("Small hall"));
+
+	let mut res = cli.get("/api/rooms/2/qr?format=png").dispatch();
+	assert_eq!(res.content_type(), Some(ContentType::PNG));
+	assert!(!res.body_bytes().unwrap().is_empty());
+
+	let res = cli.get("/api/rooms/2/qr?target=checkin").dispatch();
+	assert_eq!(res.status(), Status::Forbidden);
+}
@@ -0,0 +1,87 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::{Limit, QuotaReport};
+
+use serde_json::Value;
+
+use common::{auth, client, event, request, SUPERADMIN};
+
+const USER: &str = "hozdic@example.com";
+
+fn set(cli: &Client, owner: &str, quota: &str) {
+	let res = cli.put(format!("/api/quotas/{}", owner)).header(ContentType::JSON).header(auth(SUPERADMIN)).body(quota).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+}
+
+/// books an hour of the first room on the day of December 2031, returns the exceeded limits on failure
+fn book(cli: &Client, day: u32) -> Result<u64, Vec<Limit>> {
+	let begin = format!("2031-12-{:02}T10:00:00Z", day);
+	let end = format!("2031-12-{:02}T11:00:00Z", day);
+
+	let mut res = request(cli, "/api/events", USER, 1, &begin, &end, "");
+
+	if res.status() == Status::Ok {
+		return Ok(res.body_string().unwrap().parse().unwrap());
+	}
+
+	assert_eq!(res.status(), Status::UnprocessableEntity);
+	let body: Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(body["result"], 6);
+
+	Err(serde_json::from_value(body["violations"].as_array().unwrap().iter().map(|v| v["limit"].clone()).collect()).unwrap())
+}
+
+fn report(cli: &Client) -> QuotaReport {
+	let mut res = cli.get("/api/quota").header(ContentType::JSON).header(auth(USER)).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_quotas() {
+	let cli = client(Config::memory());
+
+	let res = cli.put("/api/quotas/role/Noob").header(ContentType::JSON).header(auth(USER)).body("{}").dispatch();
+	assert_ne!(res.status(), Status::Ok);
+
+	set(&cli, "role/Noob", r#"{"max_pending":2,"max_hours_week":3}"#);
+
+	let first = book(&cli, 8).unwrap();
+	book(&cli, 9).unwrap();
+	assert_eq!(book(&cli, 10), Err(vec![Limit::PendingRequests]));
+
+	let report = report(&cli);
+	assert_eq!((report.quota.max_pending, report.usage.pending, report.usage.future), (Some(2), 2, 2));
+
+	// personal quotas take precedence, the rest comes from the role
+	set(&cli, &format!("user/{}", USER), r#"{"max_pending":5}"#);
+	book(&cli, 10).unwrap();
+	assert_eq!(book(&cli, 11), Err(vec![Limit::HoursPerWeek]));
+
+	// the next week is a new one
+	assert!(book(&cli, 15).is_ok());
+
+	// an edited reservation isn't counted twice, the week is full already
+	let (_, tag) = event(&cli, first);
+	let res = cli
+		.patch(format!("/api/events/{}", first))
+		.header(ContentType::JSON)
+		.header(auth(USER))
+		.header(Header::new("If-Match", tag))
+		.body(r#"{"name":"renamed"}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let res = cli.delete("/api/quotas/role/Noob").header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert!(book(&cli, 11).is_ok());
+}
@@ -0,0 +1,31 @@
+extern crate backend;
+
+use backend::auth::roles::{self, Approver, FacilityManager, Noob, Superadmin};
+
+#[test]
+pub fn test_role_hierarchy() {
+	// a role passes its own guard and the guards of its ancestors
+	assert!(roles::is::<Superadmin>("Superadmin"));
+	assert!(roles::is::<Approver>("Superadmin"));
+	assert!(roles::is::<Approver>("Approver"));
+	assert!(roles::is::<FacilityManager>("FacilityManager"));
+
+	// but not the guards of its descendants or siblings
+	assert!(!roles::is::<Superadmin>("Approver"));
+	assert!(!roles::is::<Approver>("FacilityManager"));
+	assert!(!roles::is::<FacilityManager>("Superadmin"));
+	assert!(!roles::is::<Approver>("Noob"));
+
+	// everybody is a noob, even with an unknown role
+	assert!(roles::is::<Noob>("Superadmin"));
+	assert!(roles::is::<Noob>("whoever"));
+	assert!(!roles::is::<Approver>("whoever"));
+}
+
+#[test]
+pub fn test_resolve() {
+	assert_eq!(roles::resolve("Superadmin"), vec!["Superadmin", "Approver"]);
+	assert_eq!(roles::resolve("FacilityManager"), vec!["FacilityManager"]);
+	assert_eq!(roles::resolve("Noob"), Vec::<&str>::new());
+	assert_eq!(roles::resolve("whoever"), Vec::<&str>::new());
+}
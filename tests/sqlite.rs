@@ -0,0 +1,98 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate rusqlite;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Status};
+
+use backend::Config;
+use backend::db::{self, OrderedKeys, Table, TreeMan, table::Reservations};
+use backend::migrate;
+use backend::models::Reservation;
+use backend::store::{Storage, Store};
+
+use chrono::{TimeZone, offset::Utc};
+
+use common::{auth, reservation};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn path(name: &str) -> PathBuf {
+	let path = env::temp_dir().join(format!("booking-{}-{}.sqlite", name, std::process::id()));
+	let _ = fs::remove_file(&path);
+	path
+}
+
+#[test]
+pub fn test_sqlite_store() {
+	let file = path("store");
+	let day = |d| Utc.ymd(2031, 1, d).and_hms(10, 0, 0);
+	let store = Store::open(&Storage::Sqlite(file.clone())).unwrap();
+	let mut tree = TreeMan::<u64, Reservation, OrderedKeys>::open_indexed(&store, Reservations::name(), Reservations::indexes()).unwrap();
+
+	tree.insert(1, reservation("a@example.com", day(3), false)).unwrap();
+	tree.insert(2, reservation("b@example.com", day(1), true)).unwrap();
+	tree.insert(3, reservation("a@example.com", day(2), true)).unwrap();
+	tree.delete(2).unwrap();
+
+	let ids = |records: Vec<(u64, Reservation)>| records.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+	assert_eq!(ids(tree.find("author", "a@example.com").unwrap().collect()), vec![1, 3]);
+	assert_eq!(ids(tree.find_range("begin_time", Utc.ymd(2031, 1, 2).and_hms(0, 0, 0)..).unwrap().collect()), vec![3, 1]);
+	assert_eq!(ids(tree.find("approved", &true).unwrap().collect()), vec![3]);
+	assert_eq!(ids(tree.iter().collect()), vec![1, 3]);
+	assert!(store.generate_id().unwrap() < store.generate_id().unwrap());
+	drop((tree, store));
+
+	// the data survive reopening and can be queried
+	let store = Store::open(&Storage::Sqlite(file.clone())).unwrap();
+	assert_eq!(store.open_tree(Reservations::name()).unwrap().len(), 2);
+
+	let conn = rusqlite::Connection::open(&file).unwrap();
+	let authors = conn
+		.prepare("SELECT json_extract(json, '$.author') FROM \"reservation\" WHERE json_extract(json, '$.approved')")
+		.unwrap()
+		.query_map([], |r| r.get::<_, String>(0))
+		.unwrap()
+		.collect::<Result<Vec<_>, _>>()
+		.unwrap();
+	assert_eq!(authors, vec!["a@example.com"]);
+
+	// a copy of another store
+	let memory = Store::memory();
+	migrate::startup(&memory).expect("failed to migrate");
+	memory.open_tree("reservation").unwrap().insert(b"k", b"v").unwrap();
+	memory.reserve_ids(100).unwrap();
+
+	let target = Store::open(&Storage::Sqlite(path("copy"))).unwrap();
+	assert_eq!(db::copy(&memory, &target).unwrap(), 2);
+	assert_eq!(migrate::version(&target).unwrap(), migrate::latest());
+	assert!(target.generate_id().unwrap() >= 100);
+
+	let _ = fs::remove_file(&file);
+}
+
+#[test]
+pub fn test_sqlite_server() {
+	let file = path("server");
+	let cli = Client::new(backend::init(Config { storage: Storage::Sqlite(file.clone()) }).expect("failed to start the backend")).expect("wtf? the virtual client failed");
+
+	let res = cli
+		.post("/api/events")
+		.header(ContentType::JSON)
+		.header(auth("hozdic@example.com"))
+		.body(r#"{ "name": "test", "description": "test stuff", "begin_time": "2031-12-12T12:30:00Z", "end_time": "2031-12-12T13:25:00Z", "rooms": 3, "layout": 0, "people": 30}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let list = cli.get("/api/events").header(ContentType::JSON).dispatch().body_string().unwrap();
+	assert!(list.contains("test stuff"));
+
+	let _ = fs::remove_file(&file);
+}
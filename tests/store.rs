@@ -0,0 +1,39 @@
+extern crate backend;
+
+use backend::store::{Storage, Store};
+
+use std::env;
+use std::fs;
+
+#[test]
+pub fn test_sled_transactions() {
+	let path = env::temp_dir().join(format!("booking-store-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&path);
+	let store = Store::open(&Storage::Sled(path.clone())).unwrap();
+
+	// a single tree
+	assert!(store.transaction(&["a"], &|t| t[0].insert(b"k", b"1")).unwrap());
+	assert_eq!(store.open_tree("a").unwrap().get(b"k").unwrap(), Some(b"1".to_vec()));
+
+	// every tree of the transaction gets its own view
+	let names = (0..12).map(|i| format!("t{}", i)).collect::<Vec<_>>();
+	let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+	let committed = store.transaction(&names, &|t| {
+		for (i, view) in t.iter().enumerate() {
+			view.insert(b"k", &[i as u8])?;
+		}
+		Ok(())
+	});
+	assert!(committed.unwrap());
+	for (i, name) in names.iter().enumerate() {
+		assert_eq!(store.open_tree(name).unwrap().get(b"k").unwrap(), Some(vec![i as u8]));
+	}
+
+	// too many trees are an error, not a panic
+	let names = (0..40).map(|i| format!("t{}", i)).collect::<Vec<_>>();
+	let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+	assert!(store.transaction(&names, &|_| Ok(())).is_err());
+
+	drop(store);
+	let _ = fs::remove_dir_all(&path);
+}
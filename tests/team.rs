@@ -0,0 +1,103 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::{Reservation, Team};
+
+use common::{auth, client, event, id, request, SUPERADMIN};
+
+const ADMIN: &str = "lead@example.com";
+const MEMBER: &str = "member@example.com";
+const COLLEAGUE: &str = "colleague@example.com";
+const OUTSIDER: &str = "outsider@example.com";
+
+fn book(cli: &Client, email: &str, rooms: u8, team: u64) -> Result<u64, Status> {
+	let team = format!(r#""team":{}"#, team);
+	id(request(cli, "/api/events", email, rooms, "2031-12-12T10:00:00Z", "2031-12-12T11:00:00Z", &team))
+}
+
+fn rename(cli: &Client, email: &str, id: u64) -> Status {
+	let (_, tag) = event(cli, id);
+
+	cli.patch(format!("/api/events/{}", id))
+		.header(ContentType::JSON)
+		.header(auth(email))
+		.header(Header::new("If-Match", tag))
+		.body(r#"{"name":"renamed"}"#)
+		.dispatch()
+		.status()
+}
+
+fn team(cli: &Client, id: u64) -> Team {
+	let mut res = cli.get(format!("/api/teams/{}", id)).header(auth(MEMBER)).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_teams() {
+	let cli = client(Config::memory());
+
+	let body = format!(r#"{{"name":"lab","members":[],"admins":["{}"]}}"#, ADMIN);
+	let res = cli.post("/api/teams").header(ContentType::JSON).header(auth(ADMIN)).body(body.clone()).dispatch();
+	assert_ne!(res.status(), Status::Ok);
+
+	let mut res = cli.post("/api/teams").header(ContentType::JSON).header(auth(SUPERADMIN)).body(body).dispatch();
+	let id: u64 = res.body_string().unwrap().parse().unwrap();
+
+	// only admins of the team manage its members
+	let res = cli.post(format!("/api/teams/{}/members/{}", id, OUTSIDER)).header(auth(OUTSIDER)).dispatch();
+	assert_eq!(res.status(), Status::Forbidden);
+
+	for email in &[MEMBER, COLLEAGUE] {
+		let res = cli.post(format!("/api/teams/{}/members/{}", id, email)).header(auth(ADMIN)).dispatch();
+		assert_eq!(res.status(), Status::Ok);
+	}
+	assert_eq!(team(&cli, id).members, vec![MEMBER, COLLEAGUE]);
+
+	// reservations can be owned only by teams the author is a member of
+	assert_eq!(book(&cli, OUTSIDER, 1, id), Err(Status::Forbidden));
+	let reservation = book(&cli, MEMBER, 1, id).unwrap();
+
+	// any member can edit them
+	assert_eq!(rename(&cli, COLLEAGUE, reservation), Status::Ok);
+	assert_eq!(rename(&cli, ADMIN, reservation), Status::Ok);
+	assert_eq!(rename(&cli, OUTSIDER, reservation), Status::Forbidden);
+
+	let mut res = cli.get(format!("/api/events?team={}", id)).header(ContentType::JSON).dispatch();
+	let owned: Vec<(u64, Reservation)> = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	assert_eq!(owned.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![reservation]);
+
+	let mut res = cli.get(format!("/api/events?team={}", id + 1)).header(ContentType::JSON).dispatch();
+	assert_eq!(res.body_string().unwrap(), "[]");
+
+	// quotas of the team apply to all of its reservations
+	let res = cli.put(format!("/api/quotas/team/{}", id)).header(ContentType::JSON).header(auth(SUPERADMIN)).body(r#"{"max_future":1}"#).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(book(&cli, COLLEAGUE, 2, id), Err(Status::UnprocessableEntity));
+
+	// former members lose the rights
+	let res = cli.delete(format!("/api/teams/{}/members/{}", id, COLLEAGUE)).header(auth(ADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(rename(&cli, COLLEAGUE, reservation), Status::Forbidden);
+
+	// admins can be appointed by other admins
+	let res = cli.post(format!("/api/teams/{}/admins/{}", id, MEMBER)).header(auth(ADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	let res = cli.delete(format!("/api/teams/{}/admins/{}", id, ADMIN)).header(auth(MEMBER)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(team(&cli, id).admins, vec![MEMBER]);
+
+	let res = cli.delete(format!("/api/teams/{}", id)).header(auth(MEMBER)).dispatch();
+	assert_ne!(res.status(), Status::Ok);
+	let res = cli.delete(format!("/api/teams/{}", id)).header(auth(SUPERADMIN)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(cli.get(format!("/api/teams/{}", id)).header(auth(MEMBER)).dispatch().status(), Status::NotFound);
+}
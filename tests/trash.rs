@@ -0,0 +1,85 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::Reservation;
+use backend::store::Store;
+use backend::trash;
+
+use chrono::Duration;
+
+use common::{actions, auth, client, create, event};
+
+fn trashed(cli: &Client) -> Vec<(u64, Reservation)> {
+	let mut res = cli.get("/api/trash").header(ContentType::JSON).header(auth("approver@example.com")).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+#[test]
+pub fn test_trash() {
+	let cli = client(Config::memory());
+
+	let list = || cli.get("/api/events").header(ContentType::JSON).dispatch().body_string().unwrap();
+	let id = create(&cli, "hozdic@example.com", 3, "2031-12-12T12:00:00Z", "2031-12-12T13:00:00Z").unwrap();
+
+	let (_, tag) = event(&cli, id);
+	let res = cli.delete(format!("/api/events/{}", id)).header(auth("hozdic@example.com")).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(list(), "[]");
+
+	let trash = trashed(&cli);
+	assert_eq!(trash.len(), 1);
+	assert_eq!((trash[0].0, trash[0].1.deleted_by.as_deref(), trash[0].1.rejected), (id, Some("hozdic@example.com"), false));
+	assert!(trash[0].1.cancelled);
+
+	// the reservation is kept under its id, but it's gone for everyone but the trash
+	let res = cli.get(format!("/api/events/{}", id)).header(auth("hozdic@example.com")).dispatch();
+	assert_eq!(res.status(), Status::NotFound);
+
+	assert_eq!(actions(&cli, &format!("reservation:{}", id)), vec!["reservation.create", "reservation.delete"]);
+
+	// only approvers see the trash
+	let res = cli.get("/api/trash").header(ContentType::JSON).header(auth("hozdic@example.com")).dispatch();
+	assert_ne!(res.status(), Status::Ok);
+
+	// the term is free until it's restored
+	let other = create(&cli, "hozdic@example.com", 3, "2031-12-12T12:30:00Z", "2031-12-12T12:45:00Z").unwrap();
+	let res = cli.post(format!("/api/events/{}/approve", other)).header(auth("approver@example.com")).header(Header::new("If-Match", "*")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let res = cli.post(format!("/api/trash/{}/restore", id)).header(auth("approver@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Conflict);
+
+	let res = cli.delete(format!("/api/events/{}", other)).header(auth("approver@example.com")).header(Header::new("If-Match", "*")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+
+	let res = cli.post(format!("/api/trash/{}/restore", id)).header(auth("approver@example.com")).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	assert_eq!(trashed(&cli).len(), 1);
+
+	let (restored, _) = event(&cli, id);
+	assert!(!restored.cancelled && restored.deleted_at.is_none() && restored.deleted_by.is_none());
+	assert_eq!(actions(&cli, &format!("reservation:{}", id)).last().unwrap(), "reservation.restore");
+
+	let res = cli.post(format!("/api/trash/{}/restore", id)).header(auth("approver@example.com")).dispatch();
+	assert_eq!(res.status(), Status::NotFound);
+
+	// purged after the retention period
+	let (_, tag) = event(&cli, id);
+	cli.delete(format!("/api/events/{}", id)).header(auth("approver@example.com")).header(Header::new("If-Match", tag)).dispatch();
+	assert!(trashed(&cli).iter().any(|(i, t)| *i == id && t.rejected));
+
+	let store = cli.rocket().state::<Store>().unwrap();
+	trash::purge(store, Duration::days(1)).unwrap();
+	assert_eq!(trashed(&cli).len(), 2);
+	trash::purge(store, Duration::zero()).unwrap();
+	assert!(trashed(&cli).is_empty());
+}
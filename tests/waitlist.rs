@@ -0,0 +1,101 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::local::Client;
+use rocket::http::{ContentType, Header, Status};
+
+use backend::Config;
+use backend::models::WaitlistEntry;
+
+use std::env;
+
+use common::{actions, auth, client, create, event, id, request, SUPERADMIN};
+
+const BLOCKER: &str = "blocker@example.com";
+const FIRST: &str = "first@example.com";
+const SECOND: &str = "second@example.com";
+
+/// an approved reservation of the first room on the day of December 2031
+fn block(cli: &Client, day: u32) -> u64 {
+	let id = create(cli, BLOCKER, 1, &format!("2031-12-{:02}T10:00:00Z", day), &format!("2031-12-{:02}T11:00:00Z", day)).unwrap();
+	let (_, tag) = event(cli, id);
+	cli.post(format!("/api/events/{}/approve", id)).header(auth(SUPERADMIN)).header(Header::new("If-Match", tag)).dispatch();
+
+	id
+}
+
+fn cancel(cli: &Client, id: u64) {
+	let (_, tag) = event(cli, id);
+	let res = cli.delete(format!("/api/events/{}", id)).header(auth(BLOCKER)).header(Header::new("If-Match", tag)).dispatch();
+	assert_eq!(res.status(), Status::Ok);
+}
+
+fn join(cli: &Client, email: &str, day: u32) -> u64 {
+	let (begin, end) = (format!("2031-12-{:02}T10:00:00Z", day), format!("2031-12-{:02}T11:00:00Z", day));
+	id(request(cli, "/api/waitlist", email, 1, &begin, &end, "")).unwrap()
+}
+
+fn waitlist(cli: &Client, email: &str) -> Vec<(u64, WaitlistEntry)> {
+	let mut res = cli.get("/api/waitlist").header(ContentType::JSON).header(auth(email)).dispatch();
+	serde_json::from_str(&res.body_string().unwrap()).unwrap()
+}
+
+fn accept(cli: &Client, email: &str, entry: u64) -> Result<u64, Status> {
+	id(cli.post(format!("/api/waitlist/{}/accept", entry)).header(auth(email)).dispatch())
+}
+
+// both modes in a single test, the offer duration is read from the environment
+#[test]
+pub fn test_waitlist() {
+	let cli = client(Config::memory());
+
+	// a free slot is booked, not waited for
+	let free = request(&cli, "/api/waitlist", FIRST, 1, "2031-12-05T10:00:00Z", "2031-12-05T11:00:00Z", "");
+	assert_eq!(id(free), Err(Status::UnprocessableEntity));
+	assert!(waitlist(&cli, FIRST).is_empty());
+
+	// without offers, the first in line gets promoted to a pending reservation
+	let blocker = block(&cli, 12);
+	join(&cli, FIRST, 12);
+	assert_eq!(waitlist(&cli, FIRST).len(), 1);
+	assert!(waitlist(&cli, SECOND).is_empty());
+
+	cancel(&cli, blocker);
+	assert!(waitlist(&cli, FIRST).is_empty());
+
+	let mut res = cli.get("/api/events").header(ContentType::JSON).dispatch();
+	let events: Vec<(u64, serde_json::Value)> = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+	let (promoted, _) = events.iter().find(|(_, e)| e["author"] == FIRST).unwrap();
+	let (reservation, _) = event(&cli, *promoted);
+	assert_eq!((reservation.begin_time.to_rfc3339().as_str(), reservation.approved), ("2031-12-12T10:00:00+00:00", false));
+	assert_eq!(actions(&cli, &format!("reservation:{}", promoted)), vec!["reservation.promote"]);
+
+	// with offers, the slot is held for the first in line until they accept or decline
+	env::set_var("WAITLIST_OFFER_MINUTES", "30");
+
+	let blocker = block(&cli, 19);
+	let first = join(&cli, FIRST, 19);
+	let second = join(&cli, SECOND, 19);
+	assert_eq!(accept(&cli, FIRST, first), Err(Status::Conflict));
+
+	cancel(&cli, blocker);
+	let offered = |email| waitlist(&cli, email).into_iter().map(|(_, e)| e.offered_until.is_some()).collect::<Vec<_>>();
+	assert_eq!((offered(FIRST), offered(SECOND)), (vec![true], vec![false]));
+
+	// a declined offer goes to the next in line
+	assert_eq!(accept(&cli, SECOND, first), Err(Status::Forbidden));
+	assert_eq!(cli.delete(format!("/api/waitlist/{}", first)).header(auth(FIRST)).dispatch().status(), Status::Ok);
+	assert_eq!(offered(SECOND), vec![true]);
+
+	let accepted = accept(&cli, SECOND, second).unwrap();
+	let (reservation, _) = event(&cli, accepted);
+	assert_eq!((reservation.author.as_str(), reservation.approved), (SECOND, false));
+	assert!(waitlist(&cli, SECOND).is_empty());
+
+	env::remove_var("WAITLIST_OFFER_MINUTES");
+}
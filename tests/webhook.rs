@@ -0,0 +1,153 @@
+extern crate rocket;
+extern crate backend;
+extern crate base64;
+extern crate chrono;
+extern crate serde_json;
+
+mod common;
+
+use rocket::http::{ContentType, Status};
+
+use backend::Config;
+use backend::models::{Delivery, DeliveryStatus, Webhook};
+use backend::webhook;
+
+use chrono::{Duration, offset::Utc};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+use common::{auth, client, SUPERADMIN};
+
+/// a local HTTP receiver answering `status` to a single request,
+/// returns its url and the received headers and body
+fn receiver(status: u16) -> (String, mpsc::Receiver<(Vec<String>, String)>) {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let url = format!("http://{}/hook", listener.local_addr().unwrap());
+	let (tx, rx) = mpsc::channel();
+
+	thread::spawn(move || {
+		let (stream, _) = listener.accept().unwrap();
+		let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+		let mut headers = vec![];
+		loop {
+			let mut line = String::new();
+			reader.read_line(&mut line).unwrap();
+			if line.trim().is_empty() {
+				break;
+			}
+			headers.push(line.trim().to_string());
+		}
+
+		let length = headers
+			.iter()
+			.find_map(|h| h.to_lowercase().strip_prefix("content-length:").map(|l| l.trim().parse::<usize>().unwrap()))
+			.unwrap_or(0);
+		let mut body = vec![0; length];
+		reader.read_exact(&mut body).unwrap();
+
+		write!(&stream, "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+		tx.send((headers, String::from_utf8(body).unwrap())).unwrap();
+	});
+
+	(url, rx)
+}
+
+fn hook(url: String) -> Webhook {
+	Webhook { url, events: vec![], secret: "s3cret".to_string(), created: Utc::now() }
+}
+
+fn pending(attempts: u32) -> Delivery {
+	Delivery {
+		webhook: 1,
+		event: "ping".to_string(),
+		payload: r#"{"id":null,"change":null,"webhook":1}"#.to_string(),
+		status: DeliveryStatus::Pending,
+		attempts,
+		next_attempt: Utc::now(),
+		last_response: None,
+		created: Utc::now(),
+		delivered: None,
+	}
+}
+
+#[test]
+pub fn test_signed_delivery() {
+	let (url, rx) = receiver(200);
+	let mut delivery = pending(0);
+
+	webhook::attempt(&hook(url), 42, &mut delivery, Utc::now());
+
+	let (headers, body) = rx.recv().unwrap();
+	assert_eq!(body, delivery.payload);
+	assert!(headers.contains(&"X-Webhook-Event: ping".to_string()));
+	assert!(headers.contains(&"X-Webhook-Delivery: 42".to_string()));
+	assert!(headers.contains(&format!("X-Webhook-Signature: sha256={}", webhook::sign("s3cret", &body))));
+
+	assert_eq!(delivery.status, DeliveryStatus::Delivered);
+	assert_eq!(delivery.attempts, 1);
+	assert!(delivery.delivered.is_some());
+}
+
+#[test]
+pub fn test_retry_backoff() {
+	let (url, rx) = receiver(500);
+	let mut delivery = pending(2);
+	let now = Utc::now();
+
+	webhook::attempt(&hook(url), 1, &mut delivery, now);
+	rx.recv().unwrap();
+
+	assert_eq!(delivery.status, DeliveryStatus::Pending);
+	assert_eq!(delivery.attempts, 3);
+	assert_eq!(delivery.last_response.as_deref(), Some("HTTP 500"));
+	assert_eq!(delivery.next_attempt, now + Duration::seconds(120));
+}
+
+#[test]
+pub fn test_gives_up() {
+	let (url, rx) = receiver(503);
+	let mut delivery = pending(webhook::MAX_ATTEMPTS - 1);
+
+	webhook::attempt(&hook(url), 1, &mut delivery, Utc::now());
+	rx.recv().unwrap();
+
+	assert_eq!(delivery.status, DeliveryStatus::Failed);
+}
+
+#[test]
+pub fn test_signature() {
+	// RFC 4231, test case 2
+	assert_eq!(
+		webhook::sign("Jefe", "what do ya want for nothing?"),
+		"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+	);
+}
+
+#[test]
+pub fn test_subscriptions_audited() {
+	let cli = client(Config::memory());
+
+	let mut res = cli
+		.post("/admin/webhooks")
+		.header(ContentType::JSON)
+		.header(auth(SUPERADMIN))
+		.body(r#"{"url":"http://127.0.0.1:1/hook","events":["created"],"secret":"s3cret"}"#)
+		.dispatch();
+	assert_eq!(res.status(), Status::Ok);
+	let id: u64 = res.body_string().unwrap().parse().unwrap();
+
+	assert_eq!(cli.delete(format!("/admin/webhooks/{}", id)).header(auth(SUPERADMIN)).dispatch().status(), Status::Ok);
+	assert_eq!(cli.delete(format!("/admin/webhooks/{}", id)).header(auth(SUPERADMIN)).dispatch().status(), Status::NotFound);
+
+	let mut res = cli.get(format!("/admin/audit?target=webhook:{}", id)).header(ContentType::JSON).header(auth(SUPERADMIN)).dispatch();
+	let log = res.body_string().unwrap();
+	let entries: Vec<(u64, serde_json::Value)> = serde_json::from_str(&log).unwrap();
+
+	let actions = entries.iter().map(|(_, e)| (e["actor"].as_str().unwrap(), e["action"].as_str().unwrap())).collect::<Vec<_>>();
+	assert_eq!(actions, vec![(SUPERADMIN, "webhook.subscribe"), (SUPERADMIN, "webhook.unsubscribe")]);
+	assert!(!log.contains("s3cret"));
+}